@@ -1,10 +1,18 @@
 use vgmstream_rs::{vgmstream::VGMStream, render::render_vgmstream};
 use std::time::Instant;
+use vgmstream_rs::util::log::{vgm_log_set_callback, LogLevel, LogOutput};
 const SAMPLE_BUFFER_SIZE: usize = 32768;
 
 pub fn main() {
+    vgm_log_set_callback(LogLevel::Info, LogOutput::Stderr);
     let start = Instant::now();
-    let mut vgmstream = VGMStream::init("test_data/wem/474329706.wem".to_string());
+    let mut vgmstream = match VGMStream::init("test_data/wem/474329706.wem".to_string()) {
+        Ok(vgmstream) => vgmstream,
+        Err(err) => {
+            eprintln!("failed opening 474329706.wem: {}", err);
+            std::process::exit(1);
+        }
+    };
     println!("{}hz, {} channels, {} samples total, {}s length", vgmstream.sample_rate, vgmstream.ch.len(), vgmstream.num_samples, vgmstream.num_samples / vgmstream.sample_rate);
     // assert_eq!(vgmstream.sample_rate, 48000);
     // assert_eq!(vgmstream.channels, 2);
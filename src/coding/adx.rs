@@ -1,4 +1,6 @@
+use crate::vgm_log;
 use crate::util::{reader::*, util::clamp16};
+use crate::streamfile::read_streamfile;
 use crate::vgmstream::{CodingType, VGMStreamChannel};
 
 pub fn decode_adx(
@@ -30,15 +32,10 @@ pub fn decode_adx(
     /* parse frame header */
     let frame_offset = stream.offset as i32 + bytes_per_frame * frames_in;
 
-    use std::io::{Seek, Read};
     let mut frame = vec![0; bytes_per_frame as usize];
-    stream.streamfile.as_mut().unwrap().reader
-        .seek(std::io::SeekFrom::Start(frame_offset as u64))
-        .unwrap();
-    stream.streamfile.as_mut().unwrap().reader.read_exact(&mut frame).unwrap();
-    // println!("{:X?}", frame);
-
-    // read_streamfile(frame, frame_offset, bytes_per_frame, stream.streamfile); /* ignore EOF errors */
+    if let Some(sf) = stream.streamfile.as_mut() {
+        read_streamfile(sf, frame_offset as usize, &mut frame); /* ignore EOF errors */
+    }
     let mut coef1 = 0;
     let mut coef2 = 0;
     let mut scale = get_s16be(&frame[0x00..]);
@@ -54,7 +51,7 @@ pub fn decode_adx(
              * Just the upper bit triggers it even in encrypted ADX (max is 0x7FFF), but the check only here just in case. */
             if frame[0] == 0x80 && frame[1] == 0x01 {
                 scale = 0; /* fix scaled click, maybe should just exit */
-                println!("ADX: reached EOF scale");
+                vgm_log!("ADX: reached EOF scale");
             }
         }
         CodingType::coding_CRI_ADX_exp => {
@@ -80,9 +77,7 @@ pub fn decode_adx(
     }
 
     /* decode nibbles */
-    let mut count = 0;
     for i in first_sample..(first_sample + samples_to_do) {
-        count = i;
         let mut sample = 0;
         let nibbles = frame[0x02 + i as usize / 2];
 
@@ -115,9 +110,10 @@ pub fn decode_adx(
     stream.adpcm_history1_32 = hist1;
     stream.adpcm_history2_32 = hist2;
 
+    /* advance the key once the frame is fully decoded (other channels' frames use the steps between) */
     if (coding_type == CodingType::coding_CRI_ADX_enc_8
         || coding_type == CodingType::coding_CRI_ADX_enc_9)
-        && (count % 32) == 0
+        && (first_sample + samples_to_do) % samples_per_frame == 0
     {
         for _ in 0..stream.adx_channels {
             adx_next_key(stream);
@@ -125,6 +121,6 @@ pub fn decode_adx(
     }
 }
 
-fn adx_next_key(stream: &mut VGMStreamChannel) {
-    stream.adx_xor = (stream.adx_xor * stream.adx_mult + stream.adx_add) & 0x7fff;
+pub fn adx_next_key(stream: &mut VGMStreamChannel) {
+    stream.adx_xor = stream.adx_xor.wrapping_mul(stream.adx_mult).wrapping_add(stream.adx_add) & 0x7fff;
 }
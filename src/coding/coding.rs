@@ -114,4 +114,19 @@ impl Default for OpusConfig {
             frame_size: 0,
         }
     }
-}
\ No newline at end of file
+}
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Atrac9Config {
+    pub channels: i32,          /* to detect weird multichannel */
+    pub config_data: u32,       /* ATRAC9 config header */
+    pub encoder_delay: i32,     /* initial samples to discard */
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct MpegCustomConfig {
+    pub encryption: u8,         /* AHX: 0x08 = keystring encryption */
+    pub cri_type: u8,           /* AHX: 0x10 (DC) or 0x11 */
+    pub cri_key1: u16,          /* AHX: type 8 key (start/mult/add in ADX) */
+    pub cri_key2: u16,
+    pub cri_key3: u16,
+}
@@ -0,0 +1,210 @@
+use crate::vgm_log;
+use crate::error::Result;
+use crate::streamfile::*;
+use crate::vgmstream::{Streamfile, VGMStream, VGMStreamCodecData};
+
+/* ******************************************** */
+/* XMA PARSING                                  */
+/* ******************************************** */
+
+/* XMA2 frames are 512 samples, split into 128-sample subframes */
+const XMA_SAMPLES_PER_FRAME: i32 = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct XmaChunkInfo {
+    pub channels: i32,
+    pub sample_rate: i32,
+    pub loop_flag: bool,
+    pub num_samples: i32,
+    pub loop_start_sample: i32,
+    pub loop_end_sample: i32,
+}
+
+/* Reads the "XMA2" chunk (XMA2WAVEFORMAT, always BE), used by older X360 files instead of a "fmt " */
+pub fn xma2_parse_xma2_chunk(sf: &mut Streamfile, chunk_offset: usize) -> Result<XmaChunkInfo> {
+    let mut info: XmaChunkInfo = Default::default();
+
+    /* 0x00: version (3 or 4), 0x02: reserved */
+    let version = read_u8(sf, chunk_offset + 0x00)?;
+    let num_streams = read_u8(sf, chunk_offset + 0x01)?;
+    let loop_count = read_u8(sf, chunk_offset + 0x03)?;
+    info.loop_start_sample = read_u32be(sf, chunk_offset + 0x04)? as i32;
+    info.loop_end_sample = read_u32be(sf, chunk_offset + 0x08)? as i32;
+    info.sample_rate = read_u32be(sf, chunk_offset + 0x0c)? as i32;
+    /* 0x10: encode options, 0x14: pseudo bytes per sec, 0x18: block size */
+    info.num_samples = read_u32be(sf, chunk_offset + 0x1c)? as i32;
+    /* 0x20: samples in source, 0x24: block count (v4) */
+
+    /* loop count is rarely not set in looped files (encoder default?) */
+    info.loop_flag = loop_count > 0 || info.loop_end_sample > 0;
+
+    /* per stream: channels, reserved, channel mask */
+    let streams_offset = chunk_offset + if version == 3 { 0x24 } else { 0x28 };
+    for i in 0..num_streams as usize {
+        info.channels += read_u8(sf, streams_offset + i * 0x04)? as i32;
+    }
+
+    return Ok(info);
+}
+
+/* Reads the XMA2WAVEFORMATEX extra data in a "fmt " chunk, if any (samples/loops aren't part of WAVEFORMATEX) */
+pub fn xma2_parse_fmt_chunk_extra(sf: &mut Streamfile, chunk_offset: usize, big_endian: bool) -> Result<Option<XmaChunkInfo>> {
+    let read_u16 = if big_endian { read_u16be } else { read_u16le };
+    let read_u32 = if big_endian { read_u32be } else { read_u32le };
+    let mut info: XmaChunkInfo = Default::default();
+
+    if read_u16(sf, chunk_offset + 0x00)? != 0x166 {
+        return Ok(None);
+    }
+    if read_u16(sf, chunk_offset + 0x10)? < 0x22 { /* expected extra data size */
+        return Ok(None);
+    }
+
+    /* up to extra data is a WAVEFORMATEX */
+    info.channels = read_u16(sf, chunk_offset + 0x02)? as i32;
+    info.sample_rate = read_u32(sf, chunk_offset + 0x04)? as i32;
+    /* 0x12: num streams, 0x14: channel mask */
+    info.num_samples = read_u32(sf, chunk_offset + 0x18)? as i32;
+    /* 0x1c: bytes per block, 0x20: play begin, 0x24: play length */
+    info.loop_start_sample = read_u32(sf, chunk_offset + 0x28)? as i32;
+    info.loop_end_sample = info.loop_start_sample + read_u32(sf, chunk_offset + 0x2c)? as i32;
+    info.loop_flag = read_u8(sf, chunk_offset + 0x30)? > 0 || info.loop_end_sample > 0;
+    /* 0x31: encoder version, 0x32: block count */
+
+    return Ok(Some(info));
+}
+
+/* XMA frames are WMAPro frames with a fixed config (FFmpeg's decode_flags 0x10D6) */
+const XMA_BYTES_PER_PACKET: usize = 0x800;
+const XMA_BITS_FRAME_SIZE: usize = 15;
+const XMA_SAMPLES_PER_SUBFRAME: i32 = 128;
+
+/* XMA packets are read MSB first, returns None past the packet */
+struct XmaBitReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl XmaBitReader<'_> {
+    fn read(&mut self, bits: usize) -> Option<u32> {
+        if self.pos + bits > self.buf.len() * 8 {
+            return None;
+        }
+        let mut value = 0;
+        for i in self.pos..self.pos + bits {
+            value = (value << 1) | ((self.buf[i / 8] >> (7 - i % 8)) & 1) as u32;
+        }
+        self.pos += bits;
+        return Some(value);
+    }
+}
+
+/* Skips the subframe layout ("tile header"), which isn't fixed (see FFmpeg's wmaprodec decode_tilehdr) */
+fn xma_skip_tile_header(br: &mut XmaBitReader, channels: usize) -> Option<()> {
+    let mut num_samples = [0i32; 2];
+    let mut min_channel_len = 0;
+
+    let fixed_channel_layout = br.read(1)? == 1;
+    while min_channel_len < XMA_SAMPLES_PER_FRAME {
+        let last_subframe = min_channel_len == XMA_SAMPLES_PER_FRAME - XMA_SAMPLES_PER_SUBFRAME;
+        let channels_for_cur_subframe = num_samples[..channels].iter().filter(|&&len| len == min_channel_len).count();
+
+        /* check which channels contain the subframe */
+        let mut contains_subframe = [false; 2];
+        for ch in 0..channels {
+            if num_samples[ch] == min_channel_len {
+                contains_subframe[ch] = fixed_channel_layout || channels_for_cur_subframe == 1 || last_subframe || br.read(1)? == 1;
+            }
+        }
+
+        /* 512 >> (0..2), last subframe is implicit */
+        let subframe_len = if last_subframe {
+            XMA_SAMPLES_PER_SUBFRAME
+        } else if br.read(1)? == 1 {
+            XMA_SAMPLES_PER_FRAME >> (1 + br.read(1)?)
+        } else {
+            XMA_SAMPLES_PER_FRAME
+        };
+
+        for ch in 0..channels {
+            if contains_subframe[ch] {
+                num_samples[ch] += subframe_len;
+                if num_samples[ch] > XMA_SAMPLES_PER_FRAME {
+                    return None;
+                }
+            }
+        }
+        min_channel_len = *num_samples[..channels].iter().min()?;
+    }
+
+    return Some(());
+}
+
+/* Reads the start skip in the first frame of a packet (channels per stream are 1 or 2) */
+pub(crate) fn xma_get_start_skip(packet: &[u8], channels: usize) -> Option<i32> {
+    /* packet header: frame count 6b, first frame offset in bits 15b, metadata 3b, packet skip 8b */
+    let mut br = XmaBitReader { buf: packet, pos: 0 };
+    br.read(6)?;
+    let first_frame_b = br.read(15)? as usize;
+    br.pos = 32 + first_frame_b;
+
+    let frame_size = br.read(XMA_BITS_FRAME_SIZE)?;
+    if frame_size == 0x7FFF { /* end marker */
+        return None;
+    }
+
+    xma_skip_tile_header(&mut br, channels)?;
+
+    /* post-processing transform (channel matrix) */
+    if channels > 1 && br.read(1)? == 1 && br.read(1)? == 1 {
+        br.pos += channels * channels * 4;
+    }
+
+    /* dynamic range compression gain */
+    br.read(8)?;
+
+    /* start/end skips (usually start in the first frame, end in the last) */
+    let mut start_skip = 0;
+    if br.read(1)? == 1 && br.read(1)? == 1 {
+        start_skip = br.read(10)? as i32;
+    }
+
+    return Some(start_skip);
+}
+
+/* Applies XMA's encoder delay to header samples, as the decoder outputs them.
+ * Header values usually count samples of the whole stream, so both num_samples and
+ * loops need to move back. The delay is read from the start skip bits in the first frame. */
+pub fn xma_fix_raw_samples(vgmstream: &mut VGMStream, fix_num_samples: bool, fix_loop_samples: bool) {
+    let channels = if vgmstream.channels > 1 { 2 } else { 1 };
+    let mut start_skip = 0;
+
+    if let Some(VGMStreamCodecData::CustomFFmpeg(data)) = vgmstream.codec_data.as_mut() {
+        let mut packet = [0u8; XMA_BYTES_PER_PACKET];
+        let offset = data.start as usize;
+        if let Some(sf) = data.sf.as_mut() {
+            read_streamfile(sf, offset, &mut packet);
+        }
+
+        match xma_get_start_skip(&packet, channels) {
+            Some(skip) => start_skip = skip,
+            None => vgm_log!("XMA: can't read start skip at {:x}", offset),
+        }
+        crate::coding::ffmpeg::ffmpeg_set_skip_samples(data, start_skip);
+    }
+
+    if fix_num_samples {
+        vgmstream.num_samples -= start_skip;
+        if vgmstream.num_samples < 0 {
+            vgmstream.num_samples = 0;
+        }
+    }
+
+    if fix_loop_samples && vgmstream.loop_flag {
+        vgmstream.loop_start_sample -= start_skip;
+        vgmstream.loop_end_sample -= start_skip;
+        if vgmstream.loop_start_sample < 0 {
+            vgmstream.loop_start_sample = 0;
+        }
+    }
+}
@@ -1,13 +1,26 @@
+use crate::{vgm_asserti, vgm_log};
 use rsmpeg::ffi::AVRational;
 
 use crate::{
-    streamfile::Streamfile,
+    error::{Result, VgmstreamError},
+    streamfile::{read_streamfile, Streamfile},
     vgmstream::{VGMStream, STREAMFILE_DEFAULT_BUFFER_SIZE, VGMStreamCodecData},
 };
 
+use super::coding::Atrac9Config;
 use super::ffmpeg_opus::FFmpegCodecData;
 
-pub fn init_ffmpeg_offset(sf: &mut Streamfile, start: u64, size: u64) -> Option<FFmpegCodecData> {
+/* ATRAC9 through FFmpeg's decoder: RIFF with the config word, plus manual encoder delay (not in the fake header) */
+pub fn init_ffmpeg_atrac9(sf: &mut Streamfile, start: u64, size: u64, sample_rate: i32, cfg: &Atrac9Config) -> Result<Box<FFmpegCodecData>> {
+    let mut header: Vec<u8> = Vec::new();
+    ffmpeg_make_riff_atrac9(&mut header, size as usize, cfg.channels, sample_rate, cfg.config_data)?;
+
+    let mut data = init_ffmpeg_header_offset(sf, &header, start, size)?;
+    ffmpeg_set_skip_samples(&mut data, cfg.encoder_delay);
+    return Ok(data);
+}
+
+pub fn init_ffmpeg_offset(sf: &mut Streamfile, start: u64, size: u64) -> Result<Box<FFmpegCodecData>> {
     init_ffmpeg_header_offset(sf, &Vec::new(), start, size)
 }
 
@@ -16,7 +29,7 @@ pub fn init_ffmpeg_header_offset(
     header: &Vec<u8>,
     start: u64,
     size: u64,
-) -> Option<FFmpegCodecData> {
+) -> Result<Box<FFmpegCodecData>> {
     init_ffmpeg_header_offset_subsong(sf, header, start, size, 0)
 }
 
@@ -56,26 +69,21 @@ pub fn init_ffmpeg_header_offset_subsong(
     start: u64,
     size: u64,
     target_subsong: i32,
-) -> Option<FFmpegCodecData> {
-    let mut data: FFmpegCodecData = Default::default();
+) -> Result<Box<FFmpegCodecData>> {
+    /* boxed as FFmpeg's IO callbacks keep a pointer to it */
+    let mut data: Box<FFmpegCodecData> = Box::new(Default::default());
     let mut size = size;
     // int errcode;
 
     /* check values */
-    if !header.is_empty() {
-        return None;
+    let file_size = sf.get_size(std::ptr::null_mut()) as u64;
+    if start >= file_size {
+        return Err(VgmstreamError::Truncated { meta: "FFMPEG", offset: start as usize, size: size as usize });
     }
 
-    if size == 0 || start + size > sf.get_size(std::ptr::null_mut()) as u64 {
-        assert_ne!(
-            size,
-            0,
-            "FFMPEG: wrong start+size found: {} + {:x} > {:x}",
-            start,
-            size,
-            sf.get_size(std::ptr::null_mut())
-        );
-        size = sf.get_size(std::ptr::null_mut()) as u64 - start;
+    if size == 0 || start + size > file_size {
+        vgm_asserti!(size != 0, "FFMPEG: wrong start+size found: {:x} + {:x} > {:x}", start, size, file_size);
+        size = file_size - start;
     }
 
     /* initial FFmpeg setup */
@@ -95,9 +103,6 @@ pub fn init_ffmpeg_header_offset_subsong(
         data.header_size = header.len() as u64;
         // data.header_block = av_memdup(header, header_size);
         data.header_block = header.clone();
-        if data.header_block.is_empty() {
-            return None;
-        }
     }
 
     data.start = start;
@@ -110,7 +115,7 @@ pub fn init_ffmpeg_header_offset_subsong(
     unsafe {
         let errcode = init_ffmpeg_config(&mut data, target_subsong, false);
         if errcode < 0 {
-            return None;
+            return Err(VgmstreamError::DecoderFailure { codec: "FFMPEG", offset: start as usize, reason: format!("can't open stream (error {})", errcode) });
         }
     }
     /* reset non-zero values */
@@ -118,8 +123,7 @@ pub fn init_ffmpeg_header_offset_subsong(
 
     /* setup other values */
     unsafe {
-        let streams: Vec<rsmpeg::ffi::AVStream> = Vec::from_raw_parts(*(*data.formatCtx).streams, (*data.formatCtx).nb_streams as usize, (*data.formatCtx).nb_streams as usize);
-        let stream = streams[data.stream_index as usize];
+        let stream = &**(*data.formatCtx).streams.add(data.stream_index as usize);
         let mut tb: AVRational = std::mem::zeroed();
         tb.num = 1;
         tb.den = (*data.codecCtx).sample_rate;
@@ -164,7 +168,212 @@ pub fn init_ffmpeg_header_offset_subsong(
     //     ffmpeg_set_force_seek(data);
     // }
 
-    Some(data)
+    Ok(data)
+}
+
+/* ******************************************** */
+/* FAKE RIFF HELPERS                            */
+/* ******************************************** */
+/* All helpers copy a RIFF header to buf and returns the number of bytes in buf or error.
+ * FFmpeg will play the data as if it was a normal RIFF with that codec. */
+
+/* Makes a RIFF from an "XMA2" chunk (XMA2WAVEFORMAT), which FFmpeg accepts directly */
+pub fn ffmpeg_make_riff_xma2_from_xma2_chunk(buf: &mut Vec<u8>, xma2_offset: usize, xma2_size: usize, data_size: usize, sf: &mut Streamfile) -> Result<usize> {
+    if xma2_size > 0x100 {
+        return Err(VgmstreamError::BadHeader { meta: "FFMPEG", offset: xma2_offset, field: "XMA2 chunk size" });
+    }
+
+    let header_size = 0x04 + 0x04 + 0x04 + 0x04 + 0x04 + xma2_size + 0x04 + 0x04;
+    buf.clear();
+    buf.resize(header_size, 0);
+
+    buf[0x00..0x04].copy_from_slice(b"RIFF");
+    buf[0x04..0x08].copy_from_slice(&((header_size - 0x08 + data_size) as u32).to_le_bytes()); /* riff size */
+    buf[0x08..0x0c].copy_from_slice(b"WAVE");
+
+    buf[0x0c..0x10].copy_from_slice(b"XMA2");
+    buf[0x10..0x14].copy_from_slice(&(xma2_size as u32).to_le_bytes());
+    if read_streamfile(sf, xma2_offset, &mut buf[0x14..0x14 + xma2_size]) != xma2_size {
+        return Err(VgmstreamError::Truncated { meta: "FFMPEG", offset: xma2_offset, size: xma2_size });
+    }
+
+    buf[0x14 + xma2_size..0x18 + xma2_size].copy_from_slice(b"data");
+    buf[0x18 + xma2_size..0x1c + xma2_size].copy_from_slice(&(data_size as u32).to_le_bytes()); /* data size */
+
+    return Ok(header_size);
+}
+
+/* Makes a RIFF from an XMA "fmt " chunk (XMAWAVEFORMAT or XMA2WAVEFORMATEX), swapping it to LE if needed */
+pub fn ffmpeg_make_riff_xma_from_fmt_chunk(buf: &mut Vec<u8>, fmt_offset: usize, fmt_size: usize, data_size: usize, sf: &mut Streamfile, big_endian: bool) -> Result<usize> {
+    let riff_size = 0x04 + 0x04 + 0x04 + 0x08 + fmt_size + 0x08;
+    buf.clear();
+    buf.resize(riff_size, 0);
+
+    buf[0x00..0x04].copy_from_slice(b"RIFF");
+    buf[0x04..0x08].copy_from_slice(&((riff_size - 0x08 + data_size) as u32).to_le_bytes());
+    buf[0x08..0x0c].copy_from_slice(b"WAVE");
+    buf[0x0c..0x10].copy_from_slice(b"fmt ");
+    buf[0x10..0x14].copy_from_slice(&(fmt_size as u32).to_le_bytes());
+    if read_streamfile(sf, fmt_offset, &mut buf[0x14..0x14 + fmt_size]) != fmt_size {
+        return Err(VgmstreamError::Truncated { meta: "FFMPEG", offset: fmt_offset, size: fmt_size });
+    }
+
+    if big_endian {
+        let codec = u16::from_be_bytes([buf[0x14], buf[0x15]]);
+        ffmpeg_fmt_chunk_swap_endian(&mut buf[0x14..0x14 + fmt_size], codec)?;
+    }
+
+    buf[0x14 + fmt_size..0x18 + fmt_size].copy_from_slice(b"data");
+    buf[0x18 + fmt_size..0x1c + fmt_size].copy_from_slice(&(data_size as u32).to_le_bytes());
+
+    return Ok(riff_size);
+}
+
+/* Makes an xWMA RIFF (WMAv2/WMAPro). The "dpds" table holds cumulative decoded bytes per packet,
+ * used by FFmpeg to get duration and seek, and may be empty if unknown. */
+pub fn ffmpeg_make_riff_xwma(buf: &mut Vec<u8>, codec: u16, data_size: usize, channels: i32, sample_rate: i32, avg_bps: i32, block_align: i32, dpds: &[u32]) -> usize {
+    let dpds_size = if dpds.is_empty() { 0 } else { 0x08 + dpds.len() * 0x04 };
+    let riff_size = 0x04 + 0x04 + 0x04 + 0x1a + dpds_size + 0x04 + 0x04;
+    buf.clear();
+    buf.resize(riff_size, 0);
+
+    buf[0x00..0x04].copy_from_slice(b"RIFF");
+    buf[0x04..0x08].copy_from_slice(&((riff_size - 0x08 + data_size) as u32).to_le_bytes()); /* riff size */
+    buf[0x08..0x0c].copy_from_slice(b"XWMA");
+
+    buf[0x0c..0x10].copy_from_slice(b"fmt ");
+    buf[0x10..0x14].copy_from_slice(&0x12u32.to_le_bytes()); /* fmt size */
+    buf[0x14..0x16].copy_from_slice(&codec.to_le_bytes());
+    buf[0x16..0x18].copy_from_slice(&(channels as u16).to_le_bytes());
+    buf[0x18..0x1c].copy_from_slice(&(sample_rate as u32).to_le_bytes());
+    buf[0x1c..0x20].copy_from_slice(&(avg_bps as u32).to_le_bytes()); /* average bytes per second, somehow vital for XWMA */
+    buf[0x20..0x22].copy_from_slice(&(block_align as u16).to_le_bytes()); /* block align */
+    buf[0x22..0x24].copy_from_slice(&16u16.to_le_bytes()); /* bits per sample */
+    buf[0x24..0x26].copy_from_slice(&0u16.to_le_bytes()); /* extra size */
+
+    let mut offset = 0x26;
+    if !dpds.is_empty() {
+        buf[offset..offset + 0x04].copy_from_slice(b"dpds");
+        buf[offset + 0x04..offset + 0x08].copy_from_slice(&((dpds.len() * 0x04) as u32).to_le_bytes());
+        offset += 0x08;
+        for entry in dpds {
+            buf[offset..offset + 0x04].copy_from_slice(&entry.to_le_bytes());
+            offset += 0x04;
+        }
+    }
+
+    buf[offset..offset + 0x04].copy_from_slice(b"data");
+    buf[offset + 0x04..offset + 0x08].copy_from_slice(&(data_size as u32).to_le_bytes()); /* data size */
+
+    return riff_size;
+}
+
+/* Makes an ATRAC9 RIFF (WAVEFORMATEXTENSIBLE with AT9's GUID), like Sony's .at9.
+ * FFmpeg's decoder only needs the config word (passed as extradata) and a matching block align. */
+pub fn ffmpeg_make_riff_atrac9(buf: &mut Vec<u8>, data_size: usize, channels: i32, sample_rate: i32, config_data: u32) -> Result<usize> {
+    const ATRAC9_GUID: [u8; 16] = [0xD2, 0x42, 0xE1, 0x47, 0xBA, 0x36, 0x8D, 0x4D, 0x88, 0xFC, 0x61, 0x65, 0x4F, 0x8C, 0x83, 0x6C];
+
+    /* config: sync 8b, samplerate index 4b, channels index 3b, validation 1b, frame size 11b, superframe index 2b, padding 3b */
+    if (config_data >> 24) != 0xFE {
+        return Err(VgmstreamError::BadHeader { meta: "FFMPEG", offset: 0, field: "ATRAC9 config" });
+    }
+    let frame_size = ((config_data >> 5) & 0x7FF) as i32 + 1;
+    let superframe_index = ((config_data >> 3) & 0x3) as i32;
+    let block_align = frame_size * (1 << superframe_index); /* superframe, what FFmpeg expects as a packet */
+
+    let riff_size = 0x04 + 0x04 + 0x04 + 0x08 + 0x34 + 0x08;
+    buf.clear();
+    buf.resize(riff_size, 0);
+
+    buf[0x00..0x04].copy_from_slice(b"RIFF");
+    buf[0x04..0x08].copy_from_slice(&((riff_size - 0x08 + data_size) as u32).to_le_bytes()); /* riff size */
+    buf[0x08..0x0c].copy_from_slice(b"WAVE");
+
+    buf[0x0c..0x10].copy_from_slice(b"fmt ");
+    buf[0x10..0x14].copy_from_slice(&0x34u32.to_le_bytes()); /* fmt size */
+    buf[0x14..0x16].copy_from_slice(&0xFFFEu16.to_le_bytes()); /* WAVE_FORMAT_EXTENSIBLE */
+    buf[0x16..0x18].copy_from_slice(&(channels as u16).to_le_bytes());
+    buf[0x18..0x1c].copy_from_slice(&(sample_rate as u32).to_le_bytes());
+    buf[0x1c..0x20].copy_from_slice(&0u32.to_le_bytes()); /* average bytes per second (not needed) */
+    buf[0x20..0x22].copy_from_slice(&(block_align as u16).to_le_bytes());
+    buf[0x22..0x24].copy_from_slice(&0u16.to_le_bytes()); /* bits per sample */
+    buf[0x24..0x26].copy_from_slice(&0x22u16.to_le_bytes()); /* extra size */
+    buf[0x26..0x28].copy_from_slice(&0u16.to_le_bytes()); /* samples per block (unused) */
+    buf[0x28..0x2c].copy_from_slice(&0u32.to_le_bytes()); /* channel mask (unknown) */
+    buf[0x2c..0x3c].copy_from_slice(&ATRAC9_GUID);
+    buf[0x3c..0x40].copy_from_slice(&1u32.to_le_bytes()); /* version */
+    buf[0x40..0x44].copy_from_slice(&config_data.to_be_bytes());
+    buf[0x44..0x48].copy_from_slice(&0u32.to_le_bytes()); /* reserved */
+
+    buf[0x48..0x4c].copy_from_slice(b"data");
+    buf[0x4c..0x50].copy_from_slice(&(data_size as u32).to_le_bytes()); /* data size */
+
+    return Ok(riff_size);
+}
+
+/* Swaps endianness of XMA fmt chunks in place (FFmpeg only reads LE) */
+fn ffmpeg_fmt_chunk_swap_endian(chunk: &mut [u8], codec: u16) -> Result<()> {
+    fn swap16(chunk: &mut [u8], offset: usize) {
+        chunk.swap(offset, offset + 1);
+    }
+    fn swap32(chunk: &mut [u8], offset: usize) {
+        chunk[offset..offset + 4].reverse();
+    }
+
+    match codec {
+        0x0165 => { /* XMA1 */
+            if chunk.len() < 0x0c {
+                return Err(VgmstreamError::BadHeader { meta: "FFMPEG", offset: 0, field: "XMA1 fmt size" });
+            }
+            swap16(chunk, 0x00); /* FormatTag */
+            swap16(chunk, 0x02); /* BitsPerSample */
+            swap16(chunk, 0x04); /* EncodeOptions */
+            swap16(chunk, 0x06); /* LargestSkip */
+            swap16(chunk, 0x08); /* NumStreams */
+            /* 0x0a: LoopCount, 0x0b: Version */
+
+            let num_streams = u16::from_le_bytes([chunk[0x08], chunk[0x09]]) as usize;
+            for i in 0..num_streams {
+                let offset = 0x0c + 0x14 * i;
+                if offset + 0x14 > chunk.len() {
+                    break;
+                }
+                swap32(chunk, offset + 0x00); /* PsuedoBytesPerSec */
+                swap32(chunk, offset + 0x04); /* SampleRate */
+                swap32(chunk, offset + 0x08); /* LoopStart */
+                swap32(chunk, offset + 0x0c); /* LoopEnd */
+                /* 0x10: SubframeData, 0x11: Channels */
+                swap16(chunk, offset + 0x12); /* ChannelMask */
+            }
+        }
+        0x0166 => { /* XMA2 */
+            if chunk.len() < 0x34 {
+                return Err(VgmstreamError::BadHeader { meta: "FFMPEG", offset: 0, field: "XMA2 fmt size" });
+            }
+            swap16(chunk, 0x00); /* wFormatTag */
+            swap16(chunk, 0x02); /* nChannels */
+            swap32(chunk, 0x04); /* nSamplesPerSec */
+            swap32(chunk, 0x08); /* nAvgBytesPerSec */
+            swap16(chunk, 0x0c); /* nBlockAlign */
+            swap16(chunk, 0x0e); /* wBitsPerSample */
+            swap16(chunk, 0x10); /* cbSize */
+            swap16(chunk, 0x12); /* NumStreams */
+            swap32(chunk, 0x14); /* ChannelMask */
+            swap32(chunk, 0x18); /* SamplesEncoded */
+            swap32(chunk, 0x1c); /* BytesPerBlock */
+            swap32(chunk, 0x20); /* PlayBegin */
+            swap32(chunk, 0x24); /* PlayLength */
+            swap32(chunk, 0x28); /* LoopBegin */
+            swap32(chunk, 0x2c); /* LoopLength */
+            /* 0x30: LoopCount, 0x31: EncoderVersion */
+            swap16(chunk, 0x32); /* BlockCount */
+        }
+        _ => {
+            return Err(VgmstreamError::UnsupportedCodec { meta: "FFMPEG", codec: format!("fmt swap for codec {:x}", codec) });
+        }
+    }
+
+    return Ok(());
 }
 
 const FFMPEG_DEFAULT_IO_BUFFER_SIZE: usize = STREAMFILE_DEFAULT_BUFFER_SIZE;
@@ -250,11 +459,10 @@ pub unsafe fn init_ffmpeg_config(
         }
 
         for i in 0..(*data.formatCtx).nb_streams {
-            let mut streams: Vec<rsmpeg::ffi::AVStream> = Vec::from_raw_parts(*(*data.formatCtx).streams, (*data.formatCtx).nb_streams as usize, (*data.formatCtx).nb_streams as usize);
-            let mut stream = streams[i as usize];
+            let stream = *(*data.formatCtx).streams.add(i as usize);
 
-            if !stream.codecpar.is_null()
-                && (*stream.codecpar).codec_type == rsmpeg::ffi::AVMediaType_AVMEDIA_TYPE_AUDIO
+            if !(*stream).codecpar.is_null()
+                && (*(*stream).codecpar).codec_type == rsmpeg::ffi::AVMediaType_AVMEDIA_TYPE_AUDIO
             {
                 stream_count += 1;
 
@@ -265,11 +473,8 @@ pub unsafe fn init_ffmpeg_config(
             }
 
             if i as i32 != stream_index {
-                stream.discard = rsmpeg::ffi::AVDiscard_AVDISCARD_ALL; /* disable demuxing for other streams */
+                (*stream).discard = rsmpeg::ffi::AVDiscard_AVDISCARD_ALL; /* disable demuxing for other streams */
             }
-            streams[i as usize] = stream;
-
-            (*data.formatCtx).streams = &mut streams.as_mut_ptr();
         }
         if stream_count < target_subsong {
             if errcode < 0 {
@@ -297,9 +502,8 @@ pub unsafe fn init_ffmpeg_config(
         return -1;
     }
 
-    let streams: Vec<rsmpeg::ffi::AVStream> = Vec::from_raw_parts(*(*data.formatCtx).streams, (*data.formatCtx).nb_streams as usize, (*data.formatCtx).nb_streams as usize);
-    let stream = streams[data.stream_index as usize];
-    errcode = rsmpeg::ffi::avcodec_parameters_to_context(data.codecCtx, stream.codecpar.cast_const());
+    let stream = *(*data.formatCtx).streams.add(data.stream_index as usize);
+    errcode = rsmpeg::ffi::avcodec_parameters_to_context(data.codecCtx, (*stream).codecpar.cast_const());
     if errcode < 0 {
         return errcode;
     }
@@ -397,23 +601,15 @@ pub unsafe extern "C" fn ffmpeg_read(
     }
 
     /* main read */
+    let buf = std::slice::from_raw_parts_mut(buf.add(max_to_copy as usize), read_size as usize);
+    let bytes = match data.sf.as_mut() {
+        Some(sf) => read_streamfile(sf, data.offset as usize, buf),
+        None => 0,
+    };
+    data.offset += bytes as u64;
+    data.logical_offset += bytes as u64;
 
-    let mut sf2 = data.sf.clone();
-    let opus_data = sf2.as_mut().unwrap().data;
-    let read_func = data.sf.as_ref().unwrap().read.unwrap();
-    let data_read = read_func(data.sf.as_mut().unwrap(), data.offset as usize, read_size as usize, opus_data);
-
-    sf2.as_mut().unwrap().data = opus_data;
-    data.sf = sf2;
-
-    buf.copy_from(
-        data_read.as_ptr(),
-        read_size as usize,
-    );
-    data.offset += read_size as u64;
-    data.logical_offset += read_size as u64;
-
-    read_size
+    (bytes as u64 + max_to_copy) as i32
 }
 
 /* AVIO callback: seek stream, handling custom data */
@@ -491,6 +687,31 @@ pub fn ffmpeg_set_force_seek(data: &mut FFmpegCodecData) {
     //stream = data.formatCtx->streams[data.stream_index];
 }
 
+/* Samples as reported by FFmpeg (from the container's duration, may be 0 or inaccurate) */
+pub fn ffmpeg_get_samples(data: &FFmpegCodecData) -> i32 {
+    return data.total_samples as i32;
+}
+
+/* Overwrites FFmpeg's skip samples (encoder delay) with our own discard, as formats
+ * made from fake headers (XMA, AT9, etc) don't report it or report it wrongly */
+pub fn ffmpeg_set_skip_samples(data: &mut FFmpegCodecData, skip_samples: i32) {
+    if data.formatCtx.is_null() || skip_samples < 0 {
+        return;
+    }
+
+    unsafe {
+        let stream = *(*data.formatCtx).streams.add(data.stream_index as usize);
+        (*stream).start_time = 0; /* other fields affected? */
+    }
+
+    /* set skip samples with our internal discard */
+    data.skip_samples_set = true;
+    data.samples_discard = skip_samples;
+
+    /* expose (info only) */
+    data.skip_samples = skip_samples as i64;
+}
+
 pub fn reset_ffmpeg(data: &mut FFmpegCodecData) {
     seek_ffmpeg(data, 0);
 }
@@ -512,7 +733,7 @@ pub fn seek_ffmpeg(data: &mut FFmpegCodecData, num_sample: i32) {
 
             errcode = init_ffmpeg_config(data, 0, true);
             if errcode < 0 {
-                println!("FFMPEG: error during force_seek");
+                vgm_log!("FFMPEG: error during force_seek");
                 data.bad_init = true; /* internals were probably free'd */
                 return;
             }
@@ -559,11 +780,11 @@ pub unsafe fn free_ffmpeg_config(data: &mut FFmpegCodecData) {
     }
     if !data.codecCtx.is_null() {
         rsmpeg::ffi::avcodec_close(data.codecCtx);
-        rsmpeg::ffi::avcodec_free_context(data.codecCtx as *mut *mut rsmpeg::ffi::AVCodecContext);
+        rsmpeg::ffi::avcodec_free_context(&mut data.codecCtx);
         data.codecCtx = std::ptr::null_mut();
     }
     if !data.formatCtx.is_null() {
-        rsmpeg::ffi::avformat_close_input(data.formatCtx as *mut *mut rsmpeg::ffi::AVFormatContext);
+        rsmpeg::ffi::avformat_close_input(&mut data.formatCtx);
         //avformat_free_context(data.formatCtx); /* done in close_input */
         data.formatCtx = std::ptr::null_mut();
     }
@@ -573,19 +794,24 @@ pub unsafe fn free_ffmpeg_config(data: &mut FFmpegCodecData) {
         data.buffer = (*data.ioCtx).buffer;
         // data.buffer = AVMem::new((*data.ioCtx).buffer_size as usize);
         // data.buffer.copy_from((*data.ioCtx).buffer, (*data.ioCtx).buffer_size as usize);
-        rsmpeg::ffi::avio_context_free(data.ioCtx as *mut *mut rsmpeg::ffi::AVIOContext);
+        rsmpeg::ffi::avio_context_free(&mut data.ioCtx);
         //av_free(data.ioCtx); /* done in context_free (same thing) */
         data.ioCtx = std::ptr::null_mut();
     }
-    if data.buffer.is_null() {
-        // rsmpeg::ffi::av_free(data.buffer.as_mut_ptr() as *mut _);
-        // data.buffer = AVMem::new(0);
+    if !data.buffer.is_null() {
+        rsmpeg::ffi::av_free(data.buffer as *mut _);
         data.buffer = std::ptr::null_mut();
     }
 
     //todo avformat_find_stream_info may cause some Win Handle leaks? related to certain option
 }
 
+impl Drop for FFmpegCodecData {
+    fn drop(&mut self) {
+        unsafe { free_ffmpeg_config(self) };
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AVSampleFormat {
     AV_SAMPLE_FMT_NONE = -1,
@@ -821,45 +1047,47 @@ pub fn decode_ffmpeg(
     mut samples_to_do: i32,
     channels: i32,
 ) {
-    let mut data = vgmstream.codec_data.as_mut().unwrap();
-    
-    match data {
-        VGMStreamCodecData::CustomFFmpeg(data) => {
-            while samples_to_do > 0 {
-                if data.samples_consumed < data.samples_filled {
-                    /* consume samples */
-                    let mut samples_to_get = data.samples_filled - data.samples_consumed;
-                    if data.samples_discard != 0 {
-                        /* discard samples for looping */
-                        if samples_to_get > data.samples_discard {
-                            samples_to_get = data.samples_discard;
-                        }
-                        data.samples_discard -= samples_to_get;
-                    } else {
-                        /* get max samples and copy */
-                        if samples_to_get > samples_to_do {
-                            samples_to_get = samples_to_do;
-                        }
-        
-                        copy_samples(data, outbuf, samples_to_get);
-        
-                        samples_to_do -= samples_to_get;
-                        // outbuf += samples_to_get * channels;
-                    }
-        
-                    /* mark consumed samples */
-                    data.samples_consumed += samples_to_get;
-                } else {
-                    let ok = unsafe { decode_ffmpeg_frame(data) };
-                    if !ok {
-                        println!("FFMPEG: decode fail, missing {} samples", samples_to_do);
-                        samples_silence_s16(outbuf, channels, samples_to_do);
-                        return;
-                    }
+    let data = match vgmstream.codec_data.as_mut() {
+        Some(VGMStreamCodecData::CustomFFmpeg(data)) => data,
+        _ => {
+            samples_silence_s16(outbuf, channels, samples_to_do);
+            return;
+        }
+    };
+    let mut outpos = 0;
+
+    while samples_to_do > 0 {
+        if data.samples_consumed < data.samples_filled {
+            /* consume samples */
+            let mut samples_to_get = data.samples_filled - data.samples_consumed;
+            if data.samples_discard != 0 {
+                /* discard samples for looping */
+                if samples_to_get > data.samples_discard {
+                    samples_to_get = data.samples_discard;
                 }
+                data.samples_discard -= samples_to_get;
+            } else {
+                /* get max samples and copy */
+                if samples_to_get > samples_to_do {
+                    samples_to_get = samples_to_do;
+                }
+
+                copy_samples(data, &mut outbuf[outpos..], samples_to_get);
+
+                samples_to_do -= samples_to_get;
+                outpos += (samples_to_get * channels) as usize;
+            }
+
+            /* mark consumed samples */
+            data.samples_consumed += samples_to_get;
+        } else {
+            let ok = unsafe { decode_ffmpeg_frame(data) };
+            if !ok {
+                vgm_log!("FFMPEG: decode fail, missing {} samples", samples_to_do);
+                samples_silence_s16(&mut outbuf[outpos..], channels, samples_to_do);
+                return;
             }
         }
-        _ => {}
     }
 }
 
@@ -873,7 +1101,7 @@ pub unsafe fn decode_ffmpeg_frame(data: &mut FFmpegCodecData) -> bool {
 
     /* ignore once file is done (but not on EOF as FFmpeg can output samples until end_of_audio) */
     if /*data.end_of_stream ||*/ data.end_of_audio {
-        println!("FFMPEG: decode after end of audio");
+        vgm_log!("FFMPEG: decode after end of audio");
         return false;
     }
 
@@ -891,12 +1119,12 @@ pub unsafe fn decode_ffmpeg_frame(data: &mut FFmpegCodecData) -> bool {
                     data.end_of_stream = true; /* no more data to read (but may "drain" samples) */
                 }
                 else {
-                    println!("FFMPEG: av_read_frame errcode={}", errcode);
+                    vgm_log!("FFMPEG: av_read_frame errcode={}", errcode);
                     frame_error = true; //goto fail;
                 }
 
                 if !(*data.formatCtx).pb.is_null() && (*(*data.formatCtx).pb).error != 0{
-                    println!("FFMPEG: pb error={}", (*(*data.formatCtx).pb).error);
+                    vgm_log!("FFMPEG: pb error={}", (*(*data.formatCtx).pb).error);
                     frame_error = true; //goto fail;
                 }
             }
@@ -911,7 +1139,7 @@ pub unsafe fn decode_ffmpeg_frame(data: &mut FFmpegCodecData) -> bool {
         errcode = rsmpeg::ffi::avcodec_send_packet(data.codecCtx, if data.end_of_stream {std::ptr::null_mut()} else { data.packet });
         if errcode < 0 {
             if errcode != rsmpeg::ffi::AVERROR(rsmpeg::ffi::EAGAIN) {
-                println!("FFMPEG: avcodec_send_packet errcode={}", errcode);
+                vgm_log!("FFMPEG: avcodec_send_packet errcode={}", errcode);
                 frame_error = true; //goto fail;
             }
         }
@@ -931,7 +1159,7 @@ pub unsafe fn decode_ffmpeg_frame(data: &mut FFmpegCodecData) -> bool {
                 data.read_packet = true; /* 0 samples, request more encoded data */
             }
             else {
-                println!("FFMPEG: avcodec_receive_frame errcode={}", errcode);
+                vgm_log!("FFMPEG: avcodec_receive_frame errcode={}", errcode);
                 frame_error = true;//goto fail;
             }
         }
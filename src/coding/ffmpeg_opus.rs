@@ -1,4 +1,9 @@
-use crate::{streamfile::Streamfile, vgmstream::VGMStreamCodecData, coding};
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::vgm_log;
+use crate::streamfile::{read_streamfile, read_u16be, read_u16le, read_u32be, read_u8, Streamfile};
+use crate::vgmstream::VGMStreamCodecData;
+use crate::error::{Result, VgmstreamError};
 use rsmpeg::{
     avutil::AVMem,
     ffi::{AVCodec, AVCodecContext, AVFormatContext, AVFrame, AVIOContext, AVPacket},
@@ -6,7 +11,7 @@ use rsmpeg::{
 
 use super::coding::OpusConfig;
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct FFmpegCodecData {
     /*** IO internals ***/
     pub sf: Option<Streamfile>,
@@ -163,7 +168,7 @@ pub fn init_ffmpeg_wwise_opus(
     data_offset: usize,
     data_size: usize,
     cfg: &mut OpusConfig,
-) -> Option<VGMStreamCodecData> {
+) -> Result<VGMStreamCodecData> {
     return init_ffmpeg_custom_opus_config(sf, data_offset, data_size, cfg, OpusType::OPUS_WWISE);
 }
 
@@ -173,25 +178,13 @@ pub fn init_ffmpeg_custom_opus_config(
     data_size: usize,
     cfg: &mut OpusConfig,
     otype: OpusType,
-) -> Option<VGMStreamCodecData> {
+) -> Result<VGMStreamCodecData> {
     use crate::coding::ffmpeg::init_ffmpeg_offset;
 
-    let mut ffmpeg_data: Option<FFmpegCodecData> = None;
-    let mut temp_sf: Option<Streamfile> = None;
-
-    temp_sf = setup_opus_streamfile(sf, cfg, start_offset, data_size, otype);
-    if temp_sf.is_none() {
-        return None;
-    }
+    let mut temp_sf = setup_opus_streamfile(sf, cfg, start_offset, data_size, otype)?;
 
-    let mut temp_sf = temp_sf.as_mut().unwrap();
     let tsize = temp_sf.get_size(std::ptr::null_mut()) as u64;
-    ffmpeg_data = init_ffmpeg_offset(&mut temp_sf, 0x00, tsize);
-    if ffmpeg_data.is_none() {
-        return None;
-    }
-
-    let mut ffmpeg_data = ffmpeg_data.unwrap();
+    let mut ffmpeg_data = init_ffmpeg_offset(&mut temp_sf, 0x00, tsize)?;
 
     /* FFmpeg + libopus: skips samples, notifies skip in codecCtx->delay/initial_padding (not in stream->skip_samples)
      * FFmpeg + opus: skip samples but loses them on reset/seek to 0, also notifies skip in codecCtx->delay/initial_padding */
@@ -208,101 +201,116 @@ pub fn init_ffmpeg_custom_opus_config(
     }
 
     // temp_sf.close();
-    return Some(VGMStreamCodecData::CustomFFmpeg(ffmpeg_data));
+    return Ok(VGMStreamCodecData::CustomFFmpeg(ffmpeg_data));
+}
+
+/* Presents custom Opus packets as Ogg Opus, so FFmpeg can read them like a regular file */
+struct OpusReader {
+    sf: Streamfile,
+    data: OpusIOData,
+    pos: u64,
+}
+
+impl Read for OpusReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let bytes = opus_io_read(&mut self.sf, self.pos as usize, buf, &mut self.data);
+        self.pos += bytes as u64;
+        Ok(bytes)
+    }
+}
+
+impl Seek for OpusReader {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::End(offset) => self.data.logical_size as i64 + offset,
+            SeekFrom::Current(offset) => self.pos as i64 + offset,
+        };
+        if new_pos < 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "seek before start"));
+        }
+        self.pos = new_pos as u64;
+        Ok(self.pos)
+    }
 }
 
-// TODO: this needs to be actually implemented
 pub fn setup_opus_streamfile(
     sf: &mut Streamfile,
     cfg: &mut OpusConfig,
     stream_offset: usize,
     stream_size: usize,
     otype: OpusType,
-) -> Option<Streamfile> {
-    let mut new_sf: Streamfile = sf.clone();
-    let mut io_data: *mut OpusIOData = std::ptr::null_mut();
-
-    io_data = Box::into_raw(Box::new(OpusIOData::default()));
+) -> Result<Streamfile> {
+    let mut io_data = OpusIOData::default();
 
     if cfg.sample_rate == 0 {
         cfg.sample_rate = 48000; /* default / only value for opus */
     }
-    unsafe {
-        (*io_data).otype = otype;
-        (*io_data).stream_offset = stream_offset;
-        (*io_data).stream_size = stream_size;
-        (*io_data).physical_offset = stream_offset;
-        (*io_data).table_offset = cfg.table_offset;
-        (*io_data).table_count = cfg.table_count;
-        (*io_data).frame_size = cfg.frame_size;
-
-        (*io_data).head_buffer = make_oggs_first(cfg);
-        if (*io_data).head_buffer.len() == 0 {
-            return None;
-        }
+
+    io_data.otype = otype;
+    io_data.stream_offset = stream_offset;
+    io_data.stream_size = stream_size;
+    io_data.physical_offset = stream_offset;
+    io_data.table_offset = cfg.table_offset;
+    io_data.table_count = cfg.table_count;
+    io_data.frame_size = cfg.frame_size;
+
+    io_data.head_buffer = make_oggs_first(cfg);
+    if io_data.head_buffer.is_empty() {
+        return Err(VgmstreamError::DecoderFailure { codec: "OPUS", offset: stream_offset, reason: "can't make Ogg header".to_string() });
     }
 
     /* setup subfile */
-    // new_sf = open_wrap_streamfile(sf);
-    // new_sf = open_io_streamfile_ex_f(new_sf, &io_data, sizeof(opus_io_data), opus_io_read, opus_io_size, opus_io_init, opus_io_close);
-    new_sf.data = io_data as *mut std::ffi::c_void;
-    new_sf.read = Some(opus_io_read);
-    new_sf.get_size = Some(opus_io_size);
-    new_sf.close = Some(opus_io_close);
-
-    unsafe { opus_io_init(&mut new_sf, io_data) };
+    let mut inner_sf = sf.clone();
+    opus_io_init(&mut inner_sf, &mut io_data);
+    if io_data.logical_size == 0 {
+        return Err(VgmstreamError::DecoderFailure { codec: "OPUS", offset: stream_offset, reason: "can't get Ogg size".to_string() });
+    }
 
-    Some(new_sf)
+    let reader = OpusReader { sf: inner_sf, data: io_data, pos: 0 };
+    let mut new_sf = Streamfile::from_reader(sf.name.clone(), reader)?;
+    new_sf.open = sf.open.clone();
+    return Ok(new_sf);
 }
 
 /* Convers custom Opus packets to Ogg Opus, so the resulting data is larger than physical data. */
-pub fn opus_io_read(
-    sf: &mut Streamfile,
-    offset: usize,
-    length: usize,
-    in_data: *mut std::ffi::c_void,
-) -> Vec<u8> {
+fn opus_io_read(sf: &mut Streamfile, offset: usize, dest: &mut [u8], data: &mut OpusIOData) -> usize {
     let mut total_read = 0;
-    let mut dest = Vec::new();
-    // in_data *mut c_void -> *mut OpusIOData -> &mut OpusIOData
-    let mut data = unsafe { &mut *(in_data as *mut OpusIOData) };
     let mut offset = offset;
-    let mut length = length;
+    let mut length = dest.len();
+    let head_size = data.head_buffer.len();
+
     /* ignore bad reads */
     if offset > data.logical_size {
-        return Vec::new();
+        return 0;
     }
 
     /* previous offset: re-start as we can't map logical<>physical offsets */
-    if offset < data.logical_offset as usize || data.logical_offset < 0 {
+    if data.logical_offset < 0 || offset < data.logical_offset as usize {
         data.physical_offset = data.stream_offset;
         data.logical_offset = 0x00;
         data.page_size = 0;
         data.samples_done = 0;
         data.sequence = 2; /* appended header+comment is 0/1 */
 
-        if offset >= data.head_buffer.len() {
-            data.logical_offset = data.head_buffer.len() as isize;
+        if offset >= head_size {
+            data.logical_offset = head_size as isize;
         }
     }
 
     /* insert fake header */
-    if offset < data.head_buffer.len() {
-        // size_t bytes_consumed, to_read;
-
-        let mut bytes_consumed = offset - data.logical_offset as usize;
-        let mut to_read = data.head_buffer.len() - bytes_consumed;
+    if offset < head_size {
+        let bytes_consumed = offset;
+        let mut to_read = head_size - bytes_consumed;
         if to_read > length {
             to_read = length;
         }
-        // memcpy(dest, data.head_buffer + bytes_consumed, to_read);
-        dest = data.head_buffer[bytes_consumed..bytes_consumed + to_read].to_vec();
+        dest[..to_read].copy_from_slice(&data.head_buffer[bytes_consumed..bytes_consumed + to_read]);
 
         total_read += to_read;
-        // dest += to_read;
         offset += to_read;
         length -= to_read;
-        data.logical_offset += to_read as isize;
+        data.logical_offset = offset as isize; /* reaches head_size once the whole header is read */
     }
 
     /* read blocks, one at a time */
@@ -312,69 +320,32 @@ pub fn opus_io_read(
             break;
         }
 
-        use crate::streamfile::{read_u16be, read_u16le, read_u32be, read_u8};
-
         /* process new block */
         if data.page_size == 0 {
-            // size_t data_size, skip_size, oggs_size, packet_samples = 0;
-            let mut data_size = 0;
-            let mut skip_size = 0;
-            let mut oggs_size = 0;
+            let data_size;
+            let skip_size;
             let mut packet_samples = 0;
 
-            match data.otype {
-                OpusType::OPUS_SWITCH => {
-                    /* format seem to come from opus_test and not Nintendo-specific */
-                    data_size = read_u32be(sf, data.physical_offset);
-                    skip_size = 0x08; /* size + Opus state(?) */
-                }
-                OpusType::OPUS_UE4_v1 | OpusType::OPUS_FSB => {
-                    data_size = read_u16le(sf, data.physical_offset) as u32;
-                    skip_size = 0x02;
-                }
-                OpusType::OPUS_UE4_v2 => {
-                    data_size = read_u16le(sf, data.physical_offset + 0x00) as u32;
-                    packet_samples = read_u16le(sf, data.physical_offset + 0x02) as usize;
-                    skip_size = 0x02 + 0x02;
-                }
-                OpusType::OPUS_EA => {
-                    data_size = read_u16be(sf, data.physical_offset) as u32;
-                    skip_size = 0x02;
-                }
-                OpusType::OPUS_EA_M => {
-                    let mut flag = read_u8(sf, data.physical_offset + 0x00);
-                    if flag == 0x48 {
-                        /* should start on 0x44 though */
-                        data.physical_offset +=
-                            read_u16be(sf, data.physical_offset + 0x02) as usize;
-                        flag = read_u8(sf, data.physical_offset + 0x00);
+            match opus_get_block_sizes(sf, data, data.physical_offset, data.sequence as i32 - 2) {
+                Some((frame_size, header_size, samples)) => {
+                    data_size = frame_size;
+                    skip_size = header_size;
+                    if let Some(samples) = samples {
+                        packet_samples = samples;
                     }
-                    data_size = read_u16be(sf, data.physical_offset + 0x02) as u32;
-                    skip_size = if flag == 0x45 { data_size } else { 0x08 };
-                    data_size -= skip_size;
-                }
-                OpusType::OPUS_X | OpusType::OPUS_WWISE => {
-                    data_size = get_table_frame_size(&*data, data.sequence as i32 - 2) as u32;
-                    skip_size = 0;
-                }
-                OpusType::OPUS_FIXED => {
-                    data_size = data.frame_size as u32;
-                    skip_size = 0;
-                }
-                _ => {
-                    return Vec::new();
                 }
+                None => break,
             }
 
-            oggs_size = 0x1b + data_size / 0xFF + 1; /* OggS page: base size + lacing values */
+            let oggs_size = 0x1b + data_size / 0xFF + 1; /* OggS page: base size + lacing values */
 
-            data.block_size = (data_size + skip_size) as usize;
-            data.page_size = (oggs_size + data_size) as usize;
+            data.block_size = data_size + skip_size;
+            data.page_size = oggs_size + data_size;
 
             if data.page_size > data.page_buffer.len() {
                 /* happens on bad reads/EOF too */
-                println!(
-                    "OPUS: buffer can't hold OggS at {:x}, size={:x}\n",
+                vgm_log!(
+                    "OPUS: buffer can't hold OggS at {:x}, size={:x}",
                     data.physical_offset, data.page_size
                 );
                 data.page_size = 0;
@@ -382,19 +353,9 @@ pub fn opus_io_read(
             }
 
             /* create fake OggS page (full page for checksums) */
-            /* store page data */
-            data.page_buffer[oggs_size as usize..(oggs_size + data_size) as usize].copy_from_slice(
-                &sf.read(
-                    data.physical_offset + skip_size as usize,
-                    data_size as usize,
-                    sf.data,
-                ),
-            );
+            read_streamfile(sf, data.physical_offset + skip_size, &mut data.page_buffer[oggs_size..oggs_size + data_size]); /* store page data */
             if packet_samples == 0 {
-                packet_samples = opus_get_packet_samples(
-                    &data.page_buffer[oggs_size as usize..].to_vec(),
-                    data_size as i32,
-                );
+                packet_samples = opus_get_packet_samples(&data.page_buffer[oggs_size..oggs_size + data_size], data_size as i32);
             }
             data.samples_done += packet_samples;
             make_oggs_page(
@@ -416,17 +377,14 @@ pub fn opus_io_read(
 
         /* read data */
         {
-            // size_t bytes_consumed, to_read;
-
-            let mut bytes_consumed = offset - data.logical_offset as usize;
+            let bytes_consumed = offset - data.logical_offset as usize;
             let mut to_read = data.page_size - bytes_consumed;
             if to_read > length {
                 to_read = length;
             }
-            // memcpy(dest, data.page_buffer + bytes_consumed, to_read);
-            dest = data.page_buffer[bytes_consumed..bytes_consumed + to_read].to_vec();
+            dest[total_read..total_read + to_read].copy_from_slice(&data.page_buffer[bytes_consumed..bytes_consumed + to_read]);
+
             total_read += to_read;
-            // dest += to_read;
             offset += to_read;
             length -= to_read;
 
@@ -436,139 +394,122 @@ pub fn opus_io_read(
         }
     }
 
-    let mut file = std::fs::File::create("test_oggdata1.bin").unwrap();
-    use std::io::Write;
-    file.write_all(&dest.as_slice()).unwrap();
-
-    return dest;
+    return total_read;
 }
 
-pub unsafe fn opus_io_init(sf: &mut Streamfile, data: *mut OpusIOData) {
-    //;VGM_LOG("OPUS: init\n");
-    /* read table containing frame sizes */
-    if (*data).table_count != 0 {
-        //;VGM_LOG("OPUS: reading table, offset=%lx, entries=%i\n", data.table_offset, data.table_count);
-
-        // data.frame_table = malloc(data.table_count * sizeof(uint16_t));
-        (*data).frame_table = vec![0; (*data).table_count as usize];
-        // if data.frame_table.is_none() {
-        //     return false;
-        // }
-        use crate::streamfile::read_u16le;
-        for i in 0..(*data).table_count {
-            (*data).frame_table[i as usize] =
-                read_u16le(sf, (*data).table_offset + i as usize * 0x02);
+/* Reads the current block's Opus packet size, header size (skipped) and samples (if known), per type.
+ * EA_M may move the offset past non-audio blocks, so the (updated) physical offset is kept in data. */
+fn opus_get_block_sizes(sf: &mut Streamfile, data: &mut OpusIOData, offset: usize, packet: i32) -> Option<(usize, usize, Option<usize>)> {
+    let mut offset = offset;
+    let data_size;
+    let skip_size;
+    let mut packet_samples = None;
+
+    match data.otype {
+        OpusType::OPUS_SWITCH => {
+            /* format seem to come from opus_test and not Nintendo-specific */
+            data_size = read_u32be(sf, offset).ok()? as usize;
+            skip_size = 0x08; /* size + Opus state(?) */
+        }
+        OpusType::OPUS_UE4_v1 | OpusType::OPUS_FSB => {
+            data_size = read_u16le(sf, offset).ok()? as usize;
+            skip_size = 0x02;
+        }
+        OpusType::OPUS_UE4_v2 => {
+            data_size = read_u16le(sf, offset + 0x00).ok()? as usize;
+            packet_samples = Some(read_u16le(sf, offset + 0x02).ok()? as usize);
+            skip_size = 0x02 + 0x02;
+        }
+        OpusType::OPUS_EA => {
+            data_size = read_u16be(sf, offset).ok()? as usize;
+            skip_size = 0x02;
+        }
+        OpusType::OPUS_EA_M => {
+            let mut flag = read_u8(sf, offset + 0x00).ok()?;
+            if flag == 0x48 {
+                /* should start on 0x44 though */
+                offset += read_u16be(sf, offset + 0x02).ok()? as usize;
+                flag = read_u8(sf, offset + 0x00).ok()?;
+            }
+            let block_size = read_u16be(sf, offset + 0x02).ok()? as usize;
+            skip_size = if flag == 0x45 { block_size } else { 0x08 };
+            data_size = block_size.checked_sub(skip_size)?;
+        }
+        OpusType::OPUS_X | OpusType::OPUS_WWISE => {
+            data_size = get_table_frame_size(data, packet) as usize;
+            skip_size = 0;
+        }
+        OpusType::OPUS_FIXED => {
+            data_size = data.frame_size as usize;
+            skip_size = 0;
         }
     }
 
-    (*data).logical_offset = -1; /* force reset in case old data was cloned when re-opening SFs */
-    (*data).logical_size = opus_io_size(sf, data as *mut std::ffi::c_void);
-    /* force size */
+    data.physical_offset = offset;
+    return Some((data_size, skip_size, packet_samples));
 }
 
-pub fn opus_io_close(sf: &mut Streamfile, data: *mut std::ffi::c_void) {
-    //;VGM_LOG("OPUS: closing\n");
-    let data: &mut OpusIOData = unsafe { &mut *(data as *mut coding::ffmpeg_opus::OpusIOData) };
-    // free(data.frame_table);
-    data.frame_table = Vec::new();
-}
-
-pub fn opus_io_size(sf: &mut Streamfile, data: *mut std::ffi::c_void) -> usize {
-    // off_t offset, max_offset;
-    // size_t logical_size = 0;
-    let dataptr = data as *mut OpusIOData;
-    let mut packet = 0;
+fn opus_io_init(sf: &mut Streamfile, data: &mut OpusIOData) {
+    /* read table containing frame sizes */
+    if data.table_count != 0 {
+        data.frame_table = vec![0; data.table_count as usize];
+        for i in 0..data.table_count as usize {
+            data.frame_table[i] = read_u16le(sf, data.table_offset + i * 0x02).unwrap_or(0);
+        }
+    }
 
-    let data: &mut OpusIOData = unsafe { &mut *(data as *mut coding::ffmpeg_opus::OpusIOData) };
+    data.logical_offset = -1; /* force reset on first read */
+    data.logical_size = opus_io_size(sf, data);
+}
 
+fn opus_io_size(sf: &mut Streamfile, data: &mut OpusIOData) -> usize {
     if data.logical_size != 0 {
         return data.logical_size;
     }
 
-    if data.stream_offset + data.stream_size
-        > sf.get_size(dataptr as *mut std::ffi::c_void)
-    {
-        println!(
-            "OPUS: wrong streamsize {:x} + {:x} vs {:x}\n",
-            data.stream_offset,
-            data.stream_size,
-            sf.get_size(dataptr as *mut std::ffi::c_void)
+    let file_size = sf.get_size(std::ptr::null_mut());
+    if data.stream_offset + data.stream_size > file_size {
+        vgm_log!(
+            "OPUS: wrong streamsize {:x} + {:x} vs {:x}",
+            data.stream_offset, data.stream_size, file_size
         );
         return 0;
     }
 
     let mut offset = data.stream_offset;
-    let mut max_offset = data.stream_offset + data.stream_size;
+    let max_offset = data.stream_offset + data.stream_size;
     let mut logical_size = data.head_buffer.len();
-    use crate::streamfile::{read_u16be, read_u16le, read_u32be, read_u8};
+    let mut packet = 0;
+
     /* get size of the logical stream */
     while offset < max_offset {
-        // size_t data_size, skip_size, oggs_size;
-        let mut data_size = 0;
-        let mut skip_size = 0;
-        let mut oggs_size = 0;
-
-        match data.otype {
-            OpusType::OPUS_SWITCH => {
-                data_size = read_u32be(sf, offset);
-                skip_size = 0x08;
-            }
-            OpusType::OPUS_UE4_v1 | OpusType::OPUS_FSB => {
-                data_size = read_u16le(sf, offset) as u32;
-                skip_size = 0x02;
-            }
-            OpusType::OPUS_UE4_v2 => {
-                data_size = read_u16le(sf, offset) as u32;
-                skip_size = 0x02 + 0x02;
-            }
-            OpusType::OPUS_EA => {
-                data_size = read_u16be(sf, offset) as u32;
-                skip_size = 0x02;
-            }
-            OpusType::OPUS_EA_M => {
-                let mut flag = read_u8(sf, offset + 0x00);
-                if flag == 0x48 {
-                    offset += read_u16be(sf, offset + 0x02) as usize;
-                    flag = read_u8(sf, offset + 0x00);
-                }
-                data_size = read_u16be(sf, offset + 0x02) as u32;
-                skip_size = if flag == 0x45 { data_size } else { 0x08 };
-                data_size -= skip_size;
-            }
-            OpusType::OPUS_X | OpusType::OPUS_WWISE => {
-                data_size = get_table_frame_size(&data, packet) as u32;
-                skip_size = 0x00;
-            }
-            OpusType::OPUS_FIXED => {
-                data_size = data.frame_size as u32;
-                skip_size = 0;
-            }
-            _ => {
-                return 0;
-            }
-        }
+        let (data_size, skip_size) = match opus_get_block_sizes(sf, data, offset, packet) {
+            Some((data_size, skip_size, _)) => (data_size, skip_size),
+            None => return 0,
+        };
+        offset = data.physical_offset;
 
         /* FSB pads data after end (total size without frame headers is given but not too useful here) */
-        if (data.otype == OpusType::OPUS_FSB || data.otype == OpusType::OPUS_EA_M) && data_size == 0
-        {
+        if (data.otype == OpusType::OPUS_FSB || data.otype == OpusType::OPUS_EA_M) && data_size == 0 {
             break;
         }
 
         if data_size == 0 {
-            println!("OPUS: data_size is 0 at {:x}\n", offset);
+            vgm_log!("OPUS: data_size is 0 at {:x}", offset);
             return 0; /* bad rip? or could 'break' and truck along */
         }
 
-        oggs_size = 0x1b + (data_size / 0xFF + 1); /* OggS page: base size + lacing values */
+        let oggs_size = 0x1b + (data_size / 0xFF + 1); /* OggS page: base size + lacing values */
 
-        offset += (data_size + skip_size) as usize;
-        logical_size += (oggs_size + data_size) as usize;
+        offset += data_size + skip_size;
+        logical_size += oggs_size + data_size;
         packet += 1;
     }
+    data.physical_offset = data.stream_offset;
 
     /* logical size can be bigger though */
-    if offset > sf.get_size(unsafe { dataptr as *mut std::ffi::c_void }) {
-        println!("OPUS: wrong size");
+    if offset > file_size {
+        vgm_log!("OPUS: wrong size");
         return 0;
     }
 
@@ -577,39 +518,65 @@ pub fn opus_io_size(sf: &mut Streamfile, data: *mut std::ffi::c_void) -> usize {
 }
 
 pub fn make_oggs_first(cfg: &mut OpusConfig) -> Vec<u8> {
-    let mut buf: Vec<u8> = vec![0; 256];
-    let page_size = 0x1c; /* fixed for header page */
-    /* make header (first data, then page for checksum) */
-    let mut bytes = make_opus_header(&mut buf[page_size..].as_mut(), cfg);
+    let mut buf: Vec<u8> = vec![0; 0x100];
+    let page_size = 0x1c; /* fixed for header/comment pages (single lacing value) */
 
-    // let mut file = std::fs::File::create("test_head_opusheader.bin").unwrap();
-    // use std::io::Write;
-    // file.write_all(&buf).unwrap();
-
-    make_oggs_page(&mut buf.as_mut_slice(), bytes, 0, 0);
-
-    let mut buf_done = page_size as i32 + bytes;
-
-    // let mut file = std::fs::File::create("test_head_oggspage.bin").unwrap();
-    // file.write_all(&buf).unwrap();
+    /* make header (first data, then page for checksum) */
+    let bytes = make_opus_header(&mut buf[page_size..], cfg);
+    if bytes <= 0 || make_oggs_page(&mut buf, bytes, 0, 0) <= 0 {
+        return Vec::new();
+    }
+    let mut buf_done = page_size + bytes as usize;
 
-    /* make comment */
-    bytes = make_opus_comment(&mut buf[page_size..].as_mut());
+    /* make comment, in the next page */
+    let bytes = make_opus_comment(&mut buf[buf_done + page_size..]);
+    if bytes <= 0 || make_oggs_page(&mut buf[buf_done..], bytes, 1, 0) <= 0 {
+        return Vec::new();
+    }
+    buf_done += page_size + bytes as usize;
 
-    // let mut file = std::fs::File::create("test_head_opuscomment.bin").unwrap();
-    // file.write_all(&buf).unwrap();
+    buf.truncate(buf_done);
+    return buf;
+}
 
-    make_oggs_page(&mut buf.as_mut_slice(), bytes, 1, 0);
-    // buf[..bytes as usize].copy_from_slice(&buf2[..bytes as usize]);
+/* Switch Opus has no header, so encoder delay is guessed from the first packet */
+pub fn switch_opus_get_encoder_delay(offset: usize, sf: &mut Streamfile) -> i32 {
+    return custom_opus_get_encoder_delay(offset, sf, OpusType::OPUS_SWITCH);
+}
 
-    buf_done += page_size as i32 + bytes;
-    buf = buf[..buf_done as usize].to_vec();
+fn custom_opus_get_encoder_delay(offset: usize, sf: &mut Streamfile, otype: OpusType) -> i32 {
+    let mut data = OpusIOData { otype, ..Default::default() };
+    let mut buf = [0u8; 0x04];
+
+    /* first packet */
+    let (data_size, skip_size) = match opus_get_block_sizes(sf, &mut data, offset, 0) {
+        Some((data_size, skip_size, _)) => (data_size, skip_size),
+        None => return 0,
+    };
+    let offset = data.physical_offset;
+    if read_streamfile(sf, offset + skip_size, &mut buf) == 0 {
+        return 0;
+    }
 
-    let mut file = std::fs::File::create("test_head_all.bin").unwrap();
-    use std::io::Write;
-    file.write_all(&buf.as_slice()).unwrap();
+    /* encoder delay seems fixed to 1/8 of samples per frame, but may need more testing */
+    let packet_samples = opus_get_packet_samples(&buf, data_size as i32);
+    return (packet_samples / 8) as i32;
+}
 
-    return buf.to_vec();
+pub fn init_ffmpeg_switch_opus(
+    sf: &mut Streamfile,
+    start_offset: usize,
+    data_size: usize,
+    channels: i32,
+    skip: i32,
+    sample_rate: i32,
+) -> Result<VGMStreamCodecData> {
+    let mut cfg: OpusConfig = Default::default();
+    cfg.channels = channels as u8;
+    cfg.skip = skip;
+    cfg.sample_rate = sample_rate;
+
+    return init_ffmpeg_custom_opus_config(sf, start_offset, data_size, &mut cfg, OpusType::OPUS_SWITCH);
 }
 
 pub fn make_opus_header(buf: &mut [u8], cfg: &mut OpusConfig) -> i32 {
@@ -635,12 +602,12 @@ pub fn make_opus_header(buf: &mut [u8], cfg: &mut OpusConfig) -> i32 {
     }
 
     if cfg.skip < 0 {
-        println!("OPUS: wrong skip {}", cfg.skip);
+        vgm_log!("OPUS: wrong skip {}", cfg.skip);
         cfg.skip = 0; /* ??? */
     }
 
     if header_size > buf.len() as i32 {
-        println!("OPUS: buffer can't hold header");
+        vgm_log!("OPUS: buffer can't hold header");
         return 0;
     }
 
@@ -694,7 +661,7 @@ pub fn make_oggs_page(buf: &mut [u8], data_size: i32, page_sequence: i32, granul
     let stream_serial_number = 0x7667;
 
     if 0x1b + (data_size / 0xFF + 1) + data_size > buf.len() as i32 {
-        println!("OPUS: buffer can't hold OggS page\n");
+        vgm_log!("OPUS: buffer can't hold OggS page");
         return 0;
     }
 
@@ -740,9 +707,6 @@ pub fn make_oggs_page(buf: &mut [u8], data_size: i32, page_sequence: i32, granul
     // put_u32le(buf+0x16, checksum);
     buf[0x16..0x1A].copy_from_slice(&checksum.to_le_bytes());
 
-    let mut file = std::fs::File::create("test_head_oggspage.bin").unwrap();
-    use std::io::Write;
-    file.write_all(&buf[..page_done]).unwrap();
 
     return page_done as i32;
 }
@@ -753,7 +717,7 @@ pub fn make_opus_comment(buf: &mut [u8]) -> i32 {
     let comment_size = 0x14 + vendor_string.len() + user_comment_0_string.len();
 
     if comment_size > buf.len() {
-        println!("OPUS: buffer can't hold comment");
+        vgm_log!("OPUS: buffer can't hold comment");
         return 0;
     }
 
@@ -820,189 +784,16 @@ pub fn get_oggs_checksum(data: &mut [u8], bytes: i32) -> u32 {
     return crc_reg;
 }
 
-impl Streamfile {
-    pub fn opus_io_read(&mut self, offset: usize, length: usize, data: &mut OpusIOData) -> Vec<u8> {
-        let mut total_read = 0;
-        let mut dest: Vec<u8> = Vec::new();
-        let mut offset = offset;
-        let mut length = length;
-        /* ignore bad reads */
-        if offset > data.logical_size {
-            return Vec::new();
-        }
-
-        /* previous offset: re-start as we can't map logical<>physical offsets */
-        if offset < data.logical_offset as usize || data.logical_offset < 0 {
-            data.physical_offset = data.stream_offset;
-            data.logical_offset = 0x00;
-            data.page_size = 0;
-            data.samples_done = 0;
-            data.sequence = 2; /* appended header+comment is 0/1 */
-
-            if offset >= data.head_buffer.len() {
-                data.logical_offset = data.head_buffer.len() as isize;
-            }
-        }
-
-        /* insert fake header */
-        if offset < data.head_buffer.len() {
-            // size_t bytes_consumed, to_read;
-
-            let mut bytes_consumed = offset - data.logical_offset as usize;
-            let mut to_read = data.head_buffer.len() - bytes_consumed;
-            if to_read > length {
-                to_read = length;
-            }
-            // memcpy(dest, data.head_buffer + bytes_consumed, to_read);
-            dest = data.head_buffer[bytes_consumed..bytes_consumed + to_read].to_vec();
-
-            let mut file = std::fs::File::create("test_append_head.bin").unwrap();
-            use std::io::Write;
-            file.write_all(&dest.as_slice()).unwrap();
-
-            total_read += to_read;
-            //dest += to_read;
-            offset += to_read;
-            length -= to_read;
-            data.logical_offset += to_read as isize;
-        }
-
-        /* read blocks, one at a time */
-        while length > 0 {
-            /* ignore EOF */
-            if data.logical_offset >= data.logical_size as isize {
-                break;
-            }
-
-            /* process new block */
-            if data.page_size == 0 {
-                // size_t data_size, skip_size, oggs_size, packet_samples = 0;
-                let mut data_size = 0;
-                let mut skip_size = 0;
-                let mut packet_samples = 0;
-                use crate::streamfile::{read_u16be, read_u16le, read_u32be, read_u8};
-                match data.otype {
-                    OpusType::OPUS_SWITCH => {
-                        /* format seem to come from opus_test and not Nintendo-specific */
-                        data_size = read_u32be(self, data.physical_offset);
-                        skip_size = 0x08; /* size + Opus state(?) */
-                    }
-                    OpusType::OPUS_UE4_v1 | OpusType::OPUS_FSB => {
-                        data_size = read_u16le(self, data.physical_offset) as u32;
-                        skip_size = 0x02;
-                    }
-                    OpusType::OPUS_UE4_v2 => {
-                        data_size = read_u16le(self, data.physical_offset + 0x00) as u32;
-                        packet_samples = read_u16le(self, data.physical_offset + 0x02);
-                        skip_size = 0x02 + 0x02;
-                    }
-                    OpusType::OPUS_EA => {
-                        data_size = read_u16be(self, data.physical_offset) as u32;
-                        skip_size = 0x02;
-                    }
-                    OpusType::OPUS_EA_M => {
-                        let mut flag = read_u8(self, data.physical_offset + 0x00);
-                        if flag == 0x48 {
-                            /* should start on 0x44 though */
-                            data.physical_offset +=
-                                read_u16be(self, data.physical_offset + 0x02) as usize;
-                            flag = read_u8(self, data.physical_offset + 0x00);
-                        }
-                        data_size = read_u16be(self, data.physical_offset + 0x02) as u32;
-                        skip_size = if flag == 0x45 { data_size } else { 0x08 };
-                        data_size -= skip_size;
-                    }
-                    OpusType::OPUS_X | OpusType::OPUS_WWISE => {
-                        data_size = get_table_frame_size(data, data.sequence as i32 - 2) as u32;
-                        skip_size = 0;
-                    }
-                    OpusType::OPUS_FIXED => {
-                        data_size = data.frame_size as u32;
-                        skip_size = 0;
-                    }
-                    _ => {
-                        return Vec::new();
-                    }
-                }
-
-                let mut oggs_size = 0x1b + (data_size / 0xFF + 1); /* OggS page: base size + lacing values */
-
-                data.block_size = (data_size + skip_size) as usize;
-                data.page_size = (oggs_size + data_size) as usize;
-
-                if data.page_size > data.page_buffer.len() {
-                    /* happens on bad reads/EOF too */
-                    println!(
-                        "OPUS: buffer can't hold OggS at {:x}, size={:x}\n",
-                        data.physical_offset, data.page_size
-                    );
-                    data.page_size = 0;
-                }
-
-                /* create fake OggS page (full page for checksums) */
-                // read_streamfile(data.page_buffer+oggs_size, data.physical_offset + skip_size, data_size, sf); /* store page data */
-                if packet_samples == 0 {
-                    packet_samples = opus_get_packet_samples(
-                        &data.page_buffer[oggs_size as usize..].to_vec(),
-                        data_size as i32,
-                    ) as u16;
-                }
-                data.samples_done += packet_samples as usize;
-                make_oggs_page(
-                    &mut data.page_buffer,
-                    data_size as i32,
-                    data.sequence as i32,
-                    data.samples_done as i32,
-                );
-                data.sequence += 1;
-            }
-
-            /* move to next block */
-            if offset >= data.logical_offset as usize + data.page_size {
-                data.physical_offset += data.block_size;
-                data.logical_offset += data.page_size as isize;
-                data.page_size = 0;
-                continue;
-            }
-
-            /* read data */
-            {
-                // size_t bytes_consumed, to_read;
-
-                let mut bytes_consumed = offset - data.logical_offset as usize;
-                let mut to_read = data.page_size - bytes_consumed;
-                if to_read > length {
-                    to_read = length;
-                }
-                // memcpy(dest, data.page_buffer + bytes_consumed, to_read);
-                dest = data.page_buffer[bytes_consumed..bytes_consumed + to_read].to_vec();
-
-                total_read += to_read;
-                // dest += to_read;
-                offset += to_read;
-                length -= to_read;
-
-                if to_read == 0 {
-                    break; /* error/EOF */
-                }
-            }
-        }
-
-        let mut file = std::fs::File::create("test_ogg_data.bin").unwrap();
-        use std::io::Write;
-        file.write_all(&dest.as_slice()).unwrap();
-
-        return dest;
+pub fn opus_get_packet_samples(buf: &[u8], len: i32) -> usize {
+    if len < 1 || buf.is_empty() {
+        return 0;
     }
-}
-
-pub fn opus_get_packet_samples(buf: &Vec<u8>, len: i32) -> usize {
     return opus_packet_get_nb_frames(buf, len) as usize
         * opus_packet_get_samples_per_frame(buf, 48000) as usize;
 }
 
 /* from opus_decoder.c's opus_packet_get_samples_per_frame */
-pub fn opus_packet_get_samples_per_frame(data: &Vec<u8>, fs: u32) -> u32 {
+pub fn opus_packet_get_samples_per_frame(data: &[u8], fs: u32) -> u32 {
     let mut audiosize: u32 = 0;
     if data[0] & 0x80 != 0 {
         audiosize = (data[0] as u32 >> 3) & 0x3;
@@ -1025,7 +816,7 @@ pub fn opus_packet_get_samples_per_frame(data: &Vec<u8>, fs: u32) -> u32 {
 }
 
 /* from opus_decoder.c's opus_packet_get_nb_frames */
-pub fn opus_packet_get_nb_frames(packet: &Vec<u8>, len: i32) -> i32 {
+pub fn opus_packet_get_nb_frames(packet: &[u8], len: i32) -> i32 {
     let mut count = 0;
     if len < 1 {
         return 0;
@@ -1045,8 +836,8 @@ pub fn opus_packet_get_nb_frames(packet: &Vec<u8>, len: i32) -> i32 {
 /* some formats store all frames in a table, rather than right before the frame */
 pub fn get_table_frame_size(data: &OpusIOData, frame: i32) -> u16 {
     if frame < 0 || frame >= data.table_count {
-        println!(
-            "OPUS: wrong requested frame {}, count={}\n",
+        vgm_log!(
+            "OPUS: wrong requested frame {}, count={}",
             frame, data.table_count
         );
         return 0;
@@ -1,6 +1,13 @@
 pub mod coding;
+pub mod coding_utils;
 pub mod pcm_decoder;
 pub mod adx;
+pub mod ima;
+pub mod ngc_dsp;
+pub mod ptadpcm;
 pub mod vorbis;
+pub mod vorbis_bitreader;
+pub mod vorbis_custom_utils_wwise;
 pub mod ffmpeg_opus;
-pub mod ffmpeg;
\ No newline at end of file
+pub mod ffmpeg;
+pub mod mpeg_custom_utils_ahx;
\ No newline at end of file
@@ -0,0 +1,163 @@
+use crate::vgm_log;
+
+use super::coding::MpegCustomConfig;
+use super::ffmpeg::{ffmpeg_set_skip_samples, init_ffmpeg_offset};
+use super::ffmpeg_opus::FFmpegCodecData;
+use crate::error::{Result, VgmstreamError};
+use crate::streamfile::{read_streamfile, Streamfile};
+use crate::util::reader::get_u32be;
+
+/* AHX is MPEG-2 Layer II (mono, LSF) with frames trimmed to the bits actually used, so the
+ * header's frame size isn't the real size. Frames are restored to their full size (0-padded)
+ * for FFmpeg's MP2 decoder, and decrypted if needed. Voice files are small so the whole
+ * stream is rebuilt at once. */
+
+/* CRI's decoder output starts after the Layer II filterbank delay (observed) */
+const AHX_ENCODER_DELAY: i32 = 480;
+/* "AHXE(c)CRI": end-of-stream marker after the last frame */
+const AHX_END_MARKER: u32 = 0x41485845;
+
+const MPEG_BITRATES_V1_L2: [u32; 15] = [0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384];
+const MPEG_BITRATES_V2_L2: [u32; 15] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+const MPEG_SAMPLE_RATES: [[u32; 3]; 3] = [
+    [44100, 48000, 32000], /* MPEG-1 */
+    [22050, 24000, 16000], /* MPEG-2 */
+    [11025, 12000, 8000],  /* MPEG-2.5 */
+];
+
+/* Returns the full (untrimmed) size of a Layer II frame from its header */
+pub(crate) fn mpeg_get_layer2_frame_size(header: u32) -> Option<usize> {
+    if (header >> 21) & 0x7ff != 0x7ff {
+        return None; /* sync */
+    }
+    let version = match (header >> 19) & 0x03 {
+        3 => 0, /* MPEG-1 */
+        2 => 1, /* MPEG-2 */
+        0 => 2, /* MPEG-2.5 */
+        _ => return None,
+    };
+    if (header >> 17) & 0x03 != 2 {
+        return None; /* Layer II */
+    }
+    let bitrate_index = ((header >> 12) & 0x0f) as usize;
+    let sr_index = ((header >> 10) & 0x03) as usize;
+    let padding = ((header >> 9) & 0x01) as usize;
+    if bitrate_index == 0 || bitrate_index == 0x0f || sr_index == 3 {
+        return None;
+    }
+
+    let bitrate = if version == 0 { MPEG_BITRATES_V1_L2[bitrate_index] } else { MPEG_BITRATES_V2_L2[bitrate_index] };
+    let sample_rate = MPEG_SAMPLE_RATES[version][sr_index];
+
+    /* Layer II is always 1152 samples per frame (no LSF halving like Layer III) */
+    return Some((144000 * bitrate / sample_rate) as usize + padding);
+}
+
+/* Finds where the current frame ends: at the next frame with the same header, the end marker, or EOF */
+pub(crate) fn ahx_get_frame_end(data: &[u8], offset: usize, header: u32, full_size: usize) -> usize {
+    let max_end = std::cmp::min(offset + full_size, data.len());
+
+    let mut pos = offset + 0x04;
+    while pos + 0x04 <= max_end {
+        let value = get_u32be(&data[pos..]);
+        if value == header || value == AHX_END_MARKER {
+            return pos;
+        }
+        pos += 1;
+    }
+    return max_end;
+}
+
+/* MPEG bitstreams are MSB first */
+fn read_bits_msb(buf: &[u8], bit_offset: usize, bits: usize) -> u32 {
+    let mut value = 0;
+    for i in bit_offset..bit_offset + bits {
+        value = (value << 1) | ((buf[i / 8] >> (7 - i % 8)) & 1) as u32;
+    }
+    return value;
+}
+
+fn write_bits_msb(buf: &mut [u8], bit_offset: usize, bits: usize, value: u32) {
+    for (n, i) in (bit_offset..bit_offset + bits).enumerate() {
+        let bit = ((value >> (bits - 1 - n)) & 1) as u8;
+        buf[i / 8] = (buf[i / 8] & !(0x80 >> (i % 8))) | (bit << (7 - i % 8));
+    }
+}
+
+/* Encryption 0x08 modifies a few bits after the bit allocation (scfsi) of each frame. The first
+ * 2 bits select the key (none/key1/key2/key3), then key bits are XOR'd 2 at a time (from lower
+ * to higher) with the following pairs. DC AHX (type 0x10) encrypts more bits. */
+pub(crate) fn ahx_decrypt_type08(frame: &mut [u8], cfg: &MpegCustomConfig) {
+    /* 32b header + 75b of bit allocation (sblimit 30 for mono LSF: 4*4b + 7*3b + 19*2b) */
+    let mut bit_offset = 107;
+
+    let index = read_bits_msb(frame, bit_offset, 2);
+    bit_offset += 2;
+    let current_key = match index {
+        1 => cfg.cri_key1,
+        2 => cfg.cri_key2,
+        3 => cfg.cri_key3,
+        _ => 0,
+    } as u32;
+
+    let encrypted_bits = if cfg.cri_type == 0x10 { 16 } else { 6 };
+    for i in (0..encrypted_bits).step_by(2) {
+        let value = read_bits_msb(frame, bit_offset, 2) ^ ((current_key >> i) & 0x03);
+        write_bits_msb(frame, bit_offset, 2, value);
+        bit_offset += 2;
+    }
+}
+
+/* Restores trimmed frames to their full size, stopping at the first frame with a different header */
+pub(crate) fn ahx_rebuild_frames(data: &[u8], header: u32, full_size: usize, cfg: &MpegCustomConfig) -> Vec<u8> {
+    let mut stream: Vec<u8> = Vec::with_capacity(data.len() * 2);
+    let mut offset = 0;
+    while offset + 0x04 <= data.len() && get_u32be(&data[offset..]) == header {
+        let frame_end = ahx_get_frame_end(data, offset, header, full_size);
+
+        let frame_start = stream.len();
+        stream.extend_from_slice(&data[offset..frame_end]);
+        stream.resize(frame_start + full_size, 0);
+
+        if cfg.encryption == 0x08 {
+            ahx_decrypt_type08(&mut stream[frame_start..], cfg);
+        }
+
+        offset = frame_end;
+    }
+    return stream;
+}
+
+/* Rebuilds AHX frames into a standard MP2 stream and opens it with FFmpeg */
+pub fn init_ffmpeg_ahx(sf: &mut Streamfile, start_offset: usize, cfg: &MpegCustomConfig) -> Result<Box<FFmpegCodecData>> {
+    let file_size = sf.get_size(std::ptr::null_mut());
+    if start_offset + 0x04 > file_size {
+        return Err(VgmstreamError::Truncated { meta: "AHX", offset: start_offset, size: file_size });
+    }
+
+    let mut data = vec![0u8; file_size - start_offset];
+    if read_streamfile(sf, start_offset, &mut data) != data.len() {
+        return Err(VgmstreamError::Truncated { meta: "AHX", offset: start_offset, size: data.len() });
+    }
+
+    /* all frames share the first frame's header */
+    let header = get_u32be(&data);
+    let Some(full_size) = mpeg_get_layer2_frame_size(header) else {
+        return Err(VgmstreamError::BadHeader { meta: "AHX", offset: start_offset, field: "MPEG frame header" });
+    };
+
+    let stream = ahx_rebuild_frames(&data, header, full_size, cfg);
+
+    if stream.is_empty() {
+        vgm_log!("AHX: no frames found at {:x}", start_offset);
+        return Err(VgmstreamError::BadHeader { meta: "AHX", offset: start_offset, field: "frames" });
+    }
+
+    let stream_size = stream.len();
+    let mut sf_mp2 = Streamfile::from_reader(sf.name.clone(), std::io::Cursor::new(stream))?;
+    sf_mp2.open = sf.open.clone();
+
+    let mut ffmpeg_data = init_ffmpeg_offset(&mut sf_mp2, 0, stream_size as u64)?;
+    ffmpeg_set_skip_samples(&mut ffmpeg_data, AHX_ENCODER_DELAY);
+    return Ok(ffmpeg_data);
+}
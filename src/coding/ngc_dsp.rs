@@ -0,0 +1,93 @@
+use crate::vgm_log;
+use crate::error::Result;
+use crate::util::{reader::*, util::clamp16};
+use crate::streamfile::{read_s16be, read_s16le, read_streamfile};
+use crate::vgmstream::{Streamfile, VGMStream, VGMStreamChannel};
+
+pub fn decode_ngc_dsp(
+    stream: &mut VGMStreamChannel,
+    outbuf: &mut [i16],
+    channelspacing: i32,
+    first_sample: i32,
+    samples_to_do: i32,
+) {
+    let mut frame = [0u8; 0x08];
+    let mut sample_count = 0;
+    let mut hist1 = stream.adpcm_history1_16 as i32;
+    let mut hist2 = stream.adpcm_history2_16 as i32;
+
+    /* external interleave (fixed size), mono */
+    let bytes_per_frame = 0x08;
+    let samples_per_frame = (bytes_per_frame - 0x01) * 2; /* always 14 */
+    let frames_in = first_sample / samples_per_frame;
+    let first_sample = first_sample % samples_per_frame;
+
+    /* parse frame header */
+    let frame_offset = stream.offset as usize + (bytes_per_frame * frames_in) as usize;
+    if let Some(sf) = stream.streamfile.as_mut() {
+        read_streamfile(sf, frame_offset, &mut frame); /* ignore EOF errors */
+    }
+    let scale = 1 << (frame[0] & 0xf);
+    let mut index = ((frame[0] >> 4) & 0xf) as usize;
+
+    if index > 7 {
+        vgm_log!("DSP: incorrect index at {:x}", frame_offset);
+    }
+    index &= 0x07; /* (rare) only 3 bits */
+    let coef1 = stream.adpcm_coef[index * 2 + 0] as i32;
+    let coef2 = stream.adpcm_coef[index * 2 + 1] as i32;
+
+    /* decode nibbles */
+    for i in first_sample..(first_sample + samples_to_do) {
+        let nibbles = frame[0x01 + i as usize / 2];
+
+        let mut sample = if i & 1 == 1 {
+            /* high nibble first */
+            get_low_nibble_signed(nibbles)
+        } else {
+            get_high_nibble_signed(nibbles)
+        };
+        sample = (sample * scale) << 11;
+        sample = (sample + 1024 + coef1 * hist1 + coef2 * hist2) >> 11;
+        sample = clamp16(sample);
+
+        outbuf[sample_count] = sample as i16;
+        sample_count += channelspacing as usize;
+
+        hist2 = hist1;
+        hist1 = sample;
+    }
+
+    stream.adpcm_history1_16 = hist1 as i16;
+    stream.adpcm_history2_16 = hist2 as i16;
+}
+
+pub fn dsp_bytes_to_samples(bytes: isize, channels: i32) -> i32 {
+    if channels <= 0 {
+        return 0;
+    }
+    return (bytes / channels as isize / 0x08 * 14) as i32;
+}
+
+/* reads DSP coefs built in the streamfile, 16 per channel (standard DSP header coefs, WiiH chunks, etc) */
+pub fn dsp_read_coefs(vgmstream: &mut VGMStream, sf: &mut Streamfile, offset: usize, spacing: usize, big_endian: bool) -> Result<()> {
+    let read_s16 = if big_endian { read_s16be } else { read_s16le };
+
+    for ch in 0..vgmstream.channels as usize {
+        for i in 0..16 {
+            vgmstream.ch[ch].adpcm_coef[i] = read_s16(sf, offset + ch * spacing + i * 2)?;
+        }
+    }
+    return Ok(());
+}
+
+/* reads initial DSP history (yn1/yn2), found right after coefs+gain+ps in standard headers */
+pub fn dsp_read_hist(vgmstream: &mut VGMStream, sf: &mut Streamfile, offset: usize, spacing: usize, big_endian: bool) -> Result<()> {
+    let read_s16 = if big_endian { read_s16be } else { read_s16le };
+
+    for ch in 0..vgmstream.channels as usize {
+        vgmstream.ch[ch].adpcm_history1_16 = read_s16(sf, offset + ch * spacing + 0x00)?;
+        vgmstream.ch[ch].adpcm_history2_16 = read_s16(sf, offset + ch * spacing + 0x02)?;
+    }
+    return Ok(());
+}
@@ -1,6 +1,75 @@
+use crate::util::{reader::*, util::clamp16};
+use crate::streamfile::read_streamfile;
+use crate::vgmstream::VGMStreamChannel;
+
+/* reads the bytes of samples_to_do samples (mono, no interleave within), zeroing past EOF */
+fn read_pcm_frame(stream: &mut VGMStreamChannel, first_sample: i32, samples_to_do: i32, bytes_per_sample: usize) -> Vec<u8> {
+    let mut frame = vec![0u8; samples_to_do as usize * bytes_per_sample];
+    let offset = stream.offset as usize + first_sample as usize * bytes_per_sample;
+    if let Some(sf) = stream.streamfile.as_mut() {
+        read_streamfile(sf, offset, &mut frame); /* ignore EOF errors */
+    }
+    return frame;
+}
+
+pub fn decode_pcm16le(stream: &mut VGMStreamChannel, outbuf: &mut [i16], channelspacing: i32, first_sample: i32, samples_to_do: i32) {
+    let frame = read_pcm_frame(stream, first_sample, samples_to_do, 0x02);
+
+    for (i, sample) in frame.chunks_exact(0x02).enumerate() {
+        outbuf[i * channelspacing as usize] = get_s16le(sample);
+    }
+}
+
+pub fn decode_pcm16be(stream: &mut VGMStreamChannel, outbuf: &mut [i16], channelspacing: i32, first_sample: i32, samples_to_do: i32) {
+    let frame = read_pcm_frame(stream, first_sample, samples_to_do, 0x02);
+
+    for (i, sample) in frame.chunks_exact(0x02).enumerate() {
+        outbuf[i * channelspacing as usize] = get_s16be(sample);
+    }
+}
+
+pub fn decode_pcm8_unsigned(stream: &mut VGMStreamChannel, outbuf: &mut [i16], channelspacing: i32, first_sample: i32, samples_to_do: i32) {
+    let frame = read_pcm_frame(stream, first_sample, samples_to_do, 0x01);
+
+    for (i, sample) in frame.iter().enumerate() {
+        outbuf[i * channelspacing as usize] = ((*sample as i32) * 0x100 - 0x8000) as i16;
+    }
+}
+
+pub fn decode_pcm24le(stream: &mut VGMStreamChannel, outbuf: &mut [i16], channelspacing: i32, first_sample: i32, samples_to_do: i32) {
+    let frame = read_pcm_frame(stream, first_sample, samples_to_do, 0x03);
+
+    for (i, sample) in frame.chunks_exact(0x03).enumerate() {
+        /* keep the top 16 bits, sign extended */
+        let v = ((sample[0] as u32) << 8 | (sample[1] as u32) << 16 | (sample[2] as u32) << 24) as i32;
+        outbuf[i * channelspacing as usize] = (v >> 16) as i16;
+    }
+}
+
+pub fn decode_pcm24be(stream: &mut VGMStreamChannel, outbuf: &mut [i16], channelspacing: i32, first_sample: i32, samples_to_do: i32) {
+    let frame = read_pcm_frame(stream, first_sample, samples_to_do, 0x03);
+
+    for (i, sample) in frame.chunks_exact(0x03).enumerate() {
+        /* keep the top 16 bits, sign extended */
+        let v = ((sample[2] as u32) << 8 | (sample[1] as u32) << 16 | (sample[0] as u32) << 24) as i32;
+        outbuf[i * channelspacing as usize] = (v >> 16) as i16;
+    }
+}
+
+pub fn decode_pcmfloat(stream: &mut VGMStreamChannel, outbuf: &mut [i16], channelspacing: i32, first_sample: i32, samples_to_do: i32, big_endian: bool) {
+    let get_f32 = if big_endian { get_f32be } else { get_f32le };
+    let frame = read_pcm_frame(stream, first_sample, samples_to_do, 0x04);
+
+    for (i, sample) in frame.chunks_exact(0x04).enumerate() {
+        let sample_float = get_f32(sample);
+        let sample_pcm = (sample_float * 32767.0 + 0.5).floor() as i32;
+        outbuf[i * channelspacing as usize] = clamp16(sample_pcm) as i16;
+    }
+}
+
 pub fn pcm_bytes_to_samples(bytes: isize, channels: i32, bits_per_sample: i32) -> i32 {
     if channels <= 0 || bits_per_sample <= 0 {
         return 0;
     }
     return (bytes * 8) as i32 / channels / bits_per_sample;
-}
\ No newline at end of file
+}
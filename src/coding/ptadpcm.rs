@@ -0,0 +1,138 @@
+use crate::vgm_log;
+use crate::util::{reader::get_s16le, util::clamp16};
+use crate::streamfile::read_streamfile;
+use crate::vgmstream::VGMStreamChannel;
+
+/* Platinum "PtADPCM" custom ADPCM for Wwise (reverse engineered from .exes). */
+
+/* a somewhat IMA-like mix of pre-calculated [index][nibble][step,index] + exponential scale */
+const PTADPCM_TABLE: [[[i32; 2]; 16]; 16] = [
+    [
+        [   -14,  2], [   -10,  2], [    -7,  1], [    -5,  1], [    -3,  0], [    -2,  0], [    -1,  0], [     0,  0],
+        [     0,  0], [     1,  0], [     2,  0], [     3,  0], [     5,  1], [     7,  1], [    10,  2], [    14,  2],
+    ],
+    [
+        [   -28,  3], [   -20,  3], [   -14,  2], [   -10,  2], [    -7,  1], [    -5,  1], [    -3,  1], [    -1,  0],
+        [     1,  0], [     3,  1], [     5,  1], [     7,  1], [    10,  2], [    14,  2], [    20,  3], [    28,  3],
+    ],
+    [
+        [   -56,  4], [   -40,  4], [   -28,  3], [   -20,  3], [   -14,  2], [   -10,  2], [    -6,  2], [    -2,  1],
+        [     2,  1], [     6,  2], [    10,  2], [    14,  2], [    20,  3], [    28,  3], [    40,  4], [    56,  4],
+    ],
+    [
+        [  -112,  5], [   -80,  5], [   -56,  4], [   -40,  4], [   -28,  3], [   -20,  3], [   -12,  3], [    -4,  2],
+        [     4,  2], [    12,  3], [    20,  3], [    28,  3], [    40,  4], [    56,  4], [    80,  5], [   112,  5],
+    ],
+    [
+        [  -224,  6], [  -160,  6], [  -112,  5], [   -80,  5], [   -56,  4], [   -40,  4], [   -24,  4], [    -8,  3],
+        [     8,  3], [    24,  4], [    40,  4], [    56,  4], [    80,  5], [   112,  5], [   160,  6], [   224,  6],
+    ],
+    [
+        [  -448,  7], [  -320,  7], [  -224,  6], [  -160,  6], [  -112,  5], [   -80,  5], [   -48,  5], [   -16,  4],
+        [    16,  4], [    48,  5], [    80,  5], [   112,  5], [   160,  6], [   224,  6], [   320,  7], [   448,  7],
+    ],
+    [
+        [  -896,  8], [  -640,  8], [  -448,  7], [  -320,  7], [  -224,  6], [  -160,  6], [   -96,  6], [   -32,  5],
+        [    32,  5], [    96,  6], [   160,  6], [   224,  6], [   320,  7], [   448,  7], [   640,  8], [   896,  8],
+    ],
+    [
+        [ -1792,  9], [ -1280,  9], [  -896,  8], [  -640,  8], [  -448,  7], [  -320,  7], [  -192,  7], [   -64,  6],
+        [    64,  6], [   192,  7], [   320,  7], [   448,  7], [   640,  8], [   896,  8], [  1280,  9], [  1792,  9],
+    ],
+    [
+        [ -3584, 10], [ -2560, 10], [ -1792,  9], [ -1280,  9], [  -896,  8], [  -640,  8], [  -384,  8], [  -128,  7],
+        [   128,  7], [   384,  8], [   640,  8], [   896,  8], [  1280,  9], [  1792,  9], [  2560, 10], [  3584, 10],
+    ],
+    [
+        [ -7168, 11], [ -5120, 11], [ -3584, 10], [ -2560, 10], [ -1792,  9], [ -1280,  9], [  -768,  9], [  -256,  8],
+        [   256,  8], [   768,  9], [  1280,  9], [  1792,  9], [  2560, 10], [  3584, 10], [  5120, 11], [  7168, 11],
+    ],
+    [
+        [-14336, 11], [-10240, 11], [ -7168, 11], [ -5120, 11], [ -3584, 10], [ -2560, 10], [ -1536, 10], [  -512,  9],
+        [   512,  9], [  1536, 10], [  2560, 10], [  3584, 10], [  5120, 11], [  7168, 11], [ 10240, 11], [ 14336, 11],
+    ],
+    [
+        [-28672, 11], [-20480, 11], [-14336, 11], [-10240, 11], [ -7168, 11], [ -5120, 11], [ -3072, 11], [ -1024, 10],
+        [  1024, 10], [  3072, 11], [  5120, 11], [  7168, 11], [ 10240, 11], [ 14336, 11], [ 20480, 11], [ 28672, 11],
+    ],
+    /* rest is 0s (uses up to index 11) */
+    [[0, 0]; 16],
+    [[0, 0]; 16],
+    [[0, 0]; 16],
+    [[0, 0]; 16],
+];
+
+pub fn decode_ptadpcm(
+    stream: &mut VGMStreamChannel,
+    outbuf: &mut [i16],
+    channelspacing: i32,
+    first_sample: i32,
+    samples_to_do: i32,
+    frame_size: i32,
+) {
+    let mut frame = [0u8; 0x104];
+    let mut sample_count = 0;
+    let mut samples_done = 0;
+
+    /* external interleave (variable size), mono */
+    let bytes_per_frame = frame_size as usize;
+    if bytes_per_frame < 0x06 || bytes_per_frame > frame.len() {
+        return;
+    }
+    let samples_per_frame = 2 + (frame_size - 0x05) * 2;
+    let frames_in = first_sample / samples_per_frame;
+    let first_sample = first_sample % samples_per_frame;
+
+    /* parse frame header */
+    let frame_offset = stream.offset as usize + bytes_per_frame * frames_in as usize;
+    if let Some(sf) = stream.streamfile.as_mut() {
+        read_streamfile(sf, frame_offset, &mut frame[..bytes_per_frame]); /* ignore EOF errors */
+    }
+    let mut hist2 = get_s16le(&frame[0x00..]) as i32;
+    let mut hist1 = get_s16le(&frame[0x02..]) as i32;
+    let mut index = frame[0x04] as usize;
+
+    if index > 12 {
+        vgm_log!("PTADPCM: incorrect index at {:x}", frame_offset);
+        index = 15; /* silence rather than garbage */
+    }
+
+    /* write header samples (needed) */
+    for hist in [hist2, hist1] {
+        if sample_count >= first_sample && samples_done < samples_to_do {
+            outbuf[(samples_done * channelspacing) as usize] = hist as i16;
+            samples_done += 1;
+        }
+        sample_count += 1;
+    }
+
+    /* decode nibbles */
+    for i in 0..(samples_per_frame - 2) as usize {
+        let nibbles = frame[0x05 + i / 2];
+        let nibble = if i & 1 == 0 { /* low nibble first */
+            (nibbles >> 0) & 0xF
+        } else {
+            (nibbles >> 4) & 0xF
+        } as usize;
+
+        let step = PTADPCM_TABLE[index][nibble][0];
+        index = PTADPCM_TABLE[index][nibble][1] as usize;
+        let sample = clamp16(step + 2 * hist1 - hist2);
+
+        if sample_count >= first_sample && samples_done < samples_to_do {
+            outbuf[(samples_done * channelspacing) as usize] = sample as i16;
+            samples_done += 1;
+        }
+        sample_count += 1;
+
+        hist2 = hist1;
+        hist1 = sample;
+    }
+}
+
+pub fn ptadpcm_bytes_to_samples(bytes: isize, channels: i32, frame_size: isize) -> i32 {
+    if channels <= 0 || frame_size < 0x06 {
+        return 0;
+    }
+    return ((bytes / channels as isize / frame_size) * (2 + (frame_size - 0x05) * 2)) as i32;
+}
@@ -1,10 +1,13 @@
-use std::fmt::Debug;
+use crate::vgm_log;
 
 use aotuv_lancer_vorbis_sys::*;
 use ogg_next_sys::*;
 
 use super::coding::{VorbisCustomConfig, VorbisCustomType};
-use crate::{streamfile::Streamfile, vgmstream::VGMStreamCodecData};
+use super::vorbis_custom_utils_wwise::{vorbis_custom_parse_packet_wwise, vorbis_custom_setup_init_wwise};
+use crate::{streamfile::Streamfile, vgmstream::{VGMStream, VGMStreamCodecData}};
+use crate::util::util::clamp16;
+use crate::error::{Result, VgmstreamError};
 
 pub const VORBIS_DEFAULT_BUFFER_SIZE: isize = 0x8000; /* should be at least the size of the setup header, ~0x2000 */
 
@@ -92,8 +95,9 @@ pub fn init_vorbis_custom(
     start_offset: usize,
     vtype: VorbisCustomType,
     config: &mut VorbisCustomConfig,
-) -> Option<VGMStreamCodecData> {
-    let mut data: VorbisCustomCodecData = Default::default();
+) -> Result<VGMStreamCodecData> {
+    /* boxed since libvorbis states point to each other (vd to vi, vb to vd) and can't move after init */
+    let mut data: Box<VorbisCustomCodecData> = Box::default();
     // int ok;
 
     /* init stuff */
@@ -118,7 +122,7 @@ pub fn init_vorbis_custom(
     data.op.packet = data.buffer.as_mut_ptr();
     data.op.b_o_s = 1; /* fake headers start */
 
-    let mut ok = false;
+    let ok;
 
     /* init header */
     match data.vtype {
@@ -142,13 +146,11 @@ pub fn init_vorbis_custom(
         // }
         _ => {
             // println!("VORBIS: init fail at around 0x{:x}", start_offset);
-            println!("VORBIS: type {} is stubbed", data.vtype);
-            return None;
+            return Err(VgmstreamError::UnsupportedCodec { meta: "VORBIS", codec: data.vtype.to_string() });
         }
     }
     if !ok {
-        println!("VORBIS: init fail at around 0x{:x}", start_offset);
-        return None;
+        return Err(VgmstreamError::DecoderFailure { codec: "VORBIS", offset: start_offset, reason: "bad setup headers".to_string() });
     }
 
     data.op.b_o_s = 0; /* end of fake headers */
@@ -156,12 +158,10 @@ pub fn init_vorbis_custom(
     /* init vorbis global and block state */
     unsafe {
         if vorbis_synthesis_init(&mut data.vd, &mut data.vi) != 0 {
-            println!("VORBIS: init fail at around 0x{:x}", start_offset);
-            return None;
+            return Err(VgmstreamError::DecoderFailure { codec: "VORBIS", offset: start_offset, reason: "synthesis init failed".to_string() });
         }
         if vorbis_block_init(&mut data.vd, &mut data.vb) != 0 {
-            println!("VORBIS: init fail at around 0x{:x}", start_offset);
-            return None;
+            return Err(VgmstreamError::DecoderFailure { codec: "VORBIS", offset: start_offset, reason: "block init failed".to_string() });
         }
     }
 
@@ -169,181 +169,186 @@ pub fn init_vorbis_custom(
     config.data_start_offset = data.config.data_start_offset;
 
     if data.config.stream_end == 0 {
-        data.config.stream_end = sf.get_size(unsafe { std::mem::transmute(&data) }) as u32;
+        data.config.stream_end = sf.get_size(std::ptr::null_mut()) as u32;
     }
 
-    return Some(VGMStreamCodecData::CustomVorbis(data));
-
-    // VGM_LOG("VORBIS: init fail at around 0x%x\n", (uint32_t)start_offset);
-    // free_vorbis_custom(data);
-    // return NULL;
-}
-
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
-pub struct WPacketType {
-    pub header_size: usize,
-    pub packet_size: i32,
-    pub granulepos: i32,
-
-    pub has_next: i32,
-    pub inxt: [u8; 0x01],
+    return Ok(VGMStreamCodecData::CustomVorbis(data));
 }
 
-use super::coding::WwiseSetupType;
+/* Decodes Vorbis packets into a libvorbis sample buffer, and copies them to outbuf */
+pub fn decode_vorbis_custom(vgmstream: &mut VGMStream, outbuf: &mut [i16], samples_to_do: i32, channels: i32) {
+    let stream = &mut vgmstream.ch[0];
+    let data = match vgmstream.codec_data.as_mut() {
+        Some(VGMStreamCodecData::CustomVorbis(data)) => data,
+        _ => return,
+    };
+    let stream_end = data.config.stream_end as isize;
+    let mut samples_done: i32 = 0;
+
+    while samples_done < samples_to_do {
+        /* extra EOF check for edge cases */
+        if stream.offset >= stream_end && !data.samples_full {
+            outbuf[(samples_done * channels) as usize..(samples_to_do * channels) as usize].fill(0);
+            return;
+        }
 
-pub fn vorbis_custom_setup_init_wwise(
-    sf: &mut Streamfile,
-    start_offset: usize,
-    data: &mut VorbisCustomCodecData,
-) -> bool {
-    let mut wp: WPacketType = Default::default();
-    let mut ok = false;
+        if data.samples_full {
+            /* read more samples */
+            let mut pcm: *mut *mut f32 = std::ptr::null_mut();
 
-    if data.config.setup_type == WwiseSetupType::WWV_HEADER_TRIAD {
-        /* read 3 Wwise packets with triad (id/comment/setup), each with a Wwise header */
-        let mut offset = start_offset;
+            /* get PCM samples from libvorbis buffers */
+            let mut samples_to_get = unsafe { vorbis_synthesis_pcmout(&mut data.vd, &mut pcm) };
+            if samples_to_get == 0 {
+                data.samples_full = false; /* request more if empty */
+                continue;
+            }
 
-        let mut ibuf = &mut data.buffer.clone();
+            if data.samples_to_discard > 0 {
+                /* discard samples for looping */
+                if samples_to_get as usize > data.samples_to_discard {
+                    samples_to_get = data.samples_to_discard as i32;
+                }
+                data.samples_to_discard -= samples_to_get as usize;
+            } else {
+                /* get max samples and convert from Vorbis float pcm to 16bit pcm */
+                if samples_to_get > samples_to_do - samples_done {
+                    samples_to_get = samples_to_do - samples_done;
+                }
+                pcm_convert_float_to_16(
+                    data.config.channels,
+                    &mut outbuf[(samples_done * channels) as usize..],
+                    channels,
+                    samples_to_get,
+                    pcm,
+                );
+                samples_done += samples_to_get;
+            }
 
-        /* normal identificacion packet */
-        ok = read_packet(&mut wp, ibuf, data.buffer_size, sf, offset, data, true);
-        if !ok {
-            return false;
-        }
-        data.op.bytes = wp.packet_size;
-        unsafe {
-            if vorbis_synthesis_headerin(&mut data.vi, &mut data.vc, &mut data.op) != 0 {
-                return false;
+            /* mark consumed samples from the buffer
+             * (non-consumed samples are returned in next vorbis_synthesis_pcmout calls) */
+            unsafe {
+                vorbis_synthesis_read(&mut data.vd, samples_to_get);
+            }
+        } else {
+            /* read more data */
+
+            /* not actually needed, but feels nicer */
+            data.op.granulepos += samples_to_do as i64; /* can be changed next if desired */
+            data.op.packetno += 1;
+
+            /* read/transform data into the ogg_packet buffer and advance offsets */
+            let ok = match data.vtype {
+                VorbisCustomType::VORBIS_WWISE => vorbis_custom_parse_packet_wwise(stream, data),
+                _ => false,
+            };
+            if !ok {
+                vgm_log!("VORBIS: decode fail at {:x}, missing {} samples", stream.offset, samples_to_do - samples_done);
+                outbuf[(samples_done * channels) as usize..(samples_to_do * channels) as usize].fill(0);
+                return;
             }
-        }
-        offset += wp.header_size + wp.packet_size as usize;
 
-        /* normal comment packet */
-        ok = read_packet(&mut wp, ibuf, data.buffer_size, sf, offset, data, true);
-        if !ok {
-            return false;
-        }
-        data.op.bytes = wp.packet_size;
-        unsafe {
-            if vorbis_synthesis_headerin(&mut data.vi, &mut data.vc, &mut data.op) != 0 {
-                return false;
+            /* parse the fake ogg packet into a logical vorbis block */
+            let rc = unsafe { vorbis_synthesis(&mut data.vb, &mut data.op) };
+            if rc == OV_ENOTAUDIO {
+                vgm_log!("VORBIS: not an audio packet (size=0x{:x}) @ {:x}", data.op.bytes, stream.offset);
+                continue; /* rarely happens, seems ok? */
+            } else if rc != 0 {
+                vgm_log!("VORBIS: decode fail at {:x}, missing {} samples", stream.offset, samples_to_do - samples_done);
+                outbuf[(samples_done * channels) as usize..(samples_to_do * channels) as usize].fill(0);
+                return;
             }
-        }
-        offset += wp.header_size + wp.packet_size as usize;
 
-        /* normal setup packet */
-        ok = read_packet(&mut wp, ibuf, data.buffer_size, sf, offset, data, true);
-        if !ok {
-            return false;
-        }
-        data.op.bytes = wp.packet_size;
-        unsafe {
-            if vorbis_synthesis_headerin(&mut data.vi, &mut data.vc, &mut data.op) != 0 {
-                return false;
+            /* finally decode the logical block into samples */
+            let rc = unsafe { vorbis_synthesis_blockin(&mut data.vd, &mut data.vb) };
+            if rc != 0 {
+                vgm_log!("VORBIS: decode fail at {:x}, missing {} samples", stream.offset, samples_to_do - samples_done);
+                outbuf[(samples_done * channels) as usize..(samples_to_do * channels) as usize].fill(0);
+                return;
             }
+
+            data.samples_full = true;
         }
-        offset += wp.header_size + wp.packet_size as usize;
     }
+}
 
-    return false;
+/* converts from internal Vorbis format to standard PCM (mostly from Xiph's decoder_example.c) */
+fn pcm_convert_float_to_16(vorbis_channels: i32, outbuf: &mut [i16], channels: i32, samples_to_do: i32, pcm: *mut *mut f32) {
+    /* mono/stereo/etc Vorbis are interleaved the same, except channel mapping for >2ch (handled elsewhere) */
+    for ch in 0..vorbis_channels.min(channels) as usize {
+        let channel_in = unsafe { std::slice::from_raw_parts(*pcm.add(ch), samples_to_do as usize) };
+        for (i, sample) in channel_in.iter().enumerate() {
+            let val = (*sample * 32767.0 + 0.5).floor() as i32;
+            outbuf[i * channels as usize + ch] = clamp16(val) as i16;
+        }
+    }
 }
 
-fn read_packet(
-    wp: &mut WPacketType,
-    ibuf: &mut Vec<u8>,
-    ibufsize: isize,
-    sf: &mut Streamfile,
-    offset: usize,
-    data: &mut VorbisCustomCodecData,
-    is_setup: bool,
-) -> bool {
-    // uint32_t (*get_u32)(const uint8_t*) = data.config.big_endian ? get_u32be : get_u32le;
-    // uint16_t (*get_u16)(const uint8_t*) = data.config.big_endian ? get_u16be : get_u16le;
-    // int32_t  (*get_s32)(const uint8_t*) = data.config.big_endian ? get_s32be : get_s32le;
-
-    use super::coding::{WwiseHeaderType, WwisePacketType};
-    use crate::util::reader::{get_s32be, get_s32le, get_u16be, get_u16le, get_u32be, get_u32le};
-
-    let get_u32 = if data.config.big_endian {
-        get_u32be
-    } else {
-        get_u32le
-    };
-    let get_u16 = if data.config.big_endian {
-        get_u16be
-    } else {
-        get_u16le
-    };
-    let get_s32 = if data.config.big_endian {
-        get_s32be
-    } else {
-        get_s32le
-    };
+/* Vorbis identification packet, for formats that don't store it */
+pub fn build_header_identification(buf: &mut [u8], cfg: &VorbisCustomConfig) -> usize {
+    let bytes = 0x1e;
 
-    /* read header info (packet size doesn't include header size) */
-    match data.config.header_type {
-        WwiseHeaderType::WWV_TYPE_8 => {
-            wp.header_size = 0x08;
-            // read_streamfile(ibuf, offset, wp.header_size, sf);
-            *ibuf = sf.read(offset, wp.header_size, data as *mut _ as *mut _);
-            wp.packet_size = get_u32(&ibuf[0x00..]) as i32;
-            wp.granulepos = get_s32(&ibuf[0x04..]);
-        }
-        WwiseHeaderType::WWV_TYPE_6 => {
-            wp.header_size = 0x06;
-            // read_streamfile(ibuf, offset, wp.header_size, sf);
-            *ibuf = sf.read(offset, wp.header_size, data as *mut _ as *mut _);
-            wp.packet_size = get_u16(&ibuf[0x00..]) as i32;
-            wp.granulepos = get_s32(&ibuf[0x02..]);
-        }
-        WwiseHeaderType::WWV_TYPE_2 => {
-            wp.header_size = 0x02;
-            // read_streamfile(ibuf, offset, wp.header_size, sf);
-            *ibuf = sf.read(offset, wp.header_size, data as *mut _ as *mut _);
-            wp.packet_size = get_u16(&ibuf[0x00..]) as i32;
-            wp.granulepos = 0; /* granule is an arbitrary unit so we could use offset instead; libvorbis has no need for it */
-        }
-        _ => {
-            /* ? */
-            wp.header_size = 0;
-            wp.packet_size = 0;
-            wp.granulepos = 0;
-        }
+    if bytes > buf.len() {
+        return 0;
     }
 
-    if wp.header_size == 0 || wp.packet_size == 0 {
-        return false;
+    /* blocksize_1_exp is the small one (see metas) */
+    let blocksizes = ((cfg.blocksize_0_exp << 4) | cfg.blocksize_1_exp) as u8;
+
+    buf[0x00] = 0x01;                                               /* packet_type (id) */
+    buf[0x01..0x07].copy_from_slice(b"vorbis");                     /* id */
+    buf[0x07..0x0b].copy_from_slice(&0u32.to_le_bytes());           /* vorbis_version (fixed) */
+    buf[0x0b] = cfg.channels as u8;                                 /* audio_channels */
+    buf[0x0c..0x10].copy_from_slice(&(cfg.sample_rate as u32).to_le_bytes()); /* audio_sample_rate */
+    buf[0x10..0x14].copy_from_slice(&0u32.to_le_bytes());           /* bitrate_maximum (optional hint) */
+    buf[0x14..0x18].copy_from_slice(&0u32.to_le_bytes());           /* bitrate_nominal (optional hint) */
+    buf[0x18..0x1c].copy_from_slice(&0u32.to_le_bytes());           /* bitrate_minimum (optional hint) */
+    buf[0x1c] = blocksizes;                                         /* blocksize_0 + blocksize_1 nibbles */
+    buf[0x1d] = 0x01;                                               /* framing_flag (fixed) */
+
+    return bytes;
+}
+
+/* Vorbis comment packet, for formats that don't store it */
+pub fn build_header_comment(buf: &mut [u8]) -> usize {
+    let bytes = 0x19;
+
+    if bytes > buf.len() {
+        return 0;
     }
 
-    /* read packet data */
-    {
-        let mut read_size = wp.packet_size;
+    buf[0x00] = 0x03;                                               /* packet_type (comments) */
+    buf[0x01..0x07].copy_from_slice(b"vorbis");                     /* id */
+    buf[0x07..0x0b].copy_from_slice(&0x09u32.to_le_bytes());        /* vendor_length */
+    buf[0x0b..0x14].copy_from_slice(b"vgmstream");                  /* vendor_string */
+    buf[0x14..0x18].copy_from_slice(&0u32.to_le_bytes());           /* user_comment_list_length */
+    buf[0x18] = 0x01;                                               /* framing_flag (fixed) */
 
-        /* mod packets need next packet's first byte (6 bits) except at EOF, so read now too */
-        if !is_setup && data.config.packet_type == WwisePacketType::WWV_MODIFIED {
-            read_size += wp.header_size as i32 + 0x01;
-        }
+    return bytes;
+}
 
-        if wp.header_size == 0 || read_size > ibufsize as i32 {
-            return false;
-        }
+pub fn reset_vorbis_custom(data: &mut VorbisCustomCodecData) {
+    unsafe {
+        vorbis_synthesis_restart(&mut data.vd);
+    }
+    data.samples_to_discard = 0;
+}
 
-        *ibuf = sf.read(offset + wp.header_size, read_size as usize, data as *mut _ as *mut _);
-        if ibuf.len() < wp.packet_size as usize {
-            println!("Wwise Vorbis: truncated packet");
-            return false;
-        }
+pub fn seek_vorbis_custom(data: &mut VorbisCustomCodecData, num_sample: i32) {
+    /* Seeking is provided by the Ogg layer, so with custom vorbis we'd need seek tables instead.
+     * To avoid that we can just decode samples from the beginning (channel offsets must be reset too). */
+    reset_vorbis_custom(data);
+    data.samples_to_discard = num_sample as usize;
+}
 
-        if !is_setup
-            && data.config.packet_type == WwisePacketType::WWV_MODIFIED
-            && ibuf.len() == read_size as usize
-        {
-            wp.has_next = 1;
-            wp.inxt[0] = ibuf[(wp.packet_size + wp.header_size as i32) as usize];
-        } else {
-            wp.has_next = 0;
+impl Drop for VorbisCustomCodecData {
+    fn drop(&mut self) {
+        /* states are zeroed until init, which libvorbis handles fine */
+        unsafe {
+            vorbis_block_clear(&mut self.vb);
+            vorbis_dsp_clear(&mut self.vd);
+            vorbis_comment_clear(&mut self.vc);
+            vorbis_info_clear(&mut self.vi);
         }
     }
-
-    return true;
 }
+
@@ -0,0 +1,74 @@
+/* Simple bitreader/bitwriter for Vorbis, which packs values in LSB order byte by byte
+ * (a value's first bit goes to the lowest free bit of the current byte).
+ * Reads past the end return 0s and writes past the end are dropped, so callers can check
+ * the final bit offset once rather than every operation. */
+
+pub struct BitReader<'a> {
+    buf: &'a [u8],
+    pub b_off: usize, /* current offset in bits inside buffer */
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        BitReader { buf, b_off: 0 }
+    }
+
+    /* Read bits (max 32) and update the bit offset */
+    pub fn read(&mut self, bits: u32) -> u32 {
+        let mut value: u32 = 0;
+
+        for i in 0..bits {
+            let pos = self.b_off / 8;
+            if pos >= self.buf.len() {
+                break;
+            }
+            let bit = (self.buf[pos] >> (self.b_off % 8)) & 1;
+            value |= (bit as u32) << i;
+            self.b_off += 1;
+        }
+
+        return value;
+    }
+}
+
+pub struct BitWriter<'a> {
+    buf: &'a mut [u8],
+    pub b_off: usize, /* current offset in bits inside buffer */
+}
+
+impl<'a> BitWriter<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        BitWriter { buf, b_off: 0 }
+    }
+
+    /* Write bits (max 32) and update the bit offset */
+    pub fn write(&mut self, bits: u32, value: u32) {
+        for i in 0..bits {
+            let pos = self.b_off / 8;
+            if pos >= self.buf.len() {
+                return;
+            }
+            let mask = 1u8 << (self.b_off % 8);
+            if (value >> i) & 1 != 0 {
+                self.buf[pos] |= mask;
+            } else {
+                self.buf[pos] &= !mask;
+            }
+            self.b_off += 1;
+        }
+    }
+
+    /* Writes whole bytes (must be byte aligned) */
+    pub fn put_bytes(&mut self, bytes: &[u8]) {
+        let pos = self.b_off / 8;
+        if pos + bytes.len() > self.buf.len() {
+            return;
+        }
+        self.buf[pos..pos + bytes.len()].copy_from_slice(bytes);
+        self.b_off += bytes.len() * 8;
+    }
+
+    pub fn capacity_bits(&self) -> usize {
+        return self.buf.len() * 8;
+    }
+}
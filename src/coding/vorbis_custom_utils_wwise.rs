@@ -0,0 +1,956 @@
+use crate::vgm_log;
+use aotuv_lancer_vorbis_sys::*;
+
+use super::coding::{VorbisCustomConfig, WwiseHeaderType, WwisePacketType, WwiseSetupType};
+use super::vorbis::{build_header_comment, build_header_identification, VorbisCustomCodecData};
+use super::vorbis_bitreader::{BitReader, BitWriter};
+use crate::streamfile::{open_streamfile_by_ext, open_streamfile_by_filename, read_streamfile};
+use crate::util::reader::{get_s32be, get_s32le, get_u16be, get_u16le, get_u32be, get_u32le};
+use crate::vgmstream::{Streamfile, VGMStreamChannel};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct WPacketType {
+    pub header_size: usize,
+    pub packet_size: i32,
+    pub granulepos: i32,
+
+    pub has_next: i32,
+    pub inxt: [u8; 0x01],
+}
+
+/* **************************************************************************** */
+/* EXTERNAL API                                                                 */
+/* **************************************************************************** */
+
+/**
+ * Wwise stores a reduced setup, and packets have mini headers with the size, and data packets
+ * may reduced as well. The format evolved over time so there are many variations.
+ * The Wwise implementation uses Tremor (fixed-point Vorbis) but shouldn't matter.
+ *
+ * Format reverse-engineered by hcs in ww2ogg (https://github.com/hcs64/ww2ogg).
+ */
+pub fn vorbis_custom_setup_init_wwise(
+    sf: &mut Streamfile,
+    start_offset: usize,
+    data: &mut VorbisCustomCodecData,
+) -> bool {
+    let mut wp: WPacketType = Default::default();
+
+    if data.config.setup_type == WwiseSetupType::WWV_HEADER_TRIAD {
+        /* read 3 Wwise packets with triad (id/comment/setup), each with a Wwise header */
+        let mut offset = start_offset;
+
+        /* normal identificacion packet, comment packet and setup packet */
+        for _ in 0..3 {
+            if !read_packet(&mut wp, &mut data.buffer, sf, offset, &data.config, true) {
+                return false;
+            }
+            data.op.packet = data.buffer.as_mut_ptr();
+            data.op.bytes = wp.packet_size as _;
+            unsafe {
+                if vorbis_synthesis_headerin(&mut data.vi, &mut data.vc, &mut data.op) != 0 {
+                    return false;
+                }
+            }
+            offset += wp.header_size + wp.packet_size as usize;
+        }
+    } else {
+        /* rebuild headers */
+
+        /* new identificacion packet */
+        let bytes = build_header_identification(&mut data.buffer, &data.config);
+        if bytes == 0 {
+            return false;
+        }
+        data.op.bytes = bytes as _;
+        unsafe {
+            if vorbis_synthesis_headerin(&mut data.vi, &mut data.vc, &mut data.op) != 0 {
+                /* parse identification header */
+                return false;
+            }
+        }
+
+        /* new comment packet */
+        let bytes = build_header_comment(&mut data.buffer);
+        if bytes == 0 {
+            return false;
+        }
+        data.op.bytes = bytes as _;
+        unsafe {
+            if vorbis_synthesis_headerin(&mut data.vi, &mut data.vc, &mut data.op) != 0 {
+                /* parse comment header */
+                return false;
+            }
+        }
+
+        /* rebuild setup packet */
+        let mut obuf = std::mem::take(&mut data.buffer);
+        let bytes = rebuild_setup(&mut obuf, &mut wp, sf, start_offset, data);
+        data.buffer = obuf;
+        data.op.packet = data.buffer.as_mut_ptr();
+        if bytes == 0 {
+            return false;
+        }
+        data.op.bytes = bytes as _;
+        unsafe {
+            if vorbis_synthesis_headerin(&mut data.vi, &mut data.vc, &mut data.op) != 0 {
+                /* parse setup header */
+                return false;
+            }
+        }
+    }
+
+    return true;
+}
+
+pub fn vorbis_custom_parse_packet_wwise(stream: &mut VGMStreamChannel, data: &mut VorbisCustomCodecData) -> bool {
+    let mut wp: WPacketType = Default::default();
+    let Some(sf) = stream.streamfile.as_mut() else {
+        return false;
+    };
+
+    /* reconstruct a Wwise packet, if needed; final bytes may be bigger than packet_size */
+    let mut obuf = std::mem::take(&mut data.buffer);
+    let bytes = rebuild_packet(&mut obuf, &mut wp, sf, stream.offset as usize, data);
+    data.buffer = obuf;
+    data.op.packet = data.buffer.as_mut_ptr();
+
+    stream.offset += (wp.header_size + wp.packet_size as usize) as isize;
+    if bytes == 0 || bytes >= 0xFFFF {
+        return false;
+    }
+
+    data.op.bytes = bytes as _;
+    data.op.granulepos = wp.granulepos as i64; /* seems ok even with modified packets, whatever */
+
+    return true;
+}
+
+/* **************************************************************************** */
+/* INTERNAL HELPERS                                                             */
+/* **************************************************************************** */
+
+/* loads info from a Wwise packet header */
+fn read_packet(
+    wp: &mut WPacketType,
+    ibuf: &mut [u8],
+    sf: &mut Streamfile,
+    offset: usize,
+    config: &VorbisCustomConfig,
+    is_setup: bool,
+) -> bool {
+    let get_u32 = if config.big_endian { get_u32be } else { get_u32le };
+    let get_u16 = if config.big_endian { get_u16be } else { get_u16le };
+    let get_s32 = if config.big_endian { get_s32be } else { get_s32le };
+
+    /* read header info (packet size doesn't include header size) */
+    match config.header_type {
+        WwiseHeaderType::WWV_TYPE_8 => {
+            wp.header_size = 0x08;
+            read_streamfile(sf, offset, &mut ibuf[..wp.header_size]);
+            wp.packet_size = get_u32(&ibuf[0x00..]) as i32;
+            wp.granulepos = get_s32(&ibuf[0x04..]);
+        }
+        WwiseHeaderType::WWV_TYPE_6 => {
+            wp.header_size = 0x06;
+            read_streamfile(sf, offset, &mut ibuf[..wp.header_size]);
+            wp.packet_size = get_u16(&ibuf[0x00..]) as i32;
+            wp.granulepos = get_s32(&ibuf[0x02..]);
+        }
+        WwiseHeaderType::WWV_TYPE_2 => {
+            wp.header_size = 0x02;
+            read_streamfile(sf, offset, &mut ibuf[..wp.header_size]);
+            wp.packet_size = get_u16(&ibuf[0x00..]) as i32;
+            wp.granulepos = 0; /* granule is an arbitrary unit so we could use offset instead; libvorbis has no need for it */
+        }
+    }
+
+    if wp.header_size == 0 || wp.packet_size <= 0 {
+        return false;
+    }
+
+    /* read packet data */
+    let mut read_size = wp.packet_size as usize;
+
+    /* mod packets need next packet's first byte (6 bits) except at EOF, so read now too */
+    if !is_setup && config.packet_type == WwisePacketType::WWV_MODIFIED {
+        read_size += wp.header_size + 0x01;
+    }
+
+    if read_size > ibuf.len() {
+        return false;
+    }
+
+    let bytes = read_streamfile(sf, offset + wp.header_size, &mut ibuf[..read_size]);
+    if bytes < wp.packet_size as usize {
+        vgm_log!("Wwise Vorbis: truncated packet");
+        return false;
+    }
+
+    if !is_setup && config.packet_type == WwisePacketType::WWV_MODIFIED && bytes == read_size {
+        wp.has_next = 1;
+        wp.inxt[0] = ibuf[wp.packet_size as usize + wp.header_size];
+    } else {
+        wp.has_next = 0;
+    }
+
+    return true;
+}
+
+/* Wwise's external codebooks aren't bundled here (each set is a ~10kb binary blob), so they are
+ * loaded from ww2ogg's packed codebook file next to the stream, or a same-named .wvc in that format */
+fn load_wvc_library(setup_type: WwiseSetupType, sf: &Streamfile) -> Option<Vec<u8>> {
+    let filename = match setup_type {
+        WwiseSetupType::WWV_AOTUV603_CODEBOOKS => "packed_codebooks_aoTuV_603.bin",
+        _ => "packed_codebooks.bin",
+    };
+
+    let candidates = [open_streamfile_by_filename(sf, filename), open_streamfile_by_ext(sf, "wvc")];
+    for sf_setup in candidates {
+        let Ok(mut sf_setup) = sf_setup else {
+            continue;
+        };
+
+        let mut library = vec![0; sf_setup.get_size(std::ptr::null_mut())];
+        if !library.is_empty() && read_streamfile(&mut sf_setup, 0x00, &mut library) == library.len() {
+            return Some(library);
+        }
+    }
+
+    vgm_log!("Wwise Vorbis: codebook library {} not found", filename);
+    return None;
+}
+
+/* finds a codebook by id in a loaded library */
+fn load_wvc(library: &[u8], codebook_id: u32) -> Option<&[u8]> {
+    let wvc_size = library.len();
+    if wvc_size < 0x04 {
+        return None;
+    }
+
+    /* at the end of the WVC is an offset table, and we need to find codebook id (number) offset */
+    let table_start = get_u32le(&library[wvc_size - 0x04..]) as usize; /* last offset */
+    if table_start > wvc_size - 0x04 {
+        return None;
+    }
+    let codebook_count = (wvc_size - table_start) / 4 - 1;
+    if codebook_id as usize >= codebook_count {
+        vgm_log!("Wwise Vorbis: codebook_id {:04x} not found", codebook_id);
+        return None;
+    }
+
+    let entry = table_start + codebook_id as usize * 4;
+    let codebook_offset = get_u32le(&library[entry..]) as usize;
+    let codebook_end = get_u32le(&library[entry + 4..]) as usize;
+    if codebook_offset > codebook_end || codebook_end > table_start {
+        return None;
+    }
+
+    return Some(&library[codebook_offset..codebook_end]);
+}
+
+/*******************************************************************************/
+/* BUILDERS                                                                    */
+/*******************************************************************************/
+
+/* Transforms a Wwise data packet into a real Vorbis one (depending on config) */
+fn rebuild_packet(
+    obuf: &mut [u8],
+    wp: &mut WPacketType,
+    sf: &mut Streamfile,
+    offset: usize,
+    data: &mut VorbisCustomCodecData,
+) -> usize {
+    let mut ibuf = [0u8; 0x8000]; /* arbitrary max */
+
+    if obuf.len() < ibuf.len() {
+        /* arbitrary expected min */
+        return 0;
+    }
+
+    if !read_packet(wp, &mut ibuf, sf, offset, &data.config, false) {
+        return 0;
+    }
+
+    let mut ow = BitWriter::new(obuf);
+    let mut iw = BitReader::new(&ibuf);
+
+    if !ww2ogg_generate_vorbis_packet(&mut ow, &mut iw, wp, data) {
+        return 0;
+    }
+
+    if ow.b_off % 8 != 0 || ow.b_off > ow.capacity_bits() {
+        vgm_log!("Wwise Vorbis: didn't write exactly audio packet: 0x{:x} + {} bits", ow.b_off / 8, ow.b_off % 8);
+        return 0;
+    }
+
+    return ow.b_off / 8;
+}
+
+/* Transforms a Wwise setup packet into a real Vorbis one (depending on config). */
+fn rebuild_setup(
+    obuf: &mut [u8],
+    wp: &mut WPacketType,
+    sf: &mut Streamfile,
+    offset: usize,
+    data: &mut VorbisCustomCodecData,
+) -> usize {
+    let mut ibuf = [0u8; 0x8000]; /* arbitrary max */
+
+    if obuf.len() < ibuf.len() {
+        /* arbitrary expected min */
+        return 0;
+    }
+
+    /* read Wwise packet header */
+    if !read_packet(wp, &mut ibuf, sf, offset, &data.config, true) {
+        return 0;
+    }
+
+    let mut ow = BitWriter::new(obuf);
+    let mut iw = BitReader::new(&ibuf);
+
+    if !ww2ogg_generate_vorbis_setup(&mut ow, &mut iw, data, wp.packet_size as usize, sf) {
+        return 0;
+    }
+
+    if ow.b_off % 8 != 0 || ow.b_off > ow.capacity_bits() {
+        vgm_log!("Wwise Vorbis: didn't write exactly setup packet: 0x{:x} + {} bits", ow.b_off / 8, ow.b_off % 8);
+        return 0;
+    }
+
+    return ow.b_off / 8;
+}
+
+/* **************************************************************************** */
+/* INTERNAL WW2OGG STUFF                                                        */
+/* **************************************************************************** */
+/* The following code was mostly and manually converted from hcs's ww2ogg.
+ * Could be simplified but roughly tries to preserve the structure in case fixes have to be backported.
+ *
+ * Some validations are ommited (ex. read/write), as incorrect data should be rejected by libvorbis.
+ * Reads/writes unsigned ints as most are bit values less than 32 and with no sign meaning.
+ */
+
+/* Copy packet as-is or rebuild first byte if mod_packets is used.
+ * (ref: https://www.xiph.org/vorbis/doc/Vorbis_I_spec.html#x1-720004.3) */
+fn ww2ogg_generate_vorbis_packet(
+    ow: &mut BitWriter,
+    iw: &mut BitReader,
+    wp: &WPacketType,
+    data: &mut VorbisCustomCodecData,
+) -> bool {
+    let packet_size = wp.packet_size as usize;
+
+    if data.config.packet_type == WwisePacketType::WWV_MODIFIED {
+        /* rebuild first bits of packet type and window info (for the i-MDCT) */
+        let mode_bits = data.mode_bits as u32;
+
+        /* audio packet type */
+        let packet_type = 0;
+        ow.write(1, packet_type);
+
+        /* collect this packet mode from the first byte */
+        let mode_number = iw.read(mode_bits); /* max 6b */
+        ow.write(mode_bits, mode_number);
+        let remainder = iw.read(8 - mode_bits);
+
+        /* adjust window info */
+        if data.mode_blockflag[mode_number as usize] != 0 {
+            /* long window: peek at next frame to find flags */
+            let next_blockflag = if wp.has_next != 0 {
+                /* get next first byte to read next_mode_number */
+                let mut nw = BitReader::new(&wp.inxt);
+                let next_mode_number = nw.read(mode_bits); /* max 6b */
+
+                data.mode_blockflag[next_mode_number as usize]
+            } else {
+                /* EOF (probably doesn't matter) */
+                0
+            };
+
+            let prev_window_type = data.prev_blockflag as u32;
+            ow.write(1, prev_window_type);
+
+            let next_window_type = next_blockflag as u32;
+            ow.write(1, next_window_type);
+        }
+
+        data.prev_blockflag = data.mode_blockflag[mode_number as usize]; /* save for next packet */
+
+        ow.write(8 - mode_bits, remainder); /* this *isn't* byte aligned (ex. could be 10 bits written) */
+
+        /* write the rest (always 8 bits) */
+        for _ in 1..packet_size {
+            let c = iw.read(8);
+            ow.write(8, c);
+        }
+    } else {
+        /* normal packets: direct copy */
+        for _ in 0..packet_size {
+            let c = iw.read(8);
+            ow.write(8, c);
+        }
+    }
+
+    /* remove trailing garbage bits */
+    if ow.b_off % 8 != 0 {
+        let padding_bits = 8 - (ow.b_off % 8) as u32;
+        ow.write(padding_bits, 0);
+    }
+
+    return true;
+}
+
+/* Rebuild a Wwise setup (simplified with removed stuff), recreating all six setup parts.
+ * (ref: https://www.xiph.org/vorbis/doc/Vorbis_I_spec.html#x1-650004.2.4) */
+fn ww2ogg_generate_vorbis_setup(
+    ow: &mut BitWriter,
+    iw: &mut BitReader,
+    data: &mut VorbisCustomCodecData,
+    packet_size: usize,
+    sf: &mut Streamfile,
+) -> bool {
+    let channels = data.config.channels as u32;
+
+    /* packet header */
+    ow.put_bytes(&[0x05]);    /* packet_type (setup) */
+    ow.put_bytes(b"vorbis");  /* id */
+
+    /* Codebooks */
+    let codebook_count_less1 = iw.read(8);
+    ow.write(8, codebook_count_less1);
+    let codebook_count = codebook_count_less1 + 1;
+
+    match data.config.setup_type {
+        WwiseSetupType::WWV_FULL_SETUP => {
+            /* rebuild Wwise codebooks: untouched */
+            for _ in 0..codebook_count {
+                if !ww2ogg_codebook_library_copy(ow, iw) {
+                    return false;
+                }
+            }
+        }
+        WwiseSetupType::WWV_INLINE_CODEBOOKS => {
+            /* rebuild Wwise codebooks: inline in simplified format */
+            for _ in 0..codebook_count {
+                if !ww2ogg_codebook_library_rebuild(ow, iw, 0) {
+                    return false;
+                }
+            }
+        }
+        _ => {
+            /* rebuild Wwise codebooks: external (referenced by id) in simplified format */
+            let Some(library) = load_wvc_library(data.config.setup_type, sf) else {
+                return false;
+            };
+
+            for _ in 0..codebook_count {
+                let codebook_id = iw.read(10);
+
+                if !ww2ogg_codebook_library_rebuild_by_id(ow, codebook_id, &library) {
+                    return false;
+                }
+            }
+        }
+    }
+
+    /* Time domain transforms */
+    let time_count_less1 = 0;
+    ow.write(6, time_count_less1);
+    let dummy_time_value = 0;
+    ow.write(16, dummy_time_value);
+
+    if data.config.setup_type == WwiseSetupType::WWV_FULL_SETUP {
+        /* rest of setup is untouched, copy bits */
+        let setup_packet_size_bits = packet_size * 8;
+
+        while iw.b_off < setup_packet_size_bits {
+            let bitly = iw.read(1);
+            ow.write(1, bitly);
+        }
+    } else {
+        /* rest of setup is altered, reconstruct */
+
+        /* Floors */
+        let floor_count_less1 = iw.read(6);
+        ow.write(6, floor_count_less1);
+        let floor_count = floor_count_less1 + 1;
+
+        for _ in 0..floor_count {
+            let mut floor1_partition_class_list = [0u32; 32]; /* max 5b */
+            let mut floor1_class_dimensions_list = [0u32; 16 + 1]; /* max 4b+1 */
+
+            /* Always floor type 1 */
+            let floor_type = 1;
+            ow.write(16, floor_type);
+
+            let floor1_partitions = iw.read(5);
+            ow.write(5, floor1_partitions);
+
+            let mut maximum_class = 0;
+            for j in 0..floor1_partitions as usize {
+                let floor1_partition_class = iw.read(4);
+                ow.write(4, floor1_partition_class);
+
+                floor1_partition_class_list[j] = floor1_partition_class;
+
+                if floor1_partition_class > maximum_class {
+                    maximum_class = floor1_partition_class;
+                }
+            }
+
+            for j in 0..=maximum_class as usize {
+                let class_dimensions_less1 = iw.read(3);
+                ow.write(3, class_dimensions_less1);
+
+                floor1_class_dimensions_list[j] = class_dimensions_less1 + 1;
+
+                let class_subclasses = iw.read(2);
+                ow.write(2, class_subclasses);
+
+                if class_subclasses != 0 {
+                    let masterbook = iw.read(8);
+                    ow.write(8, masterbook);
+
+                    if masterbook >= codebook_count {
+                        vgm_log!("Wwise Vorbis: invalid floor1 masterbook");
+                        return false;
+                    }
+                }
+
+                for _ in 0..(1u32 << class_subclasses) {
+                    let subclass_book_plus1 = iw.read(8);
+                    ow.write(8, subclass_book_plus1);
+
+                    let subclass_book = subclass_book_plus1 as i32 - 1; /* this MUST be signed */
+                    if subclass_book >= 0 && subclass_book as u32 >= codebook_count {
+                        vgm_log!("Wwise Vorbis: invalid floor1 subclass book");
+                        return false;
+                    }
+                }
+            }
+
+            let floor1_multiplier_less1 = iw.read(2);
+            ow.write(2, floor1_multiplier_less1);
+
+            let rangebits = iw.read(4);
+            ow.write(4, rangebits);
+
+            for j in 0..floor1_partitions as usize {
+                let current_class_number = floor1_partition_class_list[j] as usize;
+                for _ in 0..floor1_class_dimensions_list[current_class_number] {
+                    let x = iw.read(rangebits); /* max 4b (15) */
+                    ow.write(rangebits, x);
+                }
+            }
+        }
+
+        /* Residues */
+        let residue_count_less1 = iw.read(6);
+        ow.write(6, residue_count_less1);
+        let residue_count = residue_count_less1 + 1;
+
+        for _ in 0..residue_count {
+            let mut residue_cascade = [0u32; 64 + 1]; /* 6b +1 */
+
+            let residue_type = iw.read(2);
+            ow.write(16, residue_type); /* 2b to 16b */
+
+            if residue_type > 2 {
+                vgm_log!("Wwise Vorbis: invalid residue type");
+                return false;
+            }
+
+            let residue_begin = iw.read(24);
+            ow.write(24, residue_begin);
+            let residue_end = iw.read(24);
+            ow.write(24, residue_end);
+            let residue_partition_size_less1 = iw.read(24);
+            ow.write(24, residue_partition_size_less1);
+            let residue_classifications_less1 = iw.read(6);
+            ow.write(6, residue_classifications_less1);
+            let residue_classbook = iw.read(8);
+            ow.write(8, residue_classbook);
+            let residue_classifications = residue_classifications_less1 + 1;
+
+            if residue_classbook >= codebook_count {
+                vgm_log!("Wwise Vorbis: invalid residue classbook");
+                return false;
+            }
+
+            for j in 0..residue_classifications as usize {
+                let mut high_bits = 0;
+
+                let low_bits = iw.read(3);
+                ow.write(3, low_bits);
+
+                let bitflag = iw.read(1);
+                ow.write(1, bitflag);
+                if bitflag != 0 {
+                    high_bits = iw.read(5);
+                    ow.write(5, high_bits);
+                }
+
+                residue_cascade[j] = high_bits * 8 + low_bits;
+            }
+
+            for j in 0..residue_classifications as usize {
+                for k in 0..8 {
+                    if residue_cascade[j] & (1 << k) != 0 {
+                        let residue_book = iw.read(8);
+                        ow.write(8, residue_book);
+
+                        if residue_book >= codebook_count {
+                            vgm_log!("Wwise Vorbis: invalid residue book");
+                            return false;
+                        }
+                    }
+                }
+            }
+        }
+
+        /* Mappings */
+        let mapping_count_less1 = iw.read(6);
+        ow.write(6, mapping_count_less1);
+        let mapping_count = mapping_count_less1 + 1;
+
+        for _ in 0..mapping_count {
+            /* always mapping type 0, the only one */
+            let mapping_type = 0;
+            ow.write(16, mapping_type);
+
+            let submaps_flag = iw.read(1);
+            ow.write(1, submaps_flag);
+
+            let mut submaps = 1;
+            if submaps_flag != 0 {
+                let submaps_less1 = iw.read(4);
+                ow.write(4, submaps_less1);
+                submaps = submaps_less1 + 1;
+            }
+
+            let square_polar_flag = iw.read(1);
+            ow.write(1, square_polar_flag);
+
+            if square_polar_flag != 0 {
+                let coupling_steps_less1 = iw.read(8);
+                ow.write(8, coupling_steps_less1);
+                let coupling_steps = coupling_steps_less1 + 1;
+
+                for _ in 0..coupling_steps {
+                    let magnitude_bits = ww2ogg_tremor_ilog(channels - 1);
+                    let angle_bits = ww2ogg_tremor_ilog(channels - 1);
+
+                    let magnitude = iw.read(magnitude_bits);
+                    ow.write(magnitude_bits, magnitude);
+                    let angle = iw.read(angle_bits);
+                    ow.write(angle_bits, angle);
+
+                    if angle == magnitude || magnitude >= channels || angle >= channels {
+                        vgm_log!("Wwise Vorbis: invalid coupling (angle={}, mag={}, ch={})", angle, magnitude, channels);
+                        return false;
+                    }
+                }
+            }
+
+            /* a rare reserved field not removed by Ak! */
+            let mapping_reserved = iw.read(2);
+            ow.write(2, mapping_reserved);
+            if mapping_reserved != 0 {
+                vgm_log!("Wwise Vorbis: mapping reserved field nonzero");
+                return false;
+            }
+
+            if submaps > 1 {
+                for _ in 0..channels {
+                    let mapping_mux = iw.read(4);
+                    ow.write(4, mapping_mux);
+                    if mapping_mux >= submaps {
+                        vgm_log!("Wwise Vorbis: mapping_mux >= submaps");
+                        return false;
+                    }
+                }
+            }
+
+            for _ in 0..submaps {
+                /* Another! Unused time domain transform configuration placeholder! */
+                let time_config = iw.read(8);
+                ow.write(8, time_config);
+
+                let floor_number = iw.read(8);
+                ow.write(8, floor_number);
+                if floor_number >= floor_count {
+                    vgm_log!("Wwise Vorbis: invalid floor mapping");
+                    return false;
+                }
+
+                let residue_number = iw.read(8);
+                ow.write(8, residue_number);
+                if residue_number >= residue_count {
+                    vgm_log!("Wwise Vorbis: invalid residue mapping");
+                    return false;
+                }
+            }
+        }
+
+        /* Modes */
+        let mode_count_less1 = iw.read(6);
+        ow.write(6, mode_count_less1);
+        let mode_count = mode_count_less1 + 1;
+
+        data.mode_blockflag = [0; 64 + 1]; /* up to max mode_count */
+        data.mode_bits = ww2ogg_tremor_ilog(mode_count - 1) as i32; /* for mod_packets */
+
+        for i in 0..mode_count as usize {
+            let block_flag = iw.read(1);
+            ow.write(1, block_flag);
+
+            data.mode_blockflag[i] = (block_flag != 0) as u8; /* for mod_packets */
+
+            let windowtype = 0;
+            let transformtype = 0;
+            ow.write(16, windowtype);
+            ow.write(16, transformtype);
+
+            let mapping = iw.read(8);
+            ow.write(8, mapping);
+            if mapping >= mapping_count {
+                vgm_log!("Wwise Vorbis: invalid mode mapping");
+                return false;
+            }
+        }
+    }
+
+    /* framing flag */
+    let framing = 1;
+    ow.write(1, framing);
+
+    /* remove trailing garbage bits */
+    if ow.b_off % 8 != 0 {
+        let padding_bits = 8 - (ow.b_off % 8) as u32;
+        ow.write(padding_bits, 0);
+    }
+
+    return true;
+}
+
+/* copies Vorbis codebooks (untouched, but size uncertain) */
+fn ww2ogg_codebook_library_copy(ow: &mut BitWriter, iw: &mut BitReader) -> bool {
+    let id = iw.read(24);
+    ow.write(24, id);
+    let dimensions = iw.read(16);
+    ow.write(16, dimensions);
+    let entries = iw.read(24);
+    ow.write(24, entries);
+
+    if id != 0x564342 {
+        /* "VCB" */
+        vgm_log!("Wwise Vorbis: invalid codebook identifier");
+        return false;
+    }
+
+    /* codeword lengths */
+    let ordered = iw.read(1);
+    ow.write(1, ordered);
+    if ordered != 0 {
+        let initial_length = iw.read(5);
+        ow.write(5, initial_length);
+
+        let mut current_entry = 0;
+        while current_entry < entries {
+            let numberbits = ww2ogg_tremor_ilog(entries - current_entry);
+
+            let number = iw.read(numberbits);
+            ow.write(numberbits, number);
+            current_entry += number;
+        }
+        if current_entry > entries {
+            vgm_log!("Wwise Vorbis: current_entry out of range");
+            return false;
+        }
+    } else {
+        let sparse = iw.read(1);
+        ow.write(1, sparse);
+
+        for _ in 0..entries {
+            let mut present_bool = true;
+            if sparse != 0 {
+                let present = iw.read(1);
+                ow.write(1, present);
+
+                present_bool = present != 0;
+            }
+
+            if present_bool {
+                let codeword_length = iw.read(5);
+                ow.write(5, codeword_length);
+            }
+        }
+    }
+
+    /* lookup table */
+    let lookup_type = iw.read(4);
+    ow.write(4, lookup_type);
+
+    return ww2ogg_codebook_copy_lookup(ow, iw, lookup_type, entries, dimensions);
+}
+
+/* rebuilds a Wwise codebook into a Vorbis codebook */
+fn ww2ogg_codebook_library_rebuild(ow: &mut BitWriter, iw: &mut BitReader, cb_size: usize) -> bool {
+    let id = 0x564342; /* "VCB" */
+
+    ow.write(24, id);
+    let dimensions = iw.read(4);
+    ow.write(16, dimensions); /* 4 to 16 */
+    let entries = iw.read(14);
+    ow.write(24, entries); /* 14 to 24*/
+
+    /* codeword lengths */
+    let ordered = iw.read(1);
+    ow.write(1, ordered);
+    if ordered != 0 {
+        let initial_length = iw.read(5);
+        ow.write(5, initial_length);
+
+        let mut current_entry = 0;
+        while current_entry < entries {
+            let numberbits = ww2ogg_tremor_ilog(entries - current_entry);
+
+            let number = iw.read(numberbits);
+            ow.write(numberbits, number);
+            current_entry += number;
+        }
+        if current_entry > entries {
+            vgm_log!("Wwise Vorbis: current_entry out of range");
+            return false;
+        }
+    } else {
+        let codeword_length_length = iw.read(3);
+        let sparse = iw.read(1);
+        ow.write(1, sparse);
+
+        if codeword_length_length == 0 || codeword_length_length > 5 {
+            vgm_log!("Wwise Vorbis: nonsense codeword length");
+            return false;
+        }
+
+        for _ in 0..entries {
+            let mut present_bool = true;
+            if sparse != 0 {
+                let present = iw.read(1);
+                ow.write(1, present);
+
+                present_bool = present != 0;
+            }
+
+            if present_bool {
+                let codeword_length = iw.read(codeword_length_length);
+                ow.write(5, codeword_length); /* max 7 (3b) to 5 */
+            }
+        }
+    }
+
+    /* lookup table */
+    let lookup_type = iw.read(1);
+    ow.write(4, lookup_type); /* 1 to 4 */
+
+    if !ww2ogg_codebook_copy_lookup(ow, iw, lookup_type, entries, dimensions) {
+        return false;
+    }
+
+    /* check that we used exactly all bytes */
+    /* note: if all bits are used in the last byte there will be one extra 0 byte */
+    if cb_size != 0 && iw.b_off / 8 + 1 != cb_size {
+        vgm_log!("Wwise Vorbis: codebook size mistach (expected 0x{:x}, wrote 0x{:x})", cb_size, iw.b_off / 8 + 1);
+        return false;
+    }
+
+    return true;
+}
+
+/* lookup table is the same in both codebook formats */
+fn ww2ogg_codebook_copy_lookup(ow: &mut BitWriter, iw: &mut BitReader, lookup_type: u32, entries: u32, dimensions: u32) -> bool {
+    match lookup_type {
+        0 => {
+            /* no lookup table */
+        }
+        1 => {
+            let min = iw.read(32);
+            ow.write(32, min);
+            let max = iw.read(32);
+            ow.write(32, max);
+            let value_length = iw.read(4);
+            ow.write(4, value_length);
+            let sequence_flag = iw.read(1);
+            ow.write(1, sequence_flag);
+
+            let quantvals = ww2ogg_tremor_book_maptype1_quantvals(entries, dimensions);
+            for _ in 0..quantvals {
+                let val_bits = value_length + 1;
+
+                let val = iw.read(val_bits);
+                ow.write(val_bits, val);
+            }
+        }
+        2 => {
+            vgm_log!("Wwise Vorbis: didn't expect lookup type 2");
+            return false;
+        }
+        _ => {
+            vgm_log!("Wwise Vorbis: invalid lookup type");
+            return false;
+        }
+    }
+
+    return true;
+}
+
+/* rebuilds an external Wwise codebook referenced by id to a Vorbis codebook */
+fn ww2ogg_codebook_library_rebuild_by_id(ow: &mut BitWriter, codebook_id: u32, library: &[u8]) -> bool {
+    let Some(codebook) = load_wvc(library, codebook_id) else {
+        return false;
+    };
+
+    let mut iw = BitReader::new(codebook);
+
+    return ww2ogg_codebook_library_rebuild(ow, &mut iw, codebook.len());
+}
+
+/* fixed-point ilog from Xiph's Tremor */
+fn ww2ogg_tremor_ilog(v: u32) -> u32 {
+    let mut ret = 0;
+    let mut v = v;
+    while v != 0 {
+        ret += 1;
+        v >>= 1;
+    }
+    return ret;
+}
+
+/* quantvals-something from Xiph's Tremor */
+fn ww2ogg_tremor_book_maptype1_quantvals(entries: u32, dimensions: u32) -> u32 {
+    if entries == 0 || dimensions == 0 {
+        return 0;
+    }
+
+    /* get us a starting hint, we'll polish it below */
+    let bits = ww2ogg_tremor_ilog(entries);
+    let mut vals = entries >> ((bits - 1) * (dimensions - 1) / dimensions);
+
+    loop {
+        let mut acc: u64 = 1;
+        let mut acc1: u64 = 1;
+        for _ in 0..dimensions {
+            acc = acc.saturating_mul(vals as u64);
+            acc1 = acc1.saturating_mul(vals as u64 + 1);
+        }
+        if acc <= entries as u64 && acc1 > entries as u64 {
+            return vals;
+        } else if acc > entries as u64 {
+            vals -= 1;
+        } else {
+            vals += 1;
+        }
+    }
+}
@@ -1,35 +1,195 @@
-use crate::vgmstream::{VGMStream, CodingType};
+use crate::vgmstream::{VGMStream, CodingType, LayoutType, MetaType};
 use crate::coding::adx::*;
+use crate::coding::ima::*;
+use crate::coding::ngc_dsp::*;
+use crate::coding::pcm_decoder::*;
+use crate::coding::ptadpcm::*;
+use crate::layout::segmented::loop_layout_segmented;
+use crate::layout::layered::loop_layout_layered;
+
+/* Loop codecs that need special handling, usually:
+ * - on hit_loop, current offset is copied to loop_ch[].offset
+ * - some codecs will overwrite loop_ch[].offset with a custom value
+ * - loop_ch[] is copied to ch[] (with custom value)
+ * - then codec will use ch[]'s offset
+ * regular codecs may use copied loop_ch[] offset without issue */
+fn loop_codec(vgmstream: &mut VGMStream) {
+    match vgmstream.coding_type {
+        CodingType::coding_FFmpeg => {
+            use crate::coding::ffmpeg::seek_ffmpeg;
+            use crate::vgmstream::VGMStreamCodecData;
+            let loop_sample = vgmstream.loop_current_sample;
+            if let Some(VGMStreamCodecData::CustomFFmpeg(data)) = vgmstream.codec_data.as_mut() {
+                seek_ffmpeg(data, loop_sample);
+            }
+        }
+        CodingType::coding_VORBIS_custom => {
+            use crate::coding::vorbis::seek_vorbis_custom;
+            use crate::vgmstream::VGMStreamCodecData;
+            let loop_sample = vgmstream.loop_current_sample;
+            if let Some(VGMStreamCodecData::CustomVorbis(data)) = vgmstream.codec_data.as_mut() {
+                seek_vorbis_custom(data, loop_sample);
+            }
+            /* decoding restarts from the beginning and discards up to the loop start */
+            vgmstream.loop_ch[0].offset = vgmstream.loop_ch[0].channel_start_offset;
+        }
+        _ => {}
+    }
+}
+
+/* Handles looping: saves state when loop start is hit and restores it at loop end.
+ * Returns true when the stream has just looped (layouts must recalculate their values then). */
+pub fn decode_do_loop(vgmstream: &mut VGMStream) -> bool {
+    /*if !vgmstream.loop_flag { return false; }*/
+
+    /* is this the loop end? = new loop, continue from loop_start_sample */
+    if vgmstream.current_sample == vgmstream.loop_end_sample as isize {
+
+        /* disable looping if target count reached and continue normally
+         * (only needed with the "play stream end after looping N times" option enabled) */
+        vgmstream.loop_count += 1;
+        if vgmstream.loop_target != 0 && vgmstream.loop_target == vgmstream.loop_count {
+            vgmstream.loop_flag = false; /* could be improved but works ok, will be restored on resets */
+            return false;
+        }
+
+        /* against everything I hold sacred, preserve adpcm history before looping for certain types */
+        if vgmstream.meta_type == MetaType::meta_DSP_STD
+            || vgmstream.meta_type == MetaType::meta_DSP_RS03
+            || vgmstream.meta_type == MetaType::meta_DSP_CSTR
+            || vgmstream.coding_type == CodingType::coding_PSX
+            || vgmstream.coding_type == CodingType::coding_PSX_badflags
+        {
+            for ch in 0..vgmstream.channels as usize {
+                vgmstream.loop_ch[ch].adpcm_history1_16 = vgmstream.ch[ch].adpcm_history1_16;
+                vgmstream.loop_ch[ch].adpcm_history2_16 = vgmstream.ch[ch].adpcm_history2_16;
+                vgmstream.loop_ch[ch].adpcm_history1_32 = vgmstream.ch[ch].adpcm_history1_32;
+                vgmstream.loop_ch[ch].adpcm_history2_32 = vgmstream.ch[ch].adpcm_history2_32;
+            }
+        }
+
+        loop_codec(vgmstream);
+
+        /* restore! */
+        vgmstream.ch = vgmstream.loop_ch.clone();
+        vgmstream.current_sample = vgmstream.loop_current_sample as isize;
+        vgmstream.samples_into_block = vgmstream.loop_samples_into_block as isize;
+        vgmstream.current_block_size = vgmstream.loop_block_size;
+        vgmstream.current_block_samples = vgmstream.loop_block_samples;
+        vgmstream.current_block_offset = vgmstream.loop_block_offset;
+        vgmstream.next_block_offset = vgmstream.loop_next_block_offset;
+
+        /* loop layouts (after restore, in case layout needs state manipulations) */
+        match vgmstream.layout_type {
+            LayoutType::layout_segmented => {
+                let loop_sample = vgmstream.loop_current_sample;
+                loop_layout_segmented(vgmstream, loop_sample);
+            }
+            LayoutType::layout_layered => {
+                let loop_sample = vgmstream.loop_current_sample;
+                loop_layout_layered(vgmstream, loop_sample);
+            }
+            _ => {}
+        }
+
+        /* the main event */
+        return true; /* looped */
+    }
+
+    /* is this the loop start? save if we haven't saved yet (right when first loop starts) */
+    if vgmstream.hit_loop == 0 && vgmstream.current_sample == vgmstream.loop_start_sample as isize {
+        /* save! */
+        vgmstream.loop_ch = vgmstream.ch.clone();
+        vgmstream.loop_current_sample = vgmstream.current_sample as i32;
+        vgmstream.loop_samples_into_block = vgmstream.samples_into_block as i32;
+        vgmstream.loop_block_size = vgmstream.current_block_size;
+        vgmstream.loop_block_samples = vgmstream.current_block_samples;
+        vgmstream.loop_block_offset = vgmstream.current_block_offset;
+        vgmstream.loop_next_block_offset = vgmstream.next_block_offset;
+
+        vgmstream.hit_loop = 1; /* info that loop is now ready to use */
+    }
+
+    return false; /* not looped */
+}
+
 /* Decode samples into the buffer. Assume that we have written samples_written into the
  * buffer already, and we have samples_to_do consecutive samples ahead of us (won't call
  * more than one frame if configured above to do so).
  * Called by layouts since they handle samples written/to_do */
 pub fn decode_vgmstream(vgmstream: &mut VGMStream, samples_written: i32, samples_to_do: i32, buffer: &mut Vec<i16>) {
     // C code: buffer += samples_written * vgmstream.channels; /* passed externally to simplify I guess */
-    let mut output_buffer = &mut buffer.clone()[(samples_written * vgmstream.channels) as usize..];
+    let channels = vgmstream.channels;
+    let output_buffer = &mut buffer[(samples_written * channels) as usize..];
     match vgmstream.coding_type {
         CodingType::coding_SILENCE => {
             //memset(buffer, 0, samples_to_do * vgmstream->channels * sizeof(sample_t));
-            for i in 0..samples_to_do * vgmstream.channels {
+            for i in 0..samples_to_do * channels {
                 output_buffer[i as usize] = 0;
             }
             return;
         },
+        CodingType::coding_PCM16LE => {
+            for ch in 0..channels {
+                decode_pcm16le(&mut vgmstream.ch[ch as usize], &mut output_buffer[ch as usize..], channels, vgmstream.samples_into_block as i32, samples_to_do);
+            }
+        },
+        CodingType::coding_PCM16BE => {
+            for ch in 0..channels {
+                decode_pcm16be(&mut vgmstream.ch[ch as usize], &mut output_buffer[ch as usize..], channels, vgmstream.samples_into_block as i32, samples_to_do);
+            }
+        },
+        CodingType::coding_PCM8_U => {
+            for ch in 0..channels {
+                decode_pcm8_unsigned(&mut vgmstream.ch[ch as usize], &mut output_buffer[ch as usize..], channels, vgmstream.samples_into_block as i32, samples_to_do);
+            }
+        },
+        CodingType::coding_PCM24LE => {
+            for ch in 0..channels {
+                decode_pcm24le(&mut vgmstream.ch[ch as usize], &mut output_buffer[ch as usize..], channels, vgmstream.samples_into_block as i32, samples_to_do);
+            }
+        },
+        CodingType::coding_PCM24BE => {
+            for ch in 0..channels {
+                decode_pcm24be(&mut vgmstream.ch[ch as usize], &mut output_buffer[ch as usize..], channels, vgmstream.samples_into_block as i32, samples_to_do);
+            }
+        },
+        CodingType::coding_PCMFLOAT => {
+            for ch in 0..channels {
+                decode_pcmfloat(&mut vgmstream.ch[ch as usize], &mut output_buffer[ch as usize..], channels, vgmstream.samples_into_block as i32, samples_to_do, vgmstream.codec_endian);
+            }
+        },
         CodingType::coding_CRI_ADX |
         CodingType::coding_CRI_ADX_exp |
         CodingType::coding_CRI_ADX_fixed |
         CodingType::coding_CRI_ADX_enc_8 |
         CodingType::coding_CRI_ADX_enc_9 => {
-            for ch in 0..vgmstream.channels {
-                decode_adx(&mut vgmstream.ch[ch as usize], &mut output_buffer[ch as usize..], vgmstream.channels, vgmstream.samples_into_block as i32, samples_to_do, vgmstream.interleave_block_size as i32, vgmstream.coding_type, vgmstream.codec_config as u32);
-                // buffer[(samples_written * vgmstream.channels + ch) as usize] = output_buffer.to_vec();
-                // replace the buffer with the output buffer, from ch onwards
-                buffer[(samples_written * vgmstream.channels + ch) as usize..].copy_from_slice(&output_buffer[ch as usize..]);
+            for ch in 0..channels {
+                decode_adx(&mut vgmstream.ch[ch as usize], &mut output_buffer[ch as usize..], channels, vgmstream.samples_into_block as i32, samples_to_do, vgmstream.interleave_block_size as i32, vgmstream.coding_type, vgmstream.codec_config as u32);
+            }
+        },
+        CodingType::coding_NGC_DSP => {
+            for ch in 0..channels {
+                decode_ngc_dsp(&mut vgmstream.ch[ch as usize], &mut output_buffer[ch as usize..], channels, vgmstream.samples_into_block as i32, samples_to_do);
+            }
+        },
+        CodingType::coding_WWISE_IMA => {
+            for ch in 0..channels {
+                decode_wwise_ima(&mut vgmstream.ch[ch as usize], &mut output_buffer[ch as usize..], channels, vgmstream.samples_into_block as i32, samples_to_do, ch, channels, vgmstream.interleave_block_size as i32, vgmstream.codec_endian);
+            }
+        },
+        CodingType::coding_PTADPCM => {
+            for ch in 0..channels {
+                decode_ptadpcm(&mut vgmstream.ch[ch as usize], &mut output_buffer[ch as usize..], channels, vgmstream.samples_into_block as i32, samples_to_do, vgmstream.interleave_block_size as i32);
             }
         },
         CodingType::coding_FFmpeg => {
             use crate::coding::ffmpeg::decode_ffmpeg;
-            decode_ffmpeg(vgmstream, buffer, samples_to_do, vgmstream.channels);
+            decode_ffmpeg(vgmstream, output_buffer, samples_to_do, channels);
+        }
+        CodingType::coding_VORBIS_custom => {
+            use crate::coding::vorbis::decode_vorbis_custom;
+            decode_vorbis_custom(vgmstream, output_buffer, samples_to_do, channels);
         }
         _ => {
             return;
@@ -42,6 +202,20 @@ pub fn decode_get_frame_size(vgmstream: &VGMStream) -> i32 {
         CodingType::coding_SILENCE => {
             return 0;
         },
+        CodingType::coding_PCM8_U => {
+            return 0x01;
+        }
+        CodingType::coding_PCM16LE |
+        CodingType::coding_PCM16BE => {
+            return 0x02;
+        }
+        CodingType::coding_PCM24LE |
+        CodingType::coding_PCM24BE => {
+            return 0x03;
+        }
+        CodingType::coding_PCMFLOAT => {
+            return 0x04;
+        }
         CodingType::coding_CRI_ADX |
         CodingType::coding_CRI_ADX_fixed |
         CodingType::coding_CRI_ADX_exp |
@@ -49,6 +223,15 @@ pub fn decode_get_frame_size(vgmstream: &VGMStream) -> i32 {
         CodingType::coding_CRI_ADX_enc_9 => {
             return vgmstream.interleave_block_size as i32;
         }
+        CodingType::coding_NGC_DSP => {
+            return 0x08;
+        }
+        CodingType::coding_WWISE_IMA => {
+            return 0x24;
+        }
+        CodingType::coding_PTADPCM => {
+            return vgmstream.interleave_block_size as i32;
+        }
         _ => {
             return 0;
         }
@@ -65,6 +248,14 @@ pub fn decode_get_samples_per_frame(vgmstream: &VGMStream) -> i32 {
         CodingType::coding_SILENCE => {
             return 0;
         }
+        CodingType::coding_PCM16LE |
+        CodingType::coding_PCM16BE |
+        CodingType::coding_PCM8_U |
+        CodingType::coding_PCM24LE |
+        CodingType::coding_PCM24BE |
+        CodingType::coding_PCMFLOAT => {
+            return 1;
+        }
         CodingType::coding_CRI_ADX |
         CodingType::coding_CRI_ADX_fixed |
         CodingType::coding_CRI_ADX_exp |
@@ -72,6 +263,15 @@ pub fn decode_get_samples_per_frame(vgmstream: &VGMStream) -> i32 {
         CodingType::coding_CRI_ADX_enc_9 => {
             return (vgmstream.interleave_block_size as i32 - 2) * 2;
         }
+        CodingType::coding_NGC_DSP => {
+            return 14;
+        }
+        CodingType::coding_WWISE_IMA => {
+            return (0x24 - 0x04) * 2; /* always 64 */
+        }
+        CodingType::coding_PTADPCM => {
+            return (vgmstream.interleave_block_size as i32 - 0x05) * 2 + 2;
+        }
         _ => {
             return 0;
         }
@@ -0,0 +1,65 @@
+use std::fmt;
+
+/* Errors found while opening or decoding a stream */
+#[derive(Debug)]
+pub enum VgmstreamError {
+    /* no meta recognized the file (also returned by metas when the file isn't theirs) */
+    UnknownFormat { filename: String },
+    /* read past the end of the file (bad header sizes or incomplete rips) */
+    Truncated { meta: &'static str, offset: usize, size: usize },
+    /* format is recognized but its codec or variant isn't supported */
+    UnsupportedCodec { meta: &'static str, codec: String },
+    /* header field with an unexpected value */
+    BadHeader { meta: &'static str, offset: usize, field: &'static str },
+    /* codec couldn't be initialized or failed to decode */
+    DecoderFailure { codec: &'static str, offset: usize, reason: String },
+    /* requested subsong doesn't exist (subsongs are 1-based) */
+    InvalidSubsong { index: i32, total: i32 },
+    /* file couldn't be opened or read */
+    Io(std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, VgmstreamError>;
+
+impl fmt::Display for VgmstreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VgmstreamError::UnknownFormat { filename } => {
+                write!(f, "unknown format: {}", filename)
+            }
+            VgmstreamError::Truncated { meta, offset, size } => {
+                write!(f, "{}: truncated file, can't read 0x{:x} bytes at 0x{:x}", meta, size, offset)
+            }
+            VgmstreamError::UnsupportedCodec { meta, codec } => {
+                write!(f, "{}: unsupported codec {}", meta, codec)
+            }
+            VgmstreamError::BadHeader { meta, offset, field } => {
+                write!(f, "{}: bad {} at 0x{:x}", meta, field, offset)
+            }
+            VgmstreamError::DecoderFailure { codec, offset, reason } => {
+                write!(f, "{}: {} at 0x{:x}", codec, reason, offset)
+            }
+            VgmstreamError::InvalidSubsong { index, total } => {
+                write!(f, "invalid subsong {} (file has {})", index, total)
+            }
+            VgmstreamError::Io(err) => {
+                write!(f, "I/O error: {}", err)
+            }
+        }
+    }
+}
+
+impl std::error::Error for VgmstreamError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            VgmstreamError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for VgmstreamError {
+    fn from(err: std::io::Error) -> Self {
+        VgmstreamError::Io(err)
+    }
+}
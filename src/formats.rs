@@ -1,7 +1,7 @@
 use crate::vgmstream_types::*;
 use crate::vgmstream_types::LayoutType::{layout_layered, layout_segmented};
 
-pub const EXTENSION_LIST: [&str; 625] = [
+pub const EXTENSION_LIST: [&str; 626] = [
     "208",
     "2dx9",
     "2pfs",
@@ -32,6 +32,7 @@ pub const EXTENSION_LIST: [&str; 625] = [
     "afs2",
     "agsc",
     "ahx",
+    "ahxi",
     "ahv",
     "ai",
     "aifc", //common?
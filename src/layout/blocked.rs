@@ -1,53 +1,42 @@
+use crate::vgm_log;
 use crate::vgmstream::VGMStream;
 use crate::decode::*;
 
-pub fn render_vgmstream_blocked(sample_count: i32, vgmstream: &mut VGMStream) -> Option<Vec<i16>> {
-    let mut frame_size = decode_get_frame_size(&vgmstream);
-    let mut samples_per_frame = decode_get_samples_per_frame(&vgmstream);
-    let mut samples_this_block = 0;
-    let mut samples_written = 0;
-
+/* Calculates samples in the current block, from block info or sizes */
+fn get_samples_this_block(vgmstream: &VGMStream, frame_size: i32, samples_per_frame: i32) -> i32 {
     if vgmstream.current_block_samples != 0 {
-        samples_this_block = vgmstream.current_block_samples;
+        return vgmstream.current_block_samples;
     } else if frame_size == 0 { /* assume 4 bit */ //TODO: decode_get_frame_size() really should return bits... */
-        samples_this_block = vgmstream.current_block_size as i32 * 2 * samples_per_frame;
+        return vgmstream.current_block_size as i32 * 2 * samples_per_frame;
     } else {
-        samples_this_block = vgmstream.current_block_size as i32 / frame_size * samples_per_frame;
+        return vgmstream.current_block_size as i32 / frame_size * samples_per_frame;
     }
+}
 
-    let mut output_buffer: Option<Vec<i16>> = None;
+pub fn render_vgmstream_blocked(buffer: &mut Vec<i16>, sample_count: i32, vgmstream: &mut VGMStream) {
+    let mut frame_size = decode_get_frame_size(vgmstream);
+    let mut samples_per_frame = decode_get_samples_per_frame(vgmstream);
+    let mut samples_this_block = get_samples_this_block(vgmstream, frame_size, samples_per_frame);
+    let mut samples_written = 0;
 
     while samples_written < sample_count {
-        let mut samples_to_do = 0; 
-
+        let mut samples_to_do;
 
-        // TODO: handle looping
-
-        // if vgmstream.loop_flag && decode_do_loop(vgmstream) {
-        //     /* handle looping, readjust back to loop start values */
-        //     if (vgmstream->current_block_samples) {
-        //         samples_this_block = vgmstream->current_block_samples;
-        //     } else if (frame_size == 0) { /* assume 4 bit */ //TODO: decode_get_frame_size() really should return bits... */
-        //         samples_this_block = vgmstream->current_block_size * 2 * samples_per_frame;
-        //     } else {
-        //         samples_this_block = vgmstream->current_block_size / frame_size * samples_per_frame;
-        //     }
-        //     continue;
-        // }
+        if vgmstream.loop_flag && decode_do_loop(vgmstream) {
+            /* handle looping, readjust back to loop start values */
+            samples_this_block = get_samples_this_block(vgmstream, frame_size, samples_per_frame);
+            continue;
+        }
 
         if samples_this_block < 0 {
             /* probably block bug or EOF, next calcs would give wrong values/segfaults/infinite loop */
-            println!("layout_blocked: wrong block samples at 0x{:x}", vgmstream.current_block_offset);
-            // memset(buffer + samples_written*vgmstream->channels, 0, (sample_count - samples_written) * vgmstream->channels * sizeof(sample_t));
-            output_buffer = None;
+            vgm_log!("layout_blocked: wrong block samples at 0x{:x}", vgmstream.current_block_offset);
             break;
         }
 
         if vgmstream.current_block_offset < 0 || vgmstream.current_block_offset == 0xFFFFFFFF {
             /* probably block bug or EOF, block functions won't be able to read anything useful/infinite loop */
-            println!("layout_blocked: wrong block offset found");
-            // memset(buffer + samples_written*vgmstream->channels, 0, (sample_count - samples_written) * vgmstream->channels * sizeof(sample_t));
-            output_buffer = None;
+            vgm_log!("layout_blocked: wrong block offset found");
             break;
         }
 
@@ -58,7 +47,7 @@ pub fn render_vgmstream_blocked(sample_count: i32, vgmstream: &mut VGMStream) ->
 
         if samples_to_do > 0 {
             /* samples_this_block = 0 is allowed (empty block, do nothing then move to next block) */
-            // output_buffer = decode_vgmstream(vgmstream, samples_written, samples_to_do);
+            decode_vgmstream(vgmstream, samples_written, samples_to_do, buffer);
         }
 
         samples_written += samples_to_do;
@@ -73,19 +62,17 @@ pub fn render_vgmstream_blocked(sample_count: i32, vgmstream: &mut VGMStream) ->
             /* update since these may change each block */
             frame_size = decode_get_frame_size(vgmstream);
             samples_per_frame = decode_get_samples_per_frame(vgmstream);
-            if vgmstream.current_block_samples != 0 {
-                samples_this_block = vgmstream.current_block_samples;
-            } else if frame_size == 0 { /* assume 4 bit */ //TODO: decode_get_frame_size() really should return bits... */
-                samples_this_block = vgmstream.current_block_size as i32 * 2 * samples_per_frame;
-            } else {
-                samples_this_block = vgmstream.current_block_size as i32 / frame_size * samples_per_frame;
-            }
+            samples_this_block = get_samples_this_block(vgmstream, frame_size, samples_per_frame);
 
             vgmstream.samples_into_block = 0;
         }
 
     }
 
-
-    return output_buffer;
-}
\ No newline at end of file
+    if samples_written < sample_count {
+        // memset(buffer + samples_written*vgmstream->channels, 0, (sample_count - samples_written) * vgmstream->channels * sizeof(sample_t));
+        let start = (samples_written * vgmstream.channels) as usize;
+        let end = (sample_count * vgmstream.channels) as usize;
+        buffer[start..end].fill(0);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::vgm_log;
 use crate::decode::*;
 use crate::vgmstream::VGMStream;
 
@@ -24,9 +25,8 @@ pub fn render_vgmstream_interleave(
     let frame_size_d = decode_get_frame_size(vgmstream);
     samples_per_frame_d = decode_get_samples_per_frame(vgmstream);
     if frame_size_d == 0 || samples_per_frame_d == 0 {
-        println!("layout_interleave: wrong values found");
-        *buffer =
-            vec![0; (sample_count - samples_written) as usize * 2 * vgmstream.channels as usize];
+        vgm_log!("layout_interleave: wrong values found");
+        buffer[..(sample_count * vgmstream.channels) as usize].fill(0);
         return;
     }
     samples_this_block_d =
@@ -35,16 +35,12 @@ pub fn render_vgmstream_interleave(
         let frame_size_f = decode_get_frame_size(vgmstream);
         samples_per_frame_f = decode_get_samples_per_frame(vgmstream); //todo samples per shortframe
         if frame_size_f == 0 || samples_per_frame_f == 0 {
-            println!("layout_interleave: wrong values found");
-            *buffer =
-                vec![
-                    0;
-                    (sample_count - samples_written) as usize * 2 * vgmstream.channels as usize
-                ];
+            vgm_log!("layout_interleave: wrong values found");
+            buffer[..(sample_count * vgmstream.channels) as usize].fill(0);
             return;
         }
         samples_this_block_f =
-            vgmstream.interleave_first_block_size / (frame_size_f * samples_per_frame_f) as isize;
+            vgmstream.interleave_first_block_size / frame_size_f as isize * samples_per_frame_f as isize;
     }
     if has_interleave_last {
         // let frame_size_l = decode_get_shortframe_size(vgmstream);
@@ -78,29 +74,26 @@ pub fn render_vgmstream_interleave(
 
     /* write samples */
     while samples_written < sample_count {
-        // int samples_to_do;
-
-        // TODO: loop
-
-        // if (vgmstream->loop_flag && decode_do_loop(vgmstream)) {
-        //     /* handle looping, restore standard interleave sizes */
-        //     if (has_interleave_first &&
-        //             vgmstream->current_sample < samples_this_block_f) {
-        //         /* use first interleave*/
-        //         samples_per_frame = samples_per_frame_f;
-        //         samples_this_block = samples_this_block_f;
-        //         if (samples_this_block == 0 && vgmstream->channels == 1)
-        //             samples_this_block = vgmstream->num_samples;
-        //     }
-        //     else if (has_interleave_last) { /* assumes that won't loop back into a interleave_last */
-        //         samples_per_frame = samples_per_frame_d;
-        //         samples_this_block = samples_this_block_d;
-        //         if (samples_this_block == 0 && vgmstream->channels == 1)
-        //             samples_this_block = vgmstream->num_samples;
-        //     }
+        if vgmstream.loop_flag && decode_do_loop(vgmstream) {
+            /* handle looping, restore standard interleave sizes */
+            if has_interleave_first && vgmstream.current_sample < samples_this_block_f {
+                /* use first interleave*/
+                samples_per_frame = samples_per_frame_f;
+                samples_this_block = samples_this_block_f;
+                if samples_this_block == 0 && vgmstream.channels == 1 {
+                    samples_this_block = vgmstream.num_samples as isize;
+                }
+            } else if has_interleave_last {
+                /* assumes that won't loop back into a interleave_last */
+                samples_per_frame = samples_per_frame_d;
+                samples_this_block = samples_this_block_d;
+                if samples_this_block == 0 && vgmstream.channels == 1 {
+                    samples_this_block = vgmstream.num_samples as isize;
+                }
+            }
 
-        //     continue;
-        // }
+            continue;
+        }
 
         let mut samples_to_do =
             vgmstream.decode_get_samples_to_do(samples_this_block as i32, samples_per_frame);
@@ -110,13 +103,11 @@ pub fn render_vgmstream_interleave(
 
         if samples_to_do == 0 {
             /* happens when interleave is not set */
-            println!("layout_interleave: wrong values found");
+            vgm_log!("layout_interleave: wrong values found");
             // memset(buffer + samples_written*vgmstream->channels, 0, (sample_count - samples_written) * vgmstream->channels * sizeof(sample_t));
-            *buffer =
-                vec![
-                    0;
-                    (sample_count - samples_written) as usize * 2 * vgmstream.channels as usize
-                ];
+            let start = (samples_written * vgmstream.channels) as usize;
+            let end = (sample_count * vgmstream.channels) as usize;
+            buffer[start..end].fill(0);
             return;
         }
 
@@ -0,0 +1,186 @@
+use crate::vgm_log;
+use crate::error::{Result, VgmstreamError};
+use crate::vgmstream::*;
+use crate::decode::*;
+use crate::render::{render_vgmstream, seek_vgmstream};
+
+const VGMSTREAM_LAYER_SAMPLE_BUFFER: i32 = 8192;
+
+/* Decodes samples for layered streams.
+ * Similar to interleave layout, but decodes full sub-vgmstreams (layers) per channel group,
+ * for data made of "parallel" streams (like 2ch + 2ch = 4ch, which may even use different codecs). */
+pub fn render_vgmstream_layered(buffer: &mut Vec<i16>, sample_count: i32, vgmstream: &mut VGMStream) {
+    let mut samples_written = 0;
+    let samples_this_block = vgmstream.num_samples; /* do all samples if possible */
+    let output_channels = vgmstream.channels;
+
+    while samples_written < sample_count {
+        if vgmstream.loop_flag && decode_do_loop(vgmstream) {
+            /* handle looping (loop_layout has been called below) */
+            continue;
+        }
+
+        let mut samples_to_do = vgmstream.decode_get_samples_to_do(samples_this_block, VGMSTREAM_LAYER_SAMPLE_BUFFER);
+        if samples_to_do > sample_count - samples_written {
+            samples_to_do = sample_count - samples_written;
+        }
+
+        if samples_to_do <= 0 { /* when decoding more than num_samples */
+            vgm_log!("LAYERED: wrong samples_to_do {} found", samples_to_do);
+            break;
+        }
+
+        /* decode all layers */
+        let Some(data) = vgmstream.layered_layout_data.as_mut() else {
+            vgm_log!("LAYERED: missing layout data");
+            break;
+        };
+        let buffer_size = (VGMSTREAM_LAYER_SAMPLE_BUFFER * data.input_channels) as usize;
+        if data.buffer.len() < buffer_size {
+            data.buffer.resize(buffer_size, 0);
+        }
+
+        let mut ch = 0;
+        for layer in data.layers.iter_mut() {
+            /* each layer will handle its own looping internally, and may have its own number of channels */
+            let layer_channels = layer.channels as usize;
+
+            render_vgmstream(&mut data.buffer, samples_to_do, layer);
+
+            /* mix layer samples to main samples */
+            copy_samples(buffer, &data.buffer, output_channels as usize, layer_channels, ch, samples_to_do, samples_written);
+            ch += layer_channels;
+        }
+
+        samples_written += samples_to_do;
+        vgmstream.current_sample += samples_to_do as isize;
+        vgmstream.samples_into_block += samples_to_do as isize;
+    }
+
+    if samples_written < sample_count {
+        let start = (samples_written * output_channels) as usize;
+        let end = (sample_count * output_channels) as usize;
+        buffer[start..end].fill(0);
+    }
+}
+
+/* copies a layer's samples to its channels in the output */
+fn copy_samples(outbuf: &mut [i16], inbuf: &[i16], output_channels: usize, layer_channels: usize, first_ch: usize, samples_to_do: i32, samples_written: i32) {
+    let outpos = samples_written as usize * output_channels;
+
+    for s in 0..samples_to_do as usize {
+        for layer_ch in 0..layer_channels {
+            if first_ch + layer_ch >= output_channels {
+                break;
+            }
+            outbuf[outpos + s * output_channels + first_ch + layer_ch] = inbuf[s * layer_channels + layer_ch];
+        }
+    }
+}
+
+/* Moves all layers to loop_sample */
+pub fn loop_layout_layered(vgmstream: &mut VGMStream, loop_sample: i32) {
+    let Some(data) = vgmstream.layered_layout_data.as_mut() else {
+        return;
+    };
+
+    for layer in data.layers.iter_mut() {
+        if data.external_looping != 0 {
+            /* looping is applied over resulting decode, as each layer is its own "solid" block
+             * with config and needs 'external' seeking */
+            seek_vgmstream(layer, loop_sample);
+        }
+        else if layer.loop_flag { /* mixing looping and non-looping layers is allowed */
+            /* looping is applied as internal loops. Normally each layer does it automatically,
+             * but forcing it here behaves a bit more controlled and so manual loops work */
+            layer.current_sample = layer.loop_end_sample as isize; /* forces do loop */
+            decode_do_loop(layer); /* guaranteed to work */
+        }
+    }
+
+    vgmstream.samples_into_block = loop_sample as isize;
+}
+
+/* Creates layout data for layer_count layers, to be filled by the meta */
+pub fn init_layout_layered(layer_count: i32) -> LayeredLayoutData {
+    let mut data: LayeredLayoutData = Default::default();
+    data.layer_count = layer_count;
+    data.layers = Vec::with_capacity(layer_count as usize);
+    return data;
+}
+
+/* Validates layers once filled and saves their initial state */
+pub fn setup_layout_layered(data: &mut LayeredLayoutData) -> Result<()> {
+    if data.layer_count <= 0 || data.layers.len() != data.layer_count as usize {
+        return Err(VgmstreamError::BadHeader { meta: "LAYERED", offset: 0, field: "layer count" });
+    }
+
+    let mut channels = 0;
+    for (i, layer) in data.layers.iter_mut().enumerate() {
+        if layer.num_samples <= 0 {
+            vgm_log!("LAYERED: no samples in layer {}", i);
+            return Err(VgmstreamError::BadHeader { meta: "LAYERED", offset: 0, field: "layer samples" });
+        }
+        if layer.channels <= 0 {
+            return Err(VgmstreamError::BadHeader { meta: "LAYERED", offset: 0, field: "layer channels" });
+        }
+
+        /* loops are handled by each layer unless the meta sets external_looping */
+        if data.external_looping != 0 && layer.loop_flag {
+            layer.loop_flag = false;
+        }
+
+        channels += layer.channels;
+        layer.setup();
+    }
+
+    data.input_channels = data.layers.iter().map(|layer| layer.channels).max().unwrap_or(0);
+    data.output_channels = channels;
+    data.buffer = vec![0; (VGMSTREAM_LAYER_SAMPLE_BUFFER * data.input_channels) as usize];
+
+    return Ok(());
+}
+
+/* Makes a VGMStream for the layers (channels of all layers, longest layer's samples, common loops) */
+pub fn allocate_layered_vgmstream(data: LayeredLayoutData) -> Result<VGMStream> {
+    let mut vgmstream = VGMStream::default();
+    let first = &data.layers[0];
+
+    let mut num_samples = 0;
+    let mut sample_rate = 0;
+    let mut loop_flag = first.loop_flag;
+    let mut coding_type = first.coding_type;
+
+    for layer in data.layers.iter() {
+        num_samples = num_samples.max(layer.num_samples);
+        sample_rate = sample_rate.max(layer.sample_rate);
+
+        /* only loops if all layers loop the same way */
+        if loop_flag && (!layer.loop_flag
+            || layer.loop_start_sample != first.loop_start_sample
+            || layer.loop_end_sample != first.loop_end_sample)
+        {
+            loop_flag = false;
+        }
+
+        if coding_type == CodingType::coding_SILENCE {
+            coding_type = layer.coding_type;
+        }
+    }
+
+    vgmstream.channels = data.output_channels;
+    vgmstream.ch = vec![VGMStreamChannel::default(); data.output_channels as usize];
+    vgmstream.sample_rate = sample_rate;
+    vgmstream.num_samples = num_samples;
+    vgmstream.loop_flag = loop_flag;
+    if loop_flag {
+        vgmstream.loop_start_sample = first.loop_start_sample;
+        vgmstream.loop_end_sample = first.loop_end_sample;
+    }
+    vgmstream.meta_type = first.meta_type;
+    vgmstream.coding_type = coding_type;
+    vgmstream.layout_type = LayoutType::layout_layered;
+    vgmstream.layered_layout_data = Some(data);
+
+    return Ok(vgmstream);
+}
@@ -1,4 +1,5 @@
 pub mod blocked;
 pub mod interleave;
 pub mod flat;
-pub mod segmented;
\ No newline at end of file
+pub mod segmented;
+pub mod layered;
\ No newline at end of file
@@ -1,85 +1,248 @@
-use crate::vgmstream::VGMStream;
+use crate::vgm_log;
+use crate::error::{Result, VgmstreamError};
+use crate::vgmstream::*;
 use crate::decode::*;
 use crate::render::render_vgmstream;
-use crate::vgmstream_types::SegmentedLayoutData;
 
 const VGMSTREAM_SEGMENT_SAMPLE_BUFFER: i32 = 8192;
 
-pub fn render_vgmstream_segmented(sample_count: i32, vgmstream: &mut VGMStream) -> Option<Vec<i16>> {
+/* Decodes samples for segmented streams.
+ * Chains together sequential vgmstreams, for data divided into separate sections or files
+ * (like one part for intro and other for loop segments, which may even use different codecs). */
+pub fn render_vgmstream_segmented(buffer: &mut Vec<i16>, sample_count: i32, vgmstream: &mut VGMStream) {
     let mut samples_written = 0;
-    let mut samples_this_block: i32 = 0;
-    let data = vgmstream.segmented_layout_data.as_ref().unwrap();
-    let use_internal_buffer;
-    // let mut current_channels = 0;
-    let mut current_segment = data.current_segment;
-
-    /* normally uses outbuf directly (faster?) but could need internal buffer if downmixing */
-    if vgmstream.channels != data.input_channels || data.mixed_channels {
-        use_internal_buffer = true;
-    }
+    let mut samples_this_block;
+    let mut current_channels;
+    let output_channels = vgmstream.channels;
+
+    {
+        let data = vgmstream.segmented_layout_data.as_ref().unwrap();
+        if data.current_segment >= data.segment_count {
+            vgm_log!("SEGMENTED: wrong current segment");
+            let start = (samples_written * output_channels) as usize;
+            let end = (sample_count * output_channels) as usize;
+            buffer[start..end].fill(0);
+            return;
+        }
 
-    if current_segment >= data.segment_count {
-        println!("SEGMENT: wrong current segment");
-        return None;
+        let segment = &data.segments[data.current_segment as usize];
+        samples_this_block = segment.get_samples();
+        current_channels = segment.channels;
     }
 
-    samples_this_block = data.segments[data.current_segment as usize].get_samples();
-    // current_channels = mixing_info(data.segments[data->current_segment], NULL);
-
     while samples_written < sample_count {
-        let mut samples_to_do = 0;
-
-        // if (vgmstream.loop_flag && decode_do_loop(vgmstream)) {
-        //     /* handle looping (loop_layout has been called below, changes segments/state) */
-        //     samples_this_block = vgmstream_get_samples(data->segments[data->current_segment]);
-        //     mixing_info(data->segments[data->current_segment], NULL, &current_channels);
-        //     continue;
-        // }
+        let mut samples_to_do;
+
+        if vgmstream.loop_flag && decode_do_loop(vgmstream) {
+            /* handle looping (loop_layout has been called below, changes segments/state) */
+            let data = vgmstream.segmented_layout_data.as_ref().unwrap();
+            let segment = &data.segments[data.current_segment as usize];
+            samples_this_block = segment.get_samples();
+            current_channels = segment.channels;
+            continue;
+        }
 
         /* detect segment change and restart (after loop, but before decode, to allow looping to kick in) */
         if vgmstream.samples_into_block >= samples_this_block as isize {
-            current_segment+=1;
+            let data = vgmstream.segmented_layout_data.as_mut().unwrap();
+            data.current_segment += 1;
 
             if data.current_segment >= data.segment_count { /* when decoding more than num_samples */
-                println!("SEGMENTED: reached last segment");
-                return None;
+                vgm_log!("SEGMENTED: reached last segment");
+                data.current_segment = data.segment_count - 1;
+                break;
             }
 
             /* in case of looping spanning multiple segments */
-            // data.segments[data.current_segment].reset();
+            let segment = &mut data.segments[data.current_segment as usize];
+            segment.reset();
 
-            samples_this_block = data.segments[data.current_segment as usize].get_samples();
-            // mixing_info(data->segments[data->current_segment], NULL, &current_channels);
+            samples_this_block = segment.get_samples();
+            current_channels = segment.channels;
             vgmstream.samples_into_block = 0;
             continue;
         }
 
-
         samples_to_do = vgmstream.decode_get_samples_to_do(samples_this_block, sample_count);
         if samples_to_do > sample_count - samples_written {
             samples_to_do = sample_count - samples_written;
         }
-        if samples_to_do > VGMSTREAM_SEGMENT_SAMPLE_BUFFER /*&& use_internal_buffer*/ { /* always for fade/etc mixes */
-            samples_to_do = VGMSTREAM_SEGMENT_SAMPLE_BUFFER;            
+        if samples_to_do > VGMSTREAM_SEGMENT_SAMPLE_BUFFER { /* always for fade/etc mixes */
+            samples_to_do = VGMSTREAM_SEGMENT_SAMPLE_BUFFER;
         }
 
         if samples_to_do < 0 { /* 0 is ok? */
-            println!("SEGMENTED: wrong samples_to_do {} found", samples_to_do);
-            return None;
+            vgm_log!("SEGMENTED: wrong samples_to_do {} found", samples_to_do);
+            break;
         }
-        // let mut buffer: Option<Vec<i16>> = if use_internal_buffer { Some(data.buffer.clone()) } else { Some(vec![samples_written as i16 * data.output_channels as i16;0]) };
-        // render_vgmstream(samples_to_do, &mut data.segments[data.current_segment as usize]);
 
-        // if use_internal_buffer {
-        //     copy_samples(outbuf, data, current_channels, samples_to_do, samples_written);
-        // }
+        {
+            let data = vgmstream.segmented_layout_data.as_mut().unwrap();
+            let buffer_size = (VGMSTREAM_SEGMENT_SAMPLE_BUFFER * data.input_channels.max(current_channels)) as usize;
+            if data.buffer.len() < buffer_size {
+                data.buffer.resize(buffer_size, 0);
+            }
+
+            let segment = &mut data.segments[data.current_segment as usize];
+            render_vgmstream(&mut data.buffer, samples_to_do, segment);
+
+            copy_samples(buffer, &data.buffer, output_channels, current_channels, samples_to_do, samples_written);
+        }
 
         samples_written += samples_to_do;
         vgmstream.current_sample += samples_to_do as isize;
         vgmstream.samples_into_block += samples_to_do as isize;
     }
 
-    None
-// decode_fail:
-//     memset(outbuf + samples_written * data->output_channels, 0, (sample_count - samples_written) * data->output_channels * sizeof(sample_t));
-}
\ No newline at end of file
+    if samples_written < sample_count {
+        // memset(outbuf + samples_written * data->output_channels, 0, (sample_count - samples_written) * data->output_channels * sizeof(sample_t));
+        let start = (samples_written * output_channels) as usize;
+        let end = (sample_count * output_channels) as usize;
+        buffer[start..end].fill(0);
+    }
+}
+
+/* copies the segment's samples to the output, padding channels if the segment has less than the layout */
+fn copy_samples(outbuf: &mut [i16], inbuf: &[i16], output_channels: i32, input_channels: i32, samples_to_do: i32, samples_written: i32) {
+    let out_ch = output_channels as usize;
+    let in_ch = input_channels as usize;
+    let outpos = samples_written as usize * out_ch;
+
+    for s in 0..samples_to_do as usize {
+        for ch in 0..out_ch {
+            outbuf[outpos + s * out_ch + ch] = if ch < in_ch { inbuf[s * in_ch + ch] } else { 0 };
+        }
+    }
+}
+
+/* Moves the layout to the segment containing loop_sample, and positions that segment */
+pub fn loop_layout_segmented(vgmstream: &mut VGMStream, loop_sample: i32) {
+    let num_samples = vgmstream.num_samples;
+    let data = vgmstream.segmented_layout_data.as_mut().unwrap();
+    let mut segment = 0;
+    let mut total_samples = 0;
+
+    while total_samples < num_samples && segment < data.segment_count {
+        let segment_samples = data.segments[segment as usize].get_samples();
+
+        /* find if loop falls within segment's samples */
+        if loop_sample >= total_samples && loop_sample < total_samples + segment_samples {
+            let loop_relative = loop_sample - total_samples;
+
+            skip_samples(&mut data.segments[segment as usize], &mut data.buffer, loop_relative);
+            data.current_segment = segment;
+            vgmstream.samples_into_block = loop_relative as isize;
+            break;
+        }
+        total_samples += segment_samples;
+        segment += 1;
+    }
+
+    if segment == data.segment_count {
+        vgm_log!("SEGMENTED: can't find loop segment");
+    }
+}
+
+/* restarts the segment then decodes and discards samples until the target position */
+fn skip_samples(segment: &mut VGMStream, buffer: &mut Vec<i16>, samples: i32) {
+    let buffer_size = (VGMSTREAM_SEGMENT_SAMPLE_BUFFER * segment.channels) as usize;
+    if buffer.len() < buffer_size {
+        buffer.resize(buffer_size, 0);
+    }
+
+    segment.reset();
+
+    let mut samples_left = samples;
+    while samples_left > 0 {
+        let samples_to_do = samples_left.min(VGMSTREAM_SEGMENT_SAMPLE_BUFFER);
+        render_vgmstream(buffer, samples_to_do, segment);
+        samples_left -= samples_to_do;
+    }
+}
+
+/* Creates layout data for segment_count segments, to be filled by the meta */
+pub fn init_layout_segmented(segment_count: i32) -> SegmentedLayoutData {
+    let mut data: SegmentedLayoutData = Default::default();
+    data.segment_count = segment_count;
+    data.segments = Vec::with_capacity(segment_count as usize);
+    return data;
+}
+
+/* Validates segments once filled and saves their initial state */
+pub fn setup_layout_segmented(data: &mut SegmentedLayoutData) -> Result<()> {
+    if data.segment_count <= 0 || data.segments.len() != data.segment_count as usize {
+        return Err(VgmstreamError::BadHeader { meta: "SEGMENTED", offset: 0, field: "segment count" });
+    }
+
+    for (i, segment) in data.segments.iter_mut().enumerate() {
+        if segment.num_samples <= 0 {
+            vgm_log!("SEGMENTED: no samples in segment {}", i);
+            return Err(VgmstreamError::BadHeader { meta: "SEGMENTED", offset: 0, field: "segment samples" });
+        }
+
+        /* loops are handled by the layout, segments just play once */
+        if segment.loop_flag {
+            vgm_log!("SEGMENTED: segment {} is looped", i);
+            segment.loop_flag = false;
+            segment.loop_start_sample = 0;
+            segment.loop_end_sample = 0;
+        }
+
+        segment.setup();
+    }
+
+    let first_channels = data.segments[0].channels;
+    data.input_channels = data.segments.iter().map(|segment| segment.channels).max().unwrap_or(0);
+    data.output_channels = data.input_channels;
+    data.mixed_channels = data.segments.iter().any(|segment| segment.channels != first_channels);
+    data.buffer = vec![0; (VGMSTREAM_SEGMENT_SAMPLE_BUFFER * data.input_channels) as usize];
+
+    return Ok(());
+}
+
+/* Makes a VGMStream for the segments (all samples in sequence), looping from the start of
+ * loop_start_segment to the end of loop_end_segment */
+pub fn allocate_segmented_vgmstream(data: SegmentedLayoutData, loop_flag: bool, loop_start_segment: i32, loop_end_segment: i32) -> Result<VGMStream> {
+    let mut vgmstream = VGMStream::default();
+
+    let mut num_samples = 0;
+    let mut sample_rate = 0;
+    let mut loop_start_sample = 0;
+    let mut loop_end_sample = 0;
+    let mut coding_type = data.segments[0].coding_type;
+
+    for (i, segment) in data.segments.iter().enumerate() {
+        if loop_flag && i as i32 == loop_start_segment {
+            loop_start_sample = num_samples;
+        }
+
+        num_samples += segment.num_samples;
+        sample_rate = sample_rate.max(segment.sample_rate);
+
+        if loop_flag && i as i32 == loop_end_segment {
+            loop_end_sample = num_samples;
+        }
+
+        if coding_type == CodingType::coding_SILENCE {
+            coding_type = segment.coding_type;
+        }
+    }
+
+    if loop_flag && loop_end_sample <= loop_start_sample {
+        return Err(VgmstreamError::BadHeader { meta: "SEGMENTED", offset: 0, field: "loop segments" });
+    }
+
+    vgmstream.channels = data.output_channels;
+    vgmstream.ch = vec![VGMStreamChannel::default(); data.output_channels as usize];
+    vgmstream.sample_rate = sample_rate;
+    vgmstream.num_samples = num_samples;
+    vgmstream.loop_flag = loop_flag;
+    vgmstream.loop_start_sample = loop_start_sample;
+    vgmstream.loop_end_sample = loop_end_sample;
+    vgmstream.meta_type = data.segments[0].meta_type;
+    vgmstream.coding_type = coding_type;
+    vgmstream.layout_type = LayoutType::layout_segmented;
+    vgmstream.segmented_layout_data = Some(data);
+
+    return Ok(vgmstream);
+}
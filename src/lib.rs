@@ -1,3 +1,4 @@
+pub mod error;
 pub mod vgmstream_types;
 pub mod vgmstream;
 pub mod formats;
@@ -13,15 +14,1316 @@ pub mod mixing;
 
 #[cfg(test)]
 mod tests {
+    /* shared builders for test files */
+    mod fixtures {
+        use crate::streamfile::Streamfile;
+        use std::collections::HashMap;
+        use std::rc::Rc;
+
+        pub fn open_memory(filename: &str, data: Vec<u8>) -> Streamfile {
+            Streamfile::from_reader(filename.to_string(), std::io::Cursor::new(data)).unwrap()
+        }
+
+        /* opens the first file of a small virtual filesystem, where companion files can be found */
+        pub fn open_vfs(files: Vec<(&str, Vec<u8>)>) -> Streamfile {
+            let filename = files[0].0.to_string();
+            let files: HashMap<String, Vec<u8>> = files.into_iter().map(|(name, data)| (name.to_string(), data)).collect();
+            let files = Rc::new(files);
+
+            let opener = move |filename: &str| match files.get(filename) {
+                Some(data) => Streamfile::from_reader(filename.to_string(), std::io::Cursor::new(data.clone())),
+                None => Err(crate::error::VgmstreamError::UnknownFormat { filename: filename.to_string() }),
+            };
+            let mut sf = opener(&filename).unwrap();
+            sf.set_opener(Rc::new(opener));
+            sf
+        }
+
+        /* RIFF/WAVE with the chunks in order (no padding, like Wwise) */
+        pub fn make_riff(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+            let mut body = b"WAVE".to_vec();
+            for (id, data) in chunks {
+                body.extend(*id);
+                body.extend((data.len() as u32).to_le_bytes());
+                body.extend(*data);
+            }
+
+            let mut riff = b"RIFF".to_vec();
+            riff.extend((body.len() as u32).to_le_bytes());
+            riff.extend(body);
+            riff
+        }
+
+        /* WAVEFORMATEX, with extra data (including its size) as-is */
+        pub fn make_fmt(format: u16, channels: u16, sample_rate: u32, block_size: u16, bits_per_sample: u16, extra: &[u8]) -> Vec<u8> {
+            let mut fmt = Vec::new();
+            fmt.extend(format.to_le_bytes());
+            fmt.extend(channels.to_le_bytes());
+            fmt.extend(sample_rate.to_le_bytes());
+            fmt.extend((sample_rate * block_size as u32).to_le_bytes());
+            fmt.extend(block_size.to_le_bytes());
+            fmt.extend(bits_per_sample.to_le_bytes());
+            fmt.extend(extra);
+            fmt
+        }
+
+        /* one forward loop, end is inclusive */
+        pub fn make_smpl(loop_start: u32, loop_end: u32) -> Vec<u8> {
+            let mut smpl = vec![0; 0x3c];
+            smpl[0x1c..0x20].copy_from_slice(&1u32.to_le_bytes()); /* loop count */
+            smpl[0x2c..0x30].copy_from_slice(&loop_start.to_le_bytes());
+            smpl[0x30..0x34].copy_from_slice(&loop_end.to_le_bytes());
+            smpl
+        }
+
+        /* cue points as (id, sample position) */
+        pub fn make_cue(points: &[(u32, u32)]) -> Vec<u8> {
+            let mut cue = (points.len() as u32).to_le_bytes().to_vec();
+            for (id, position) in points {
+                cue.extend(id.to_le_bytes());
+                cue.extend(position.to_le_bytes());
+                cue.extend(b"data");
+                cue.extend([0x00; 0x08]);
+                cue.extend(position.to_le_bytes());
+            }
+            cue
+        }
+
+        /* mono 16-bit PCM .wem (silence) */
+        pub fn make_wem(samples: u32) -> Vec<u8> {
+            let fmt = make_fmt(1, 1, 48000, 2, 16, &[]);
+            let data = vec![0; samples as usize * 2];
+            make_riff(&[(b"fmt ", &fmt), (b"data", &data)])
+        }
+
+        /* mono 16-bit PCM .wem whose data chunk says more samples than there are (.bnk prefetch) */
+        pub fn make_wem_prefetch(samples: u32, header_samples: u32) -> Vec<u8> {
+            let data: Vec<u8> = (0..samples as i16).flat_map(|i| (i * 0x10).to_le_bytes()).collect();
+            let fmt = make_fmt(1, 1, 48000, 2, 16, &[]);
+            let mut wem = make_riff(&[(b"fmt ", &fmt), (b"data", &data)]);
+            let data_size_offset = 0x0c + 0x08 + fmt.len() + 0x04;
+            wem[data_size_offset..data_size_offset + 0x04].copy_from_slice(&(header_samples * 2).to_le_bytes());
+            wem
+        }
+
+        /* stereo IMA .wem, 2 blocks: per-channel headers then data interleaved every 2 bytes */
+        pub fn make_wem_ima(extra_chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+            let mut block: Vec<u8> = Vec::new();
+            block.extend(1000i16.to_le_bytes());
+            block.extend([0x00, 0x00]);
+            block.extend((-1000i16).to_le_bytes());
+            block.extend([0x00, 0x00]);
+            for _ in 0..0x10 {
+                block.extend([0x77, 0x77, 0x00, 0x00]);
+            }
+            let data = [block.as_slice(), block.as_slice()].concat();
+
+            let fmt = make_fmt(2, 2, 48000, 0x48, 4, &[0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+            let mut chunks: Vec<(&[u8; 4], &[u8])> = vec![(b"fmt ", &fmt), (b"data", &data)];
+            chunks.extend_from_slice(extra_chunks);
+            make_riff(&chunks)
+        }
+
+        /* mono Vorbis packets from libvorbis' encoder (3 header packets first), with granules */
+        pub fn encode_vorbis(pcm: &[i16], sample_rate: i32) -> Vec<(Vec<u8>, i64)> {
+            use aotuv_lancer_vorbis_sys::*;
+            use ogg_next_sys::ogg_packet;
+
+            fn copy_packet(packets: &mut Vec<(Vec<u8>, i64)>, op: &ogg_packet) {
+                let data = unsafe { std::slice::from_raw_parts(op.packet, op.bytes as usize) };
+                packets.push((data.to_vec(), op.granulepos));
+            }
+
+            fn flush_packets(vd: &mut vorbis_dsp_state, vb: &mut vorbis_block, packets: &mut Vec<(Vec<u8>, i64)>) {
+                unsafe {
+                    let mut op: ogg_packet = std::mem::zeroed();
+                    while vorbis_analysis_blockout(vd, vb) == 1 {
+                        vorbis_analysis(vb, std::ptr::null_mut());
+                        vorbis_bitrate_addblock(vb);
+                        while vorbis_bitrate_flushpacket(vd, &mut op) == 1 {
+                            copy_packet(packets, &op);
+                        }
+                    }
+                }
+            }
+
+            let mut packets = Vec::new();
+            unsafe {
+                let mut vi: vorbis_info = std::mem::zeroed();
+                let mut vc: vorbis_comment = std::mem::zeroed();
+                let mut vd: vorbis_dsp_state = std::mem::zeroed();
+                let mut vb: vorbis_block = std::mem::zeroed();
+
+                vorbis_info_init(&mut vi);
+                assert_eq!(vorbis_encode_init_vbr(&mut vi, 1, sample_rate as _, 0.4), 0);
+                vorbis_comment_init(&mut vc);
+                vorbis_analysis_init(&mut vd, &mut vi);
+                vorbis_block_init(&mut vd, &mut vb);
+
+                let mut headers: [ogg_packet; 3] = std::mem::zeroed();
+                let [id, comment, setup] = &mut headers;
+                vorbis_analysis_headerout(&mut vd, &mut vc, id, comment, setup);
+                for op in &headers {
+                    copy_packet(&mut packets, op);
+                }
+
+                for chunk in pcm.chunks(1024) {
+                    let buffer = vorbis_analysis_buffer(&mut vd, chunk.len() as i32);
+                    let channel = std::slice::from_raw_parts_mut(*buffer, chunk.len());
+                    for (out, sample) in channel.iter_mut().zip(chunk) {
+                        *out = *sample as f32 / 32768.0;
+                    }
+                    vorbis_analysis_wrote(&mut vd, chunk.len() as i32);
+                    flush_packets(&mut vd, &mut vb, &mut packets);
+                }
+                vorbis_analysis_wrote(&mut vd, 0);
+                flush_packets(&mut vd, &mut vb, &mut packets);
+
+                vorbis_block_clear(&mut vb);
+                vorbis_dsp_clear(&mut vd);
+                vorbis_comment_clear(&mut vc);
+                vorbis_info_clear(&mut vi);
+            }
+            packets
+        }
+
+        /* early Wwise Vorbis .wem ("vorb" size 0x28): standard header triad and packets, each with a size + granule header */
+        pub fn make_wem_vorbis(pcm: &[i16], extra_chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+            let packets = encode_vorbis(pcm, 48000);
+
+            let mut data = Vec::new();
+            let mut audio_offset = 0;
+            for (i, (packet, granule)) in packets.iter().enumerate() {
+                data.extend((packet.len() as u32).to_le_bytes());
+                data.extend((*granule as i32).to_le_bytes());
+                data.extend(packet);
+                if i == 2 {
+                    audio_offset = data.len() as u32;
+                }
+            }
+
+            let mut vorb = vec![0; 0x28];
+            vorb[0x00..0x04].copy_from_slice(&(pcm.len() as u32).to_le_bytes());
+            vorb[0x18..0x1c].copy_from_slice(&0u32.to_le_bytes()); /* setup offset */
+            vorb[0x1c..0x20].copy_from_slice(&audio_offset.to_le_bytes());
+
+            let fmt = make_fmt(0xFFFF, 1, 48000, 0, 0, &[]);
+            let mut chunks: Vec<(&[u8; 4], &[u8])> = vec![(b"fmt ", &fmt), (b"vorb", &vorb), (b"data", &data)];
+            chunks.extend_from_slice(extra_chunks);
+            make_riff(&chunks)
+        }
+
+        /* later Wwise Vorbis .wem (fmt extra 0x30): stripped setup referencing external codebooks and modified
+         * packets, converted from libvorbis' output (ww2ogg in reverse); returns the .wem and its .wvc library */
+        pub fn make_wem_vorbis_modified(pcm: &[i16]) -> (Vec<u8>, Vec<u8>) {
+            use crate::coding::vorbis_bitreader::{BitReader, BitWriter};
+
+            fn copy_bits(iw: &mut BitReader, ow: &mut BitWriter, bits: u32) -> u32 {
+                let value = iw.read(bits);
+                ow.write(bits, value);
+                value
+            }
+
+            fn ilog(v: u32) -> u32 {
+                32 - v.leading_zeros()
+            }
+
+            /* Vorbis codebook to Wwise's simplified format, sized like ww2ogg expects (read bits / 8 + 1) */
+            fn convert_codebook(iw: &mut BitReader) -> Vec<u8> {
+                let mut buf = vec![0; 0x10000];
+                let mut ow = BitWriter::new(&mut buf);
+
+                assert_eq!(iw.read(24), 0x564342); /* "VCB" */
+                let dimensions = iw.read(16);
+                let entries = iw.read(24);
+                assert!(dimensions < 0x10 && entries < 0x4000);
+                ow.write(4, dimensions);
+                ow.write(14, entries);
+
+                let ordered = copy_bits(iw, &mut ow, 1);
+                if ordered != 0 {
+                    copy_bits(iw, &mut ow, 5);
+                    let mut current_entry = 0;
+                    while current_entry < entries {
+                        current_entry += copy_bits(iw, &mut ow, ilog(entries - current_entry));
+                    }
+                } else {
+                    let sparse = iw.read(1);
+                    let lengths: Vec<Option<u32>> = (0..entries)
+                        .map(|_| if sparse == 0 || iw.read(1) != 0 { Some(iw.read(5)) } else { None })
+                        .collect();
+                    let length_bits = ilog(lengths.iter().flatten().copied().max().unwrap_or(0)).max(1);
+
+                    ow.write(3, length_bits);
+                    ow.write(1, sparse);
+                    for length in lengths {
+                        if sparse != 0 {
+                            ow.write(1, length.is_some() as u32);
+                        }
+                        if let Some(length) = length {
+                            ow.write(length_bits, length);
+                        }
+                    }
+                }
+
+                let lookup_type = iw.read(4);
+                assert!(lookup_type <= 1);
+                ow.write(1, lookup_type);
+                if lookup_type == 1 {
+                    copy_bits(iw, &mut ow, 32); /* min */
+                    copy_bits(iw, &mut ow, 32); /* max */
+                    let value_length = copy_bits(iw, &mut ow, 4);
+                    copy_bits(iw, &mut ow, 1); /* sequence flag */
+
+                    let mut quantvals = 1u32;
+                    while (quantvals as u64 + 1).pow(dimensions) <= entries as u64 {
+                        quantvals += 1;
+                    }
+                    for _ in 0..quantvals {
+                        copy_bits(iw, &mut ow, value_length + 1);
+                    }
+                }
+
+                let size = ow.b_off / 8 + 1;
+                buf.truncate(size);
+                buf
+            }
+
+            let packets = encode_vorbis(pcm, 48000);
+            let (id, setup) = (&packets[0].0, &packets[2].0);
+            assert_eq!(&setup[0x00..0x07], b"\x05vorbis");
+
+            let mut library = Vec::new();
+            let mut offsets = Vec::new();
+            let mut wwise_setup = vec![0; setup.len()];
+            let mut iw = BitReader::new(&setup[0x07..]);
+            let mut ow = BitWriter::new(&mut wwise_setup);
+
+            /* codebooks, moved to the library and referenced by id */
+            let codebook_count = copy_bits(&mut iw, &mut ow, 8) + 1;
+            for codebook_id in 0..codebook_count {
+                offsets.push(library.len() as u32);
+                library.extend(convert_codebook(&mut iw));
+                ow.write(10, codebook_id);
+            }
+
+            /* time domain transforms (removed) */
+            let time_count = iw.read(6) + 1;
+            for _ in 0..time_count {
+                assert_eq!(iw.read(16), 0);
+            }
+
+            /* floors (type removed) */
+            let floor_count = copy_bits(&mut iw, &mut ow, 6) + 1;
+            for _ in 0..floor_count {
+                assert_eq!(iw.read(16), 1);
+                let partitions = copy_bits(&mut iw, &mut ow, 5);
+                let classes: Vec<u32> = (0..partitions).map(|_| copy_bits(&mut iw, &mut ow, 4)).collect();
+                let max_class = classes.iter().copied().max().unwrap_or(0);
+
+                let mut class_dimensions = Vec::new();
+                for _ in 0..=max_class {
+                    class_dimensions.push(copy_bits(&mut iw, &mut ow, 3) + 1);
+                    let subclasses = copy_bits(&mut iw, &mut ow, 2);
+                    if subclasses != 0 {
+                        copy_bits(&mut iw, &mut ow, 8); /* masterbook */
+                    }
+                    for _ in 0..(1 << subclasses) {
+                        copy_bits(&mut iw, &mut ow, 8); /* subclass book + 1 */
+                    }
+                }
+
+                copy_bits(&mut iw, &mut ow, 2); /* multiplier - 1 */
+                let rangebits = copy_bits(&mut iw, &mut ow, 4);
+                for class in classes {
+                    for _ in 0..class_dimensions[class as usize] {
+                        copy_bits(&mut iw, &mut ow, rangebits);
+                    }
+                }
+            }
+
+            /* residues (type reduced to 2 bits) */
+            let residue_count = copy_bits(&mut iw, &mut ow, 6) + 1;
+            for _ in 0..residue_count {
+                let residue_type = iw.read(16);
+                ow.write(2, residue_type);
+                copy_bits(&mut iw, &mut ow, 24); /* begin */
+                copy_bits(&mut iw, &mut ow, 24); /* end */
+                copy_bits(&mut iw, &mut ow, 24); /* partition size - 1 */
+                let classifications = copy_bits(&mut iw, &mut ow, 6) + 1;
+                copy_bits(&mut iw, &mut ow, 8); /* classbook */
+
+                let mut cascade = Vec::new();
+                for _ in 0..classifications {
+                    let low_bits = copy_bits(&mut iw, &mut ow, 3);
+                    let high_bits = if copy_bits(&mut iw, &mut ow, 1) != 0 { copy_bits(&mut iw, &mut ow, 5) } else { 0 };
+                    cascade.push(high_bits * 8 + low_bits);
+                }
+                for bits in cascade {
+                    for _ in 0..bits.count_ones() {
+                        copy_bits(&mut iw, &mut ow, 8); /* residue book */
+                    }
+                }
+            }
+
+            /* mappings (type removed), mono so no coupling bits */
+            let mapping_count = copy_bits(&mut iw, &mut ow, 6) + 1;
+            for _ in 0..mapping_count {
+                assert_eq!(iw.read(16), 0);
+                let submaps = if copy_bits(&mut iw, &mut ow, 1) != 0 { copy_bits(&mut iw, &mut ow, 4) + 1 } else { 1 };
+                assert_eq!(copy_bits(&mut iw, &mut ow, 1), 0); /* square polar */
+                copy_bits(&mut iw, &mut ow, 2); /* reserved */
+                if submaps > 1 {
+                    copy_bits(&mut iw, &mut ow, 4); /* mux */
+                }
+                for _ in 0..submaps {
+                    copy_bits(&mut iw, &mut ow, 8); /* time config */
+                    copy_bits(&mut iw, &mut ow, 8); /* floor */
+                    copy_bits(&mut iw, &mut ow, 8); /* residue */
+                }
+            }
+
+            /* modes (window and transform types removed) */
+            let mode_count = copy_bits(&mut iw, &mut ow, 6) + 1;
+            let mut mode_blockflag = Vec::new();
+            for _ in 0..mode_count {
+                mode_blockflag.push(copy_bits(&mut iw, &mut ow, 1) != 0);
+                assert_eq!(iw.read(16), 0);
+                assert_eq!(iw.read(16), 0);
+                copy_bits(&mut iw, &mut ow, 8); /* mapping */
+            }
+            assert_eq!(iw.read(1), 1); /* framing (removed) */
+
+            let setup_size = ow.b_off.div_ceil(8);
+            wwise_setup.truncate(setup_size);
+
+            /* library: codebooks then their offsets, the last being the table start */
+            let table_start = library.len() as u32;
+            for offset in offsets {
+                library.extend(offset.to_le_bytes());
+            }
+            library.extend(table_start.to_le_bytes());
+
+            /* setup and audio packets with a size header */
+            let mut data = Vec::new();
+            data.extend((wwise_setup.len() as u16).to_le_bytes());
+            data.extend(&wwise_setup);
+            let audio_offset = data.len() as u32;
+
+            let mode_bits = ilog(mode_count - 1);
+            for (packet, _) in &packets[3..] {
+                let mut buf = vec![0; packet.len()];
+                let mut iw = BitReader::new(packet);
+                let mut ow = BitWriter::new(&mut buf);
+
+                /* remove packet type and window flags (rebuilt from the mode's blockflags) */
+                assert_eq!(iw.read(1), 0);
+                let mode_number = copy_bits(&mut iw, &mut ow, mode_bits);
+                if mode_blockflag[mode_number as usize] {
+                    iw.read(2);
+                }
+                while iw.b_off < packet.len() * 8 {
+                    copy_bits(&mut iw, &mut ow, 1);
+                }
+
+                let size = ow.b_off.div_ceil(8);
+                data.extend((size as u16).to_le_bytes());
+                data.extend(&buf[..size]);
+            }
+
+            let mut extra = vec![0; 0x32];
+            /* extra size, channel layout, then Vorbis fields from fmt + 0x18 */
+            extra[0x00..0x02].copy_from_slice(&0x30u16.to_le_bytes());
+            extra[0x08..0x0c].copy_from_slice(&(pcm.len() as u32).to_le_bytes());
+            extra[0x18..0x1c].copy_from_slice(&0u32.to_le_bytes()); /* setup offset */
+            extra[0x1c..0x20].copy_from_slice(&audio_offset.to_le_bytes());
+            extra[0x30] = id[0x1c] & 0x0F; /* small blocksize */
+            extra[0x31] = id[0x1c] >> 4; /* big blocksize */
+
+            let fmt = make_fmt(0xFFFF, 1, 48000, 0, 0, &extra);
+            (make_riff(&[(b"fmt ", &fmt), (b"data", &data)]), library)
+        }
+
+        /* AFS2 with waveids 0..N, each subfile aligned to 0x20 (offsets point to the padding before them) */
+        pub fn make_awb(subfiles: &[&[u8]]) -> Vec<u8> {
+            let count = subfiles.len();
+            let mut offsets = vec![0x10 + count * 0x02 + (count + 1) * 0x04];
+            for subfile in subfiles {
+                offsets.push(offsets[offsets.len() - 1].next_multiple_of(0x20) + subfile.len());
+            }
+
+            let mut awb = b"AFS2".to_vec();
+            awb.extend([0x01, 0x04, 0x02, 0x00]);
+            awb.extend((count as u32).to_le_bytes());
+            awb.extend(0x20u16.to_le_bytes());
+            awb.extend(0u16.to_le_bytes()); /* subkey */
+            for waveid in 0..count as u16 {
+                awb.extend(waveid.to_le_bytes());
+            }
+            for offset in &offsets {
+                awb.extend((*offset as u32).to_le_bytes());
+            }
+            for (subfile, offset) in subfiles.iter().zip(&offsets) {
+                awb.resize(offset.next_multiple_of(0x20), 0);
+                awb.extend(*subfile);
+            }
+            awb
+        }
+
+        /* test tone */
+        pub fn make_sine(samples: usize) -> Vec<i16> {
+            (0..samples).map(|i| ((i as f64 * 440.0 * 2.0 * std::f64::consts::PI / 48000.0).sin() * 8000.0) as i16).collect()
+        }
+
+        /* minimal @UTF table, column types taken from the first row */
+        pub enum UtfCell {
+            U8(u8),
+            U16(u16),
+            Str(&'static str),
+            Data(Vec<u8>),
+        }
+
+        pub fn make_utf(name: &str, columns: &[&str], rows: Vec<Vec<UtfCell>>) -> Vec<u8> {
+            let mut strings: Vec<u8> = b"<NULL>\0".to_vec();
+            let add_string = |strings: &mut Vec<u8>, s: &str| {
+                let offset = strings.len() as u32;
+                strings.extend(s.as_bytes());
+                strings.push(0);
+                offset
+            };
+            let name_offset = add_string(&mut strings, name);
+
+            let mut schema = Vec::new();
+            let mut row_width = 0;
+            for (column, cell) in columns.iter().zip(rows[0].iter()) {
+                let (column_type, size) = match cell {
+                    UtfCell::U8(_) => (0x00, 1),
+                    UtfCell::U16(_) => (0x02, 2),
+                    UtfCell::Str(_) => (0x0a, 4),
+                    UtfCell::Data(_) => (0x0b, 8),
+                };
+                schema.push(0x50 | column_type); /* name + per-row value */
+                schema.extend(add_string(&mut strings, column).to_be_bytes());
+                row_width += size;
+            }
+
+            let mut row_data = Vec::new();
+            let mut data: Vec<u8> = Vec::new();
+            for row in rows.iter() {
+                for cell in row.iter() {
+                    match cell {
+                        UtfCell::U8(v) => row_data.push(*v),
+                        UtfCell::U16(v) => row_data.extend(v.to_be_bytes()),
+                        UtfCell::Str(v) => row_data.extend(add_string(&mut strings, v).to_be_bytes()),
+                        UtfCell::Data(v) => {
+                            row_data.extend((data.len() as u32).to_be_bytes());
+                            row_data.extend((v.len() as u32).to_be_bytes());
+                            data.extend(v);
+                        }
+                    }
+                }
+            }
+
+            let rows_offset = 0x20 + schema.len();
+            let strings_offset = rows_offset + row_data.len();
+            let data_offset = strings_offset + strings.len();
+            let mut utf = b"@UTF".to_vec();
+            utf.extend(((data_offset + data.len() - 0x08) as u32).to_be_bytes());
+            utf.extend(0x0001u16.to_be_bytes());
+            utf.extend((rows_offset as u16 - 0x08).to_be_bytes());
+            utf.extend((strings_offset as u32 - 0x08).to_be_bytes());
+            utf.extend((data_offset as u32 - 0x08).to_be_bytes());
+            utf.extend(name_offset.to_be_bytes());
+            utf.extend((columns.len() as u16).to_be_bytes());
+            utf.extend((row_width as u16).to_be_bytes());
+            utf.extend((rows.len() as u32).to_be_bytes());
+            utf.extend(schema);
+            utf.extend(row_data);
+            utf.extend(strings);
+            utf.extend(data);
+            utf
+        }
+    }
+
+    use fixtures::*;
+
     #[test]
     fn wwise_vorbis() {
-        // let vorbis_data = include_bytes!("../test_data/wem/474329706.wem");
-        // let wav_data = include_bytes!("../test_data/wem/474329706.wav");
+        use crate::render::render_vgmstream;
+        use crate::vgmstream::VGMStream;
+
+        let pcm = make_sine(48000);
+        let mut sf = open_memory("test.wem", make_wem_vorbis(&pcm, &[]));
+        let mut vgmstream = VGMStream::init_from_streamfile(&mut sf).unwrap();
+        assert_eq!(vgmstream.channels, 1);
+        assert_eq!(vgmstream.sample_rate, 48000);
+        assert_eq!(vgmstream.num_samples, 48000);
+
+        /* lossy, but must follow the source closely */
+        let mut buffer: Vec<i16> = vec![0; pcm.len()];
+        render_vgmstream(&mut buffer, pcm.len() as i32, &mut vgmstream);
+        let max_diff = buffer.iter().zip(&pcm).map(|(a, b)| (*a as i32 - *b as i32).abs()).max().unwrap();
+        assert!(max_diff < 0x200);
+    }
+
+    #[test]
+    fn wwise_vorbis_modified() {
+        use crate::render::render_vgmstream;
+        use crate::vgmstream::VGMStream;
+
+        /* codebooks come from the companion .wvc, setup and packets are rebuilt by ww2ogg's code */
+        let pcm = make_sine(48000);
+        let (wem, wvc) = make_wem_vorbis_modified(&pcm);
+        let mut sf = open_vfs(vec![("test.wem", wem), ("test.wvc", wvc)]);
+        let mut vgmstream = VGMStream::init_from_streamfile(&mut sf).unwrap();
+        assert_eq!(vgmstream.channels, 1);
+        assert_eq!(vgmstream.num_samples, 48000);
+
+        let mut buffer: Vec<i16> = vec![0; pcm.len()];
+        render_vgmstream(&mut buffer, pcm.len() as i32, &mut vgmstream);
+        let max_diff = buffer.iter().zip(&pcm).map(|(a, b)| (*a as i32 - *b as i32).abs()).max().unwrap();
+        assert!(max_diff < 0x200);
+    }
+
+    #[test]
+    fn play_config() {
+        use crate::vgmstream::{VGMStream, PlayConfig};
+
+        let mut vgmstream = VGMStream {
+            num_samples: 1000,
+            sample_rate: 100,
+            channels: 1,
+            loop_flag: true,
+            loop_start_sample: 200,
+            loop_end_sample: 800,
+            ..Default::default()
+        };
+
+        let config = PlayConfig {
+            loop_count: 2.0,
+            loop_count_set: 1,
+            fade_time: 1.0,
+            pad_begin: 10,
+            ..Default::default()
+        };
+        vgmstream.apply_config(&config);
+
+        /* pad begin + intro + 2 loops + fade */
+        assert_eq!(vgmstream.get_samples(), 10 + 200 + 600 * 2 + 100);
+        assert_eq!(vgmstream.get_samples(), vgmstream.get_play_samples(2.0, 1.0, 0.0) + 10);
+    }
+
+    #[test]
+    fn reset_replay() {
+        use crate::render::render_vgmstream;
+
+        let mut vgmstream = crate::vgmstream::VGMStream::init("test_data/adx/mono.adx".to_string()).unwrap();
+        let samples = 4096;
+
+        let mut first: Vec<i16> = vec![0; samples * vgmstream.channels as usize];
+        render_vgmstream(&mut first, samples as i32, &mut vgmstream);
+
+        vgmstream.reset();
+        assert_eq!(vgmstream.current_sample, 0);
+
+        let mut second: Vec<i16> = vec![0; samples * vgmstream.channels as usize];
+        render_vgmstream(&mut second, samples as i32, &mut vgmstream);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn reapply_config() {
+        use crate::render::render_vgmstream;
+        use crate::vgmstream::{VGMStream, PlayConfig};
+
+        let mut vgmstream = VGMStream::init("test_data/adx/mono.adx".to_string()).unwrap();
+        let num_samples = vgmstream.num_samples;
+        let loop_start = num_samples / 4;
+        let loop_end = num_samples / 2;
+        vgmstream.force_loop(true, loop_start, loop_end);
+
+        let config = PlayConfig {
+            ignore_loop: 1,
+            ..Default::default()
+        };
+        vgmstream.apply_config(&config);
+        assert!(!vgmstream.loop_flag);
+        assert_eq!(vgmstream.get_samples(), num_samples);
+
+        let mut buffer: Vec<i16> = vec![0; num_samples as usize];
+        render_vgmstream(&mut buffer, num_samples, &mut vgmstream);
+
+        /* loops must come back from the initial state, not the ignore_loop one */
+        let config = PlayConfig {
+            loop_count: 2.0,
+            loop_count_set: 1,
+            ..Default::default()
+        };
+        vgmstream.apply_config(&config);
+        assert!(vgmstream.loop_flag);
+        assert_eq!(vgmstream.loop_start_sample, loop_start);
+        assert_eq!(vgmstream.loop_end_sample, loop_end);
+        assert_eq!(vgmstream.get_samples(), loop_start + (loop_end - loop_start) * 2);
+
+        let samples = vgmstream.get_samples();
+        let mut looped: Vec<i16> = vec![0; samples as usize];
+        render_vgmstream(&mut looped, samples, &mut vgmstream);
+        assert_eq!(looped[..loop_end as usize], buffer[..loop_end as usize]);
+    }
+
+    #[test]
+    fn init_errors() {
+        use crate::error::VgmstreamError;
+        use crate::vgmstream::VGMStream;
+
+        let result = VGMStream::init("test_data/missing.adx".to_string());
+        assert!(matches!(result, Err(VgmstreamError::Io(_))));
+
+        let result = VGMStream::init("Cargo.toml".to_string());
+        assert!(matches!(result, Err(VgmstreamError::UnknownFormat { .. })));
+    }
+
+    #[test]
+    fn subsongs() {
+        use crate::error::VgmstreamError;
+        use crate::vgmstream::VGMStream;
+
+        let subsongs = VGMStream::list_subsongs("test_data/adx/mono.adx".to_string()).unwrap();
+        assert_eq!(subsongs.len(), 1);
+        assert_eq!(subsongs[0].index, 1);
+
+        assert!(VGMStream::open_subsong("test_data/adx/mono.adx".to_string(), 1).is_ok());
+        let result = VGMStream::open_subsong("test_data/adx/mono.adx".to_string(), 2);
+        assert!(matches!(result, Err(VgmstreamError::InvalidSubsong { index: 2, total: 1 })));
+    }
+
+    #[test]
+    fn subsongs_with_errors() {
+        use crate::vgmstream::VGMStream;
+
+        /* an unsupported subsong (HCA) is listed with its error instead of failing the whole list */
+        let adx = std::fs::read("test_data/adx/mono.adx").unwrap();
+        let mut hca = b"HCA\0".to_vec();
+        hca.resize(0x60, 0);
+        let mut sf = open_memory("test.awb", make_awb(&[&hca, &adx]));
+
+        let subsongs = VGMStream::list_subsongs_from_streamfile(&mut sf).unwrap();
+        assert_eq!(subsongs.len(), 2);
+        assert!(subsongs[0].error.as_ref().unwrap().contains("HCA"));
+        assert_eq!(subsongs[1].error, None);
+
+        assert!(VGMStream::init_subsong_from_streamfile(&mut sf, 2).is_ok());
+    }
+
+    #[test]
+    fn wwise_bnk() {
+        use crate::vgmstream::VGMStream;
+
+        let wems = [(1234u32, make_wem(100)), (5678u32, make_wem(200)), (9012u32, make_wem_prefetch(50, 0x8000))];
+
+        let mut didx: Vec<u8> = Vec::new();
+        let mut data: Vec<u8> = Vec::new();
+        for (id, wem) in &wems {
+            didx.extend_from_slice(&id.to_le_bytes());
+            didx.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            didx.extend_from_slice(&(wem.len() as u32).to_le_bytes());
+            data.extend_from_slice(wem);
+            data.resize((data.len() + 0x0f) & !0x0f, 0);
+        }
+
+        let mut bnk: Vec<u8> = Vec::new();
+        bnk.extend_from_slice(b"BKHD");
+        bnk.extend_from_slice(&0x08u32.to_le_bytes());
+        bnk.extend_from_slice(&0x78u32.to_le_bytes()); /* version */
+        bnk.extend_from_slice(&0x01u32.to_le_bytes()); /* bank id */
+        bnk.extend_from_slice(b"DIDX");
+        bnk.extend_from_slice(&(didx.len() as u32).to_le_bytes());
+        bnk.extend_from_slice(&didx);
+        bnk.extend_from_slice(b"DATA");
+        bnk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        bnk.extend_from_slice(&data);
+
+        let mut sf = open_memory("test.bnk", bnk);
+        let subsongs = VGMStream::list_subsongs_from_streamfile(&mut sf).unwrap();
+        assert_eq!(subsongs.len(), 3);
+        assert_eq!(subsongs[0].name, "1234");
+        assert_eq!(subsongs[1].name, "5678");
+        assert_eq!(subsongs[2].name, "9012 [pre]");
+
+        let vgmstream = VGMStream::init_subsong_from_streamfile(&mut sf, 2).unwrap();
+        assert_eq!(vgmstream.num_streams, 3);
+        assert_eq!(vgmstream.num_samples, 200);
+    }
+
+    #[test]
+    fn wwise_markers() {
+        use crate::vgmstream::VGMStream;
+
+        let cue = make_cue(&[(2, 500), (1, 250)]);
+
+        let mut list = b"adtl".to_vec();
+        list.extend(b"labl");
+        list.extend(0x09u32.to_le_bytes());
+        list.extend(2u32.to_le_bytes());
+        list.extend(b"sync\0\0");
+
+        let fmt = make_fmt(1, 1, 48000, 2, 16, &[]);
+        let data = vec![0; 1000 * 2];
+        let wem = make_riff(&[(b"fmt ", &fmt), (b"data", &data), (b"smpl", &make_smpl(100, 899)), (b"cue ", &cue), (b"LIST", &list)]);
+
+        let mut sf = open_memory("test.wem", wem);
+        let vgmstream = VGMStream::init_from_streamfile(&mut sf).unwrap();
+        assert!(vgmstream.loop_flag);
+        assert_eq!(vgmstream.loop_start_sample, 100);
+        assert_eq!(vgmstream.loop_end_sample, 900);
+        assert_eq!(vgmstream.markers.len(), 2);
+        assert_eq!(vgmstream.markers[0].position, 250);
+        assert_eq!(vgmstream.markers[0].name, "");
+        assert_eq!(vgmstream.markers[1].position, 500);
+        assert_eq!(vgmstream.markers[1].name, "sync");
+
+        /* bogus labl size is ignored rather than read */
+        let mut list = b"adtl".to_vec();
+        list.extend(b"labl");
+        list.extend(0xFFFFFFF0u32.to_le_bytes());
+        list.extend(2u32.to_le_bytes());
+        list.extend(b"sync\0\0");
+        let wem = make_riff(&[(b"fmt ", &fmt), (b"data", &data), (b"cue ", &cue), (b"LIST", &list)]);
+
+        let mut sf = open_memory("test.wem", wem);
+        let vgmstream = VGMStream::init_from_streamfile(&mut sf).unwrap();
+        assert_eq!(vgmstream.markers.len(), 2);
+        assert_eq!(vgmstream.markers[1].name, "");
+    }
+
+    #[test]
+    fn wwise_encoder_delay() {
+        use crate::meta::wwise::wwise_apply_encoder_delay;
+        use crate::vgmstream::VGMStream;
+
+        let fmt = make_fmt(1, 1, 48000, 2, 16, &[]);
+        let data = vec![0; 1000 * 2];
+        let cue = make_cue(&[(1, 250), (2, 50)]);
+        let wem = make_riff(&[(b"fmt ", &fmt), (b"data", &data), (b"smpl", &make_smpl(100, 899)), (b"cue ", &cue)]);
+
+        let mut sf = open_memory("test.wem", wem);
+        let mut vgmstream = VGMStream::init_from_streamfile(&mut sf).unwrap();
+        wwise_apply_encoder_delay(&mut vgmstream, 80);
+        assert_eq!(vgmstream.loop_start_sample, 20);
+        assert_eq!(vgmstream.loop_end_sample, 820);
+        assert_eq!(vgmstream.markers[0].position, 0);
+        assert_eq!(vgmstream.markers[1].position, 170);
+    }
+
+    #[test]
+    fn wwise_ima() {
+        use crate::render::render_vgmstream;
+        use crate::vgmstream::VGMStream;
+
+        let wem = make_wem_ima(&[]);
+        let mut sf = open_memory("test.wem", wem);
+        let mut vgmstream = VGMStream::init_from_streamfile(&mut sf).unwrap();
+        assert_eq!(vgmstream.num_samples, 128);
+
+        let mut buffer: Vec<i16> = vec![0; 128 * 2];
+        render_vgmstream(&mut buffer, 128, &mut vgmstream);
+        assert_eq!(buffer[0], 1011);
+        assert_eq!(buffer[1], -1000);
+        assert_eq!(buffer[128], 1011); /* second block resets from its header */
+    }
+
+    #[test]
+    fn xma_start_skip() {
+        use crate::coding::coding_utils::xma_get_start_skip;
+
+        /* mono: fixed 512 subframe, skip 512 */
+        let packet = [0x00, 0x00, 0x00, 0x00, 0x00, 0xC9, 0x00, 0x70, 0x00];
+        assert_eq!(xma_get_start_skip(&packet, 1), Some(512));
+
+        /* stereo: frame at bit 3, variable subframes (128+256+128 / 128+128+256), channel matrix, skip 320 */
+        let packet = [0x04, 0x00, 0x18, 0x00, 0xE0, 0x32, 0x1F, 0x5D, 0xD5, 0x55, 0x55, 0x6A, 0x00];
+        assert_eq!(xma_get_start_skip(&packet, 2), Some(320));
+        assert_eq!(xma_get_start_skip(&packet[..8], 2), None);
+    }
+    #[test]
+    fn wwise_dsp() {
+        use crate::render::render_vgmstream;
+        use crate::vgmstream::VGMStream;
+
+        /* coefs: index 0 = 1.0*hist1, index 1 = 0.5*hist1 (11-bit fixed point) */
+        let mut extra: Vec<u8> = Vec::new();
+        extra.extend(0x3au16.to_le_bytes()); /* 0x0c + 0x2e * channels */
+        extra.extend([0x00, 0x00]);
+        extra.extend(0x04u32.to_le_bytes()); /* channel layout */
+        extra.extend(28i32.to_le_bytes()); /* num samples */
+        let mut coefs = [0i16; 16];
+        coefs[0] = 2048;
+        coefs[2] = 1024;
+        for coef in coefs {
+            extra.extend(coef.to_le_bytes());
+        }
+        extra.extend([0x00, 0x00, 0x00, 0x00]); /* gain + initial ps */
+        extra.extend(100i16.to_le_bytes()); /* initial hist1 */
+        extra.extend(0i16.to_le_bytes()); /* initial hist2 */
+        extra.extend([0x00; 0x06]); /* loop ps/hist */
+        extra.extend([0x00, 0x00]);
+
+        /* scale 1 + coef 0, then scale 8 + coef 1 */
+        let data = [
+            0x00, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x13, 0xF7, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let fmt = make_fmt(0xFFF0, 1, 32000, 0x08, 4, &extra);
+        let wem = make_riff(&[(b"fmt ", &fmt), (b"data", &data)]);
+
+        let mut sf = open_memory("test.wem", wem);
+        let mut vgmstream = VGMStream::init_from_streamfile(&mut sf).unwrap();
+        assert_eq!(vgmstream.num_samples, 28);
+
+        let mut buffer: Vec<i16> = vec![0; 28];
+        render_vgmstream(&mut buffer, 28, &mut vgmstream);
+        assert_eq!(buffer[0..3], [101, 103, 103]);
+        assert_eq!(buffer[13], 103);
+        assert_eq!(buffer[14..17], [44, 78, 39]);
+    }
+
+    #[test]
+    fn wwise_ptadpcm() {
+        use crate::render::render_vgmstream;
+        use crate::vgmstream::VGMStream;
+
+        /* hist2 + hist1 + index, then nibbles (low first) */
+        let mut data: Vec<u8> = Vec::new();
+        data.extend(10i16.to_le_bytes());
+        data.extend(20i16.to_le_bytes());
+        data.push(0x00);
+        data.extend([0xF7, 0x08]);
+        data.extend([0x00; 0x1d]);
+
+        /* bad index, clamped to a silent step */
+        data.extend(100i16.to_le_bytes());
+        data.extend((-100i16).to_le_bytes());
+        data.push(0xFF);
+        data.extend([0x0F]);
+        data.extend([0x00; 0x1e]);
+
+        let fmt = make_fmt(0x8311, 1, 48000, 0x24, 4, &[0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        let wem = make_riff(&[(b"fmt ", &fmt), (b"data", &data)]);
+
+        let mut sf = open_memory("test.wem", wem);
+        let mut vgmstream = VGMStream::init_from_streamfile(&mut sf).unwrap();
+        assert_eq!(vgmstream.num_samples, 128);
+
+        let mut buffer: Vec<i16> = vec![0; 128];
+        render_vgmstream(&mut buffer, 128, &mut vgmstream);
+        assert_eq!(buffer[0..6], [10, 20, 30, 54, 80, 78]);
+        assert_eq!(buffer[64..68], [100, -100, -300, -514]);
+    }
+    #[test]
+    fn loop_render() {
+        use crate::render::render_vgmstream;
+        use crate::vgmstream::VGMStream;
+
+        /* ADX: after the loop end samples must repeat from loop start (with the saved ADPCM history) */
+        let mut vgmstream = VGMStream::init("test_data/adx/mono.adx".to_string()).unwrap();
+        let loop_start = 10000;
+        let loop_end = 30000;
+        vgmstream.force_loop(true, loop_start, loop_end);
+
+        let samples = loop_end + 8192;
+        let mut buffer: Vec<i16> = vec![0; samples as usize];
+        render_vgmstream(&mut buffer, samples, &mut vgmstream);
+        assert_eq!(buffer[loop_end as usize..], buffer[loop_start as usize..(loop_start + 8192) as usize]);
+
+        /* IMA: loop points in the middle of blocks */
+        let wem = make_wem_ima(&[(b"smpl", &make_smpl(2, 99))]);
+        let mut sf = open_memory("test.wem", wem);
+        let mut vgmstream = VGMStream::init_from_streamfile(&mut sf).unwrap();
+        assert!(vgmstream.loop_flag);
+        assert_eq!(vgmstream.loop_start_sample, 2);
+        assert_eq!(vgmstream.loop_end_sample, 100);
+
+        let mut buffer: Vec<i16> = vec![0; 198 * 2];
+        render_vgmstream(&mut buffer, 198, &mut vgmstream);
+        assert_eq!(buffer[100 * 2..], buffer[2 * 2..100 * 2]);
+        assert_ne!(buffer[100 * 2], buffer[99 * 2]);
+    }
+
+    /* seeks to positions before, inside and past the loop, and within the fade, and compares with a linear render */
+    fn assert_seeks(vgmstream: &mut crate::vgmstream::VGMStream) {
+        use crate::render::render_vgmstream;
+        use crate::vgmstream::PlayConfig;
+
+        let config = PlayConfig {
+            loop_count: 2.0,
+            loop_count_set: 1,
+            fade_time: 0.1,
+            ..Default::default()
+        };
+        vgmstream.apply_config(&config);
+
+        let channels = vgmstream.channels as usize;
+        let samples = vgmstream.get_samples();
+        let mut linear: Vec<i16> = vec![0; samples as usize * channels];
+        render_vgmstream(&mut linear, samples, vgmstream);
+
+        let loop_start = vgmstream.loop_start_sample;
+        let loop_body = vgmstream.loop_end_sample - loop_start;
+        let fade_start = vgmstream.pstate.fade_start;
+        let positions = [
+            100,
+            loop_start - 10,                /* crosses the loop start */
+            loop_start + loop_body / 2,
+            loop_start + loop_body - 10,    /* crosses the loop end */
+            loop_start + loop_body * 3 / 2, /* second loop */
+            fade_start + 100,
+            100,                            /* back */
+        ];
+        for position in positions {
+            let to_do = 1024.min(samples - position);
+            vgmstream.seek(position);
+            let mut buffer: Vec<i16> = vec![0; to_do as usize * channels];
+            render_vgmstream(&mut buffer, to_do, vgmstream);
+
+            let start = position as usize * channels;
+            assert_eq!(buffer, linear[start..start + buffer.len()], "seek to {}", position);
+        }
+    }
+
+    #[test]
+    fn seek_loop() {
+        use crate::vgmstream::VGMStream;
+
+        /* decoded and discarded */
+        let mut vgmstream = VGMStream::init("test_data/adx/mono.adx".to_string()).unwrap();
+        vgmstream.force_loop(true, 10000, 30000);
+        assert_seeks(&mut vgmstream);
+
+        /* codec seeking */
+        let pcm = make_sine(48000);
+        let mut sf = open_memory("test.wem", make_wem_vorbis(&pcm, &[(b"smpl", &make_smpl(10000, 29999))]));
+        let mut vgmstream = VGMStream::init_from_streamfile(&mut sf).unwrap();
+        assert_seeks(&mut vgmstream);
+    }
+
+    #[test]
+    fn wwise_pcm24() {
+        use crate::render::render_vgmstream;
+        use crate::vgmstream::VGMStream;
+
+        /* stereo 24-bit, interleaved per sample */
+        let mut data: Vec<u8> = Vec::new();
+        for i in 0..10i32 {
+            data.extend_from_slice(&(i * 0x100 * 0x100).to_le_bytes()[..3]);
+            data.extend_from_slice(&(-i * 0x100 * 0x100).to_le_bytes()[..3]);
+        }
+        let fmt = make_fmt(1, 2, 48000, 6, 24, &[]);
+        let wem = make_riff(&[(b"fmt ", &fmt), (b"data", &data)]);
+
+        let mut sf = open_memory("test.wem", wem);
+        let mut vgmstream = VGMStream::init_from_streamfile(&mut sf).unwrap();
+        assert_eq!(vgmstream.num_samples, 10);
+
+        let mut buffer: Vec<i16> = vec![0; 10 * 2];
+        render_vgmstream(&mut buffer, 10, &mut vgmstream);
+        assert_eq!(buffer[2], 0x100);
+        assert_eq!(buffer[3], -0x100);
+        assert_eq!(buffer[18], 0x900);
+        assert_eq!(buffer[19], -0x900);
+    }
+
+    #[test]
+    fn wwise_prefetch() {
+        use crate::render::render_vgmstream;
+        use crate::vgmstream::VGMStream;
+
+        /* .bnk prefetch: data chunk says 0x8000 samples but only the first 100 are in the file */
+        let wem = make_wem_prefetch(100, 0x8000);
+
+        let mut sf = open_memory("test.wem", wem);
+        let mut vgmstream = VGMStream::init_from_streamfile(&mut sf).unwrap();
+        assert_eq!(vgmstream.num_samples, 100);
+
+        let mut buffer: Vec<i16> = vec![0; 100];
+        render_vgmstream(&mut buffer, 100, &mut vgmstream);
+        assert_eq!(buffer[1], 0x10);
+        assert_eq!(buffer[99], 99 * 0x10);
+    }
+
+    #[test]
+    fn streamfile_reader() {
+        use crate::error::VgmstreamError;
+        use crate::streamfile::{read_streamfile, read_u32be, read_u32le};
+
+        let data: Vec<u8> = (0..0x10000u32).map(|i| i as u8).collect();
+        let mut sf = open_memory("test.bin", data);
+        assert_eq!(sf.get_size(std::ptr::null_mut()), 0x10000);
+
+        /* crosses the buffer window */
+        assert_eq!(read_u32be(&mut sf, 0x7FFE).unwrap(), 0xFEFF0001);
+        assert_eq!(read_u32le(&mut sf, 0x0000).unwrap(), 0x03020100);
+
+        /* past EOF */
+        assert!(matches!(read_u32le(&mut sf, 0xFFFE), Err(VgmstreamError::Truncated { .. })));
+        let mut buf = [0xAA; 4];
+        assert_eq!(read_streamfile(&mut sf, 0xFFFE, &mut buf), 2);
+        assert_eq!(buf, [0xFE, 0xFF, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn streamfile_companion() {
+        use crate::streamfile::{open_streamfile_by_ext, open_streamfile_by_filename, read_u8};
+
+        let sf = open_vfs(vec![
+            ("vfs/bgm.acb", vec![1]),
+            ("vfs/bgm.awb", vec![2]),
+            ("vfs/sub/.txth", vec![3]),
+        ]);
+
+        let mut sf_awb = open_streamfile_by_ext(&sf, "awb").unwrap();
+        assert_eq!(sf_awb.name, "vfs/bgm.awb");
+        assert_eq!(read_u8(&mut sf_awb, 0x00).unwrap(), 2);
+
+        let mut sf_txth = open_streamfile_by_filename(&sf_awb, "sub/.txth").unwrap();
+        assert_eq!(read_u8(&mut sf_txth, 0x00).unwrap(), 3);
+
+        assert!(open_streamfile_by_ext(&sf, "mib").is_err());
+    }
+
+    #[test]
+    fn log_callback() {
+        use crate::util::log::{vgm_log_disable, vgm_log_set_callback, LogLevel, LogOutput};
+        use std::sync::{Arc, Mutex};
+
+        let messages: Arc<Mutex<Vec<(LogLevel, String)>>> = Arc::new(Mutex::new(Vec::new()));
+        let captured = messages.clone();
+        vgm_log_set_callback(LogLevel::Info, LogOutput::Callback(Arc::new(move |level, msg| {
+            captured.lock().unwrap().push((level, msg.to_string()));
+            /* callbacks may log or change the logger without deadlocking */
+            match msg {
+                "TEST: info 1" => crate::vgm_logi!("TEST: nested"),
+                "TEST: stop" => vgm_log_disable(),
+                _ => {}
+            }
+        })));
+
+        crate::vgm_logi!("TEST: info {}", 1);
+        crate::vgm_log!("TEST: debug {}", 2); /* above level, ignored */
+        crate::vgm_logi!("TEST: stop");
+        crate::vgm_logi!("TEST: disabled");
+
+        let messages = messages.lock().unwrap();
+        let expected = ["TEST: info 1", "TEST: nested", "TEST: stop"].map(|msg| (LogLevel::Info, msg.to_string()));
+        assert_eq!(*messages, expected);
+    }
+
+    #[test]
+    fn adx_encrypted() {
+        use crate::meta::adx_keys::derive_adx_key8;
+        use crate::render::render_vgmstream;
+        use crate::vgmstream::VGMStream;
+
+        assert_eq!(derive_adx_key8("karaage"), (0x49e1, 0x4a57, 0x553d));
+
+        /* encrypt scales of a mono file with a known type 8 key, which must be guessed back */
+        let plain = std::fs::read("test_data/adx/mono.adx").unwrap();
+        let mut encrypted = plain.clone();
+        encrypted[0x12..0x14].copy_from_slice(&0x0408u16.to_be_bytes());
+        let (mut xor, mult, add) = derive_adx_key8("karaage");
+        for frame in encrypted[0x120..].chunks_exact_mut(0x12) {
+            let scale = u16::from_be_bytes([frame[0], frame[1]]) ^ xor;
+            frame[0..2].copy_from_slice(&scale.to_be_bytes());
+            xor = xor.wrapping_mul(mult).wrapping_add(add) & 0x7fff;
+        }
+
+        let mut samples = Vec::new();
+        for data in [plain, encrypted] {
+            let mut sf = open_memory("test.adx", data);
+            let mut vgmstream = VGMStream::init_from_streamfile(&mut sf).unwrap();
+            let mut buffer: Vec<i16> = vec![0; 0x8000];
+            render_vgmstream(&mut buffer, 0x8000, &mut vgmstream);
+            samples.push(buffer);
+        }
+        assert_eq!(samples[0], samples[1]);
+    }
+
+    #[test]
+    fn adx_key_search() {
+        use crate::meta::adx::find_adx_key_candidates;
+        use crate::meta::adx_keys::derive_adx_key9;
+
+        /* unknown type 9 key, recovered from the silent frames at the start */
+        let mut encrypted = std::fs::read("test_data/adx/mono.adx").unwrap();
+        encrypted[0x12..0x14].copy_from_slice(&0x0409u16.to_be_bytes());
+        let key = derive_adx_key9(0x0123456789abcdef);
+        let (mut xor, mult, add) = key;
+        for frame in encrypted[0x120..].chunks_exact_mut(0x12) {
+            let scale = u16::from_be_bytes([frame[0], frame[1]]) ^ xor;
+            frame[0..2].copy_from_slice(&scale.to_be_bytes());
+            xor = xor.wrapping_mul(mult).wrapping_add(add) & 0x7fff;
+        }
+
+        let mut sf = open_memory("test.adx", encrypted);
+        assert_eq!(find_adx_key_candidates(&mut sf).unwrap(), vec![key]);
+    }
+
+    #[test]
+    fn ahx_frames() {
+        use crate::coding::coding::MpegCustomConfig;
+        use crate::coding::mpeg_custom_utils_ahx::{ahx_get_frame_end, ahx_rebuild_frames, mpeg_get_layer2_frame_size};
+
+        /* MPEG-2 Layer II mono 22050hz 160kbps */
+        let header = 0xFFF5E0C0u32;
+        let full_size = mpeg_get_layer2_frame_size(header).unwrap();
+        assert_eq!(full_size, 0x414);
+
+        /* trimmed frames, then the end marker */
+        let mut data: Vec<u8> = Vec::new();
+        data.extend(header.to_be_bytes());
+        data.extend([0x11; 0x0a]);
+        data.extend(header.to_be_bytes());
+        data.extend([0x22; 0x14]);
+        data.extend(b"AHXE(c)CRI\0\0");
+
+        assert_eq!(ahx_get_frame_end(&data, 0x00, header, full_size), 0x0e);
+        assert_eq!(ahx_get_frame_end(&data, 0x0e, header, full_size), 0x26);
+
+        let stream = ahx_rebuild_frames(&data, header, full_size, &MpegCustomConfig::default());
+        assert_eq!(stream.len(), 2 * full_size);
+        assert_eq!(stream[0x0d], 0x11);
+        assert_eq!(stream[0x0e], 0x00);
+        assert_eq!(stream[full_size..full_size + 0x04], header.to_be_bytes());
+        assert_eq!(stream[full_size + 0x17], 0x22);
+        assert_eq!(stream[full_size + 0x18], 0x00);
+    }
+
+    #[test]
+    fn ahx_decrypt() {
+        use crate::coding::coding::MpegCustomConfig;
+        use crate::coding::mpeg_custom_utils_ahx::ahx_decrypt_type08;
+
+        let cfg = MpegCustomConfig { encryption: 0x08, cri_type: 0x11, cri_key1: 0x002D, cri_key2: 0x7FFF, cri_key3: 0x7FFF };
+
+        /* key index 1 at bit 107, then 3 pairs XOR'd with key1 from the low bits */
+        let mut frame = [0u8; 0x20];
+        frame[0x0d] = 0x08;
+        ahx_decrypt_type08(&mut frame, &cfg);
+        assert_eq!(frame[0x0d], 0x0B);
+        assert_eq!(frame[0x0e], 0xC0);
+
+        /* XOR is symmetric */
+        ahx_decrypt_type08(&mut frame, &cfg);
+        assert_eq!(frame[0x0d], 0x08);
+        assert_eq!(frame[0x0e], 0x00);
+
+        /* index 0 is unencrypted */
+        let mut frame = [0u8; 0x20];
+        frame[0x0e] = 0xC0;
+        ahx_decrypt_type08(&mut frame, &cfg);
+        assert_eq!(frame[0x0d], 0x00);
+        assert_eq!(frame[0x0e], 0xC0);
+    }
+    #[test]
+    fn aix_layered() {
+        use crate::render::render_vgmstream;
+        use crate::vgmstream::VGMStream;
+
+        /* 2 segments with 2 mono layers each, blocks interleaved per layer */
+        let adx = std::fs::read("test_data/adx/mono.adx").unwrap();
+        let adx_samples = u32::from_be_bytes([adx[0x0c], adx[0x0d], adx[0x0e], adx[0x0f]]);
+
+        let mut segment = Vec::new();
+        for chunk in adx.chunks(0x1000) {
+            for layer in 0..2u8 {
+                segment.extend(b"AIXP");
+                segment.extend((chunk.len() as u32 + 0x08).to_be_bytes());
+                segment.extend([layer, 2, 0x00, 0x10, 0, 0, 0, 0]);
+                segment.extend(chunk);
+            }
+        }
+        segment.extend(b"AIXE");
+        segment.extend(0x08u32.to_be_bytes());
+        segment.extend([0; 0x08]);
+
+        let mut aix = vec![0; 0x800];
+        aix[0x00..0x04].copy_from_slice(b"AIXF");
+        aix[0x08..0x0c].copy_from_slice(&0x01000014u32.to_be_bytes());
+        aix[0x0c..0x10].copy_from_slice(&0x800u32.to_be_bytes());
+        aix[0x18..0x1a].copy_from_slice(&2u16.to_be_bytes());
+        for i in 0..2 {
+            let entry = 0x20 + i * 0x10;
+            aix[entry..entry + 0x04].copy_from_slice(&(0x800 + (i * segment.len()) as u32).to_be_bytes());
+            aix[entry + 0x04..entry + 0x08].copy_from_slice(&(segment.len() as u32).to_be_bytes());
+            aix[entry + 0x08..entry + 0x0c].copy_from_slice(&adx_samples.to_be_bytes());
+            aix[entry + 0x0c..entry + 0x10].copy_from_slice(&adx[0x08..0x0c]);
+        }
+        aix[0x50] = 2; /* layers */
+        aix.extend(&segment);
+        aix.extend(&segment);
+        let aix_size = aix.len() as u32 - 0x08;
+        aix[0x04..0x08].copy_from_slice(&aix_size.to_be_bytes());
+
+        let mut sf = open_memory("test.adx", adx);
+        let mut mono = VGMStream::init_from_streamfile(&mut sf).unwrap();
+        let mut mono_buffer: Vec<i16> = vec![0; 0x8000];
+        render_vgmstream(&mut mono_buffer, 0x8000, &mut mono);
+
+        let mut sf = open_memory("test.aix", aix);
+        let mut vgmstream = VGMStream::init_from_streamfile(&mut sf).unwrap();
+        assert_eq!(vgmstream.channels, 2);
+        assert_eq!(vgmstream.num_samples, adx_samples as i32 * 2);
+        assert!(vgmstream.loop_flag);
+        assert_eq!((vgmstream.loop_start_sample, vgmstream.loop_end_sample), (adx_samples as i32, adx_samples as i32 * 2));
+
+        let mut buffer: Vec<i16> = vec![0; 0x8000 * 2];
+        render_vgmstream(&mut buffer, 0x8000, &mut vgmstream);
+        let left: Vec<i16> = buffer.iter().step_by(2).copied().collect();
+        let right: Vec<i16> = buffer.iter().skip(1).step_by(2).copied().collect();
+        assert_eq!(left, mono_buffer);
+        assert_eq!(right, mono_buffer);
+    }
+
+    #[test]
+    fn awb_acb_names() {
+        use crate::vgmstream::VGMStream;
+        use UtfCell::*;
+
+        /* AWB with 2 ADX waveforms */
+        let adx = std::fs::read("test_data/adx/mono.adx").unwrap();
+        let awb = make_awb(&[&adx, &adx]);
+
+        /* ACB: cue 0 > waveform 1, cue 1 > synth 0 > waveform 0, cue 2 > memory waveform (ignored) */
+        let cuenames = make_utf("CueName", &["CueName", "CueIndex"], vec![
+            vec![Str("bgm_intro"), U16(0)],
+            vec![Str("bgm_loop"), U16(1)],
+            vec![Str("se_memory"), U16(2)],
+        ]);
+        let cues = make_utf("Cue", &["ReferenceType", "ReferenceIndex"], vec![
+            vec![U8(1), U16(1)],
+            vec![U8(2), U16(0)],
+            vec![U8(1), U16(2)],
+        ]);
+        let synths = make_utf("Synth", &["ReferenceItems"], vec![
+            vec![Data(vec![0x00, 0x01, 0x00, 0x00])],
+        ]);
+        let waveforms = make_utf("Waveform", &["MemoryAwbId", "StreamAwbId", "Streaming"], vec![
+            vec![U16(0xffff), U16(0), U8(1)],
+            vec![U16(0xffff), U16(1), U8(1)],
+            vec![U16(0), U16(0xffff), U8(0)],
+        ]);
+        let acb = make_utf("Header", &["CueNameTable", "CueTable", "SynthTable", "WaveformTable"], vec![
+            vec![Data(cuenames), Data(cues), Data(synths), Data(waveforms)],
+        ]);
+
+        let mut sf = open_vfs(vec![("bgm.awb", awb), ("bgm.acb", acb)]);
+
+        let subsongs = VGMStream::list_subsongs_from_streamfile(&mut sf).unwrap();
+        let names: Vec<&str> = subsongs.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["bgm_loop", "bgm_intro"]);
 
-        let vgmstream = crate::vgmstream::VGMStream::init("../test_data/wem/474329706.wem".to_string());
-        // println!("{:?}", vgmstream);
-        // assert_eq!(vgmstream.sample_rate, 48000);
-        // assert_eq!(vgmstream.channels, 2);
+        let vgmstream = VGMStream::init_subsong_from_streamfile(&mut sf, 2).unwrap();
+        assert_eq!(vgmstream.num_streams, 2);
+        assert_eq!(vgmstream.num_samples, 0x3b6000);
     }
 
     // #[test]
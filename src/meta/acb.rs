@@ -0,0 +1,202 @@
+use crate::vgm_log;
+use crate::streamfile::*;
+use crate::util::cri_utf::UtfTable;
+
+/* ACB (CRI's Atom Cue sheet Binary) info, to find cue names for AWB waveforms.
+ * An ACB is a @UTF table with subtables, where cues point to waveforms through a chain like:
+ *   CueName > Cue > Sequence > Track > TrackEvent/Command > Synth > Waveform
+ * (some steps may be skipped or repeated), and a waveform's id is the AWB subsong index. */
+
+const ACB_MAX_DEPTH: i32 = 3; /* nested synths/sequences (unsure if real files go deeper) */
+const ACB_MAX_NAMES: usize = 25; /* some waveforms are reused by lots of cues */
+
+struct AcbData<'a> {
+    sf: &'a mut Streamfile,
+    is_memory: bool,
+    target_waveid: u16,
+
+    cue_table: Option<UtfTable>,
+    sequence_table: Option<UtfTable>,
+    track_table: Option<UtfTable>,
+    track_command_table: Option<UtfTable>,
+    synth_table: Option<UtfTable>,
+    waveform_table: Option<UtfTable>,
+
+    cue_name: String, /* current cue being followed */
+    names: Vec<String>,
+}
+
+impl AcbData<'_> {
+    fn load_waveform(&mut self, index: u16) {
+        let Some(table) = self.waveform_table.as_ref() else { return };
+        let index = index as usize;
+
+        /* older: Id, newer: MemoryAwbId + StreamAwbId (0xFFFF if not set) */
+        let id = match table.query_u16(index, "Id") {
+            Some(id) => id,
+            None if self.is_memory => table.query_u16(index, "MemoryAwbId").unwrap_or(0xffff),
+            None => table.query_u16(index, "StreamAwbId").unwrap_or(0xffff),
+        };
+
+        /* 0: memory, 1: streamed, 2: streamed with a memory prefetch */
+        let streaming = table.query_u8(index, "Streaming").unwrap_or(0);
+        if (self.is_memory && streaming == 1) || (!self.is_memory && streaming == 0) {
+            return;
+        }
+
+        if id != self.target_waveid {
+            return;
+        }
+
+        /* multiple cues may use the same waveform */
+        if self.names.len() < ACB_MAX_NAMES && !self.names.contains(&self.cue_name) {
+            self.names.push(self.cue_name.clone());
+        }
+    }
+
+    fn load_synth(&mut self, index: u16, depth: i32) {
+        if depth > ACB_MAX_DEPTH {
+            vgm_log!("ACB: max synth depth reached");
+            return;
+        }
+        let Some(table) = self.synth_table.as_ref() else { return };
+
+        /* list of u16 type + u16 index */
+        let Some((items_offset, items_size)) = table.query_data(index as usize, "ReferenceItems") else { return };
+        let Ok(items) = read_exact_bytes(self.sf, items_offset, items_size) else { return };
+
+        for item in items.chunks_exact(0x04) {
+            let item_type = u16::from_be_bytes([item[0], item[1]]);
+            let item_index = u16::from_be_bytes([item[2], item[3]]);
+
+            match item_type {
+                0x00 => break, /* no reference */
+                0x01 => self.load_waveform(item_index),
+                0x02 => self.load_synth(item_index, depth + 1),
+                0x03 => self.load_sequence(item_index, depth + 1),
+                _ => vgm_log!("ACB: unknown synth item type {:x}", item_type),
+            }
+        }
+    }
+
+    fn load_track_command(&mut self, index: u16, depth: i32) {
+        let Some(table) = self.track_command_table.as_ref() else { return };
+
+        /* list of TLV commands (many kinds), only some reference synths/sequences */
+        let Some((command_offset, command_size)) = table.query_data(index as usize, "Command") else { return };
+        let Ok(commands) = read_exact_bytes(self.sf, command_offset, command_size) else { return };
+
+        let mut offset = 0;
+        while offset + 0x03 <= commands.len() {
+            let tlv_code = u16::from_be_bytes([commands[offset + 0x00], commands[offset + 0x01]]);
+            let tlv_size = commands[offset + 0x02] as usize;
+            offset += 0x03;
+
+            if offset + tlv_size > commands.len() {
+                break;
+            }
+
+            /* 2000 (note on) / 2003 (note on with cycle), same reference as synth items */
+            if (tlv_code == 0x07d0 || tlv_code == 0x07d3) && tlv_size >= 0x04 {
+                let tlv_type = u16::from_be_bytes([commands[offset + 0x00], commands[offset + 0x01]]);
+                let tlv_index = u16::from_be_bytes([commands[offset + 0x02], commands[offset + 0x03]]);
+
+                match tlv_type {
+                    0x02 => self.load_synth(tlv_index, depth + 1),
+                    0x03 => self.load_sequence(tlv_index, depth + 1),
+                    _ => vgm_log!("ACB: unknown command type {:x}", tlv_type),
+                }
+            }
+
+            offset += tlv_size;
+        }
+    }
+
+    fn load_track(&mut self, index: u16, depth: i32) {
+        let Some(table) = self.track_table.as_ref() else { return };
+
+        let event_index = table.query_u16(index as usize, "EventIndex").unwrap_or(0xffff);
+        if event_index == 0xffff { /* no event (seen in some empty tracks) */
+            return;
+        }
+
+        self.load_track_command(event_index, depth);
+    }
+
+    fn load_sequence(&mut self, index: u16, depth: i32) {
+        if depth > ACB_MAX_DEPTH {
+            vgm_log!("ACB: max sequence depth reached");
+            return;
+        }
+        let Some(table) = self.sequence_table.as_ref() else { return };
+
+        /* list of u16 track indexes */
+        let num_tracks = table.query_u16(index as usize, "NumTracks").unwrap_or(0) as usize;
+        let Some((tracks_offset, tracks_size)) = table.query_data(index as usize, "TrackIndex") else { return };
+        if num_tracks * 0x02 > tracks_size {
+            vgm_log!("ACB: wrong sequence tracks");
+            return;
+        }
+        let Ok(tracks) = read_exact_bytes(self.sf, tracks_offset, num_tracks * 0x02) else { return };
+
+        for track in tracks.chunks_exact(0x02) {
+            self.load_track(u16::from_be_bytes([track[0], track[1]]), depth);
+        }
+    }
+
+    fn load_cue(&mut self, index: u16) {
+        let Some(table) = self.cue_table.as_ref() else { return };
+
+        let Some(reference_type) = table.query_u8(index as usize, "ReferenceType") else { return };
+        let Some(reference_index) = table.query_u16(index as usize, "ReferenceIndex") else { return };
+
+        match reference_type {
+            0x01 => self.load_waveform(reference_index), /* Cue > Waveform (ex. PES 2017) */
+            0x02 => self.load_synth(reference_index, 0), /* Cue > Synth > Waveform (ex. Ukiyo no Roushi) */
+            0x03 => self.load_sequence(reference_index, 0), /* Cue > Sequence > Track > Command > Synth > Waveform (ex. Valkyrie Profile anatomia) */
+            _ => vgm_log!("ACB: unknown cue reference type {:x}", reference_type), /* 0x08: block sequence (rare) */
+        }
+    }
+}
+
+/* Returns cue names that use waveid (as "name1; name2; ..."), or None if not found.
+ * is_memory tells if waveid is from the ACB's internal AWB or an external (stream) AWB. */
+pub fn load_acb_wave_name(sf_acb: &mut Streamfile, waveid: u16, is_memory: bool) -> Option<String> {
+    let header = UtfTable::open(sf_acb, 0x00).ok()?;
+    if header.name != "Header" {
+        return None;
+    }
+
+    let cuename_table = header.open_subtable(sf_acb, 0, "CueNameTable")?;
+
+    /* older ACBs use CommandTable, newer TrackEventTable */
+    let track_command_column = if header.has_column("TrackEventTable") { "TrackEventTable" } else { "CommandTable" };
+
+    let mut acb = AcbData {
+        is_memory,
+        target_waveid: waveid,
+        cue_table: header.open_subtable(sf_acb, 0, "CueTable"),
+        sequence_table: header.open_subtable(sf_acb, 0, "SequenceTable"),
+        track_table: header.open_subtable(sf_acb, 0, "TrackTable"),
+        track_command_table: header.open_subtable(sf_acb, 0, track_command_column),
+        synth_table: header.open_subtable(sf_acb, 0, "SynthTable"),
+        waveform_table: header.open_subtable(sf_acb, 0, "WaveformTable"),
+        sf: sf_acb,
+        cue_name: String::new(),
+        names: Vec::new(),
+    };
+
+    /* follow all cue names to see which ones end up using the waveform */
+    for row in 0..cuename_table.rows {
+        let Some(cue_name) = cuename_table.query_string(row, "CueName") else { continue };
+        let Some(cue_index) = cuename_table.query_u16(row, "CueIndex") else { continue };
+
+        acb.cue_name = cue_name;
+        acb.load_cue(cue_index);
+    }
+
+    if acb.names.is_empty() {
+        return None;
+    }
+    return Some(acb.names.join("; "));
+}
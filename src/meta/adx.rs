@@ -1,29 +1,38 @@
+use crate::vgm_logi;
+use crate::coding::adx::adx_next_key;
+use crate::error::{Result, VgmstreamError};
+use crate::meta::adx_keys::*;
 use crate::streamfile::*;
+use crate::util::reader::{get_u16be, get_u64be};
 use crate::vgmstream::*;
 use libm::*;
 
-pub fn init_vgmstream_adx(sf: &mut Streamfile) -> Option<VGMStream> {
+pub fn init_vgmstream_adx(sf: &mut Streamfile) -> Result<VGMStream> {
     return init_vgmstream_adx_subkey(sf, 0);
 }
 
-fn init_vgmstream_adx_subkey(sf: &mut Streamfile, subkey: u16) -> Option<VGMStream> {
+/* subkey comes from AWB containers, which may modify type 9 keycodes per file */
+pub fn init_vgmstream_adx_subkey(sf: &mut Streamfile, subkey: u16) -> Result<VGMStream> {
     let mut vgmstream = VGMStream::default();
     
-    if read_u16be(sf, 0x0) != 0x8000 {
-        return None;
+    if read_u16be(sf, 0x0)? != 0x8000 {
+        return Err(VgmstreamError::UnknownFormat { filename: sf.name.clone() });
     }
 
     if !check_extensions(sf, vec!["adx", "adp"]) {
-        return None;
+        return Err(VgmstreamError::UnknownFormat { filename: sf.name.clone() });
     }
 
-    let start_offset = read_u16be(sf, 0x02) + 0x04;
-    if read_u16be(sf, (start_offset - 0x06).into()) != 0x2863 ||     /* "(c" */
-        read_u32be(sf, (start_offset - 0x04).into()) != 0x29435249   /* ")CRI" */
+    let start_offset = read_u16be(sf, 0x02)? as usize + 0x04;
+    if start_offset < 0x06 + 0x14 {
+        return Err(VgmstreamError::BadHeader { meta: "ADX", offset: 0x02, field: "header size" });
+    }
+    if read_u16be(sf, start_offset - 0x06)? != 0x2863 ||     /* "(c" */
+        read_u32be(sf, start_offset - 0x04)? != 0x29435249   /* ")CRI" */
     {
-        return None;
+        return Err(VgmstreamError::UnknownFormat { filename: sf.name.clone() });
     }
-    let encoding_type = read_u8(sf, 0x04);
+    let encoding_type = read_u8(sf, 0x04)?;
     let coding_type: CodingType;
     match encoding_type {
         0x02 => {
@@ -36,7 +45,7 @@ fn init_vgmstream_adx_subkey(sf: &mut Streamfile, subkey: u16) -> Option<VGMStre
             coding_type = CodingType::coding_CRI_ADX_exp;
         }
         _ => { /* 0x10 is AHX for DC, 0x11 is AHX */
-            return None;
+            return Err(VgmstreamError::UnsupportedCodec { meta: "ADX", codec: format!("encoding type 0x{:x}", encoding_type) });
         }
     }
 
@@ -44,35 +53,45 @@ fn init_vgmstream_adx_subkey(sf: &mut Streamfile, subkey: u16) -> Option<VGMStre
      * though output is unusual and may not be fully supported (works in mono so not an interleave)
      * Later versions of the decode just use constant 0x12 ignoring it, though. */
 
-    let frame_size = read_u8(sf, 0x05) as isize;
+    let frame_size = read_u8(sf, 0x05)? as isize;
 
-    if read_u8(sf, 0x06) != 4 { /* bits per sample */
-        return None;
+    if read_u8(sf, 0x06)? != 4 { /* bits per sample */
+        return Err(VgmstreamError::BadHeader { meta: "ADX", offset: 0x06, field: "bits per sample" });
     }
 
-    let channels = read_u8(sf, 0x07);
-    let sample_rate = read_s32be(sf, 0x08);
-    let num_samples = read_s32be(sf, 0x0c);
-    let cutoff = read_u16be(sf, 0x10); /* high-pass cutoff frequency, always 500 */
-    let version = read_u16be(sf, 0x12); /* version + revision, originally read as separate */
-
-    // TODO: ADX encryption
+    let channels = read_u8(sf, 0x07)?;
+    let sample_rate = read_s32be(sf, 0x08)?;
+    let num_samples = read_s32be(sf, 0x0c)?;
+    let cutoff = read_u16be(sf, 0x10)?; /* high-pass cutoff frequency, always 500 */
+    let version = read_u16be(sf, 0x12)?; /* version + revision, originally read as separate */
 
-    // if version == 0x0408 {
+    if channels == 0 {
+        return Err(VgmstreamError::BadHeader { meta: "ADX", offset: 0x07, field: "channels" });
+    }
 
-    //     if (!find_adx_key(sf, 8, &xor_start, &xor_mult, &xor_add, 0)) {
-    //         vgm_logi("ADX: decryption keystring not found\n");
-    //     }
-    //     coding_type = CodingType::coding_CRI_ADX_enc_8;
-    //     version = 0x0400;
-    // }
-    // else if version == 0x0409 {
-    //     if (!find_adx_key(sf, 9, &xor_start, &xor_mult, &xor_add, subkey)) {
-    //         vgm_logi("ADX: decryption keycode not found\n");
-    //     }
-    //     coding_type = CodingType::coding_CRI_ADX_enc_9;
-    //     version = 0x0400;
-    // }
+    let mut coding_type = coding_type;
+    let mut version = version;
+    let mut xor_start = 0;
+    let mut xor_mult = 0;
+    let mut xor_add = 0;
+
+    /* encryption */
+    if version == 0x0408 {
+        match find_adx_key(sf, 8, 0)? {
+            Some((start, mult, add)) => (xor_start, xor_mult, xor_add) = (start, mult, add),
+            None => vgm_logi!("ADX: decryption keystring not found"),
+        }
+        coding_type = CodingType::coding_CRI_ADX_enc_8;
+        version = 0x0400;
+    }
+    else if version == 0x0409 {
+        match find_adx_key(sf, 9, subkey)? {
+            Some((start, mult, add)) => (xor_start, xor_mult, xor_add) = (start, mult, add),
+            None => vgm_logi!("ADX: decryption keycode not found"),
+        }
+        coding_type = CodingType::coding_CRI_ADX_enc_9;
+        version = 0x0400;
+    }
 
     let header_type: MetaType;
 
@@ -87,15 +106,15 @@ fn init_vgmstream_adx_subkey(sf: &mut Streamfile, subkey: u16) -> Option<VGMStre
 
         header_type = MetaType::meta_ADX_03;
 
-        if start_offset - 0x06 >= (base_size + loops_size) as u16 { /* enough space for loop info? */
+        if start_offset - 0x06 >= base_size + loops_size { /* enough space for loop info? */
             let loops_offset = base_size;
 
             /* 0x00 (2): initial loop padding (the encoder adds a few blank samples so loop start is block-aligned; max 31)
              *  ex. loop_start=12: enc_start=32, padding=20 (32-20=12); loop_start=35: enc_start=64, padding=29 (64-29=35)
              * 0x02 (2): loop flag? (always 1) */
-            loop_flag           = read_s32be(sf, loops_offset+0x04) != 0; /* loop count + loop type? (always 1) */
-            loop_start_sample   = read_s32be(sf, loops_offset+0x08);
-            loop_end_sample     = read_s32be(sf, loops_offset+0x10);
+            loop_flag           = read_s32be(sf, loops_offset+0x04)? != 0; /* loop count + loop type? (always 1) */
+            loop_start_sample   = read_s32be(sf, loops_offset+0x08)?;
+            loop_end_sample     = read_s32be(sf, loops_offset+0x10)?;
         }
     }
     else if version == 0x0400 {  /* common */
@@ -118,18 +137,19 @@ fn init_vgmstream_adx_subkey(sf: &mut Streamfile, subkey: u16) -> Option<VGMStre
 
         ainf_offset = base_size + hist_size as usize + 0x04; /* not seen with >2ch though */
         if is_id32be(sf, ainf_offset+0x00, "AINF") {
-            ainf_size = read_u32be(sf, ainf_offset+0x04);
+            ainf_size = read_u32be(sf, ainf_offset+0x04)? as usize;
         }
 
-        if (start_offset as u32 - ainf_size - 0x06) as usize >= (hist_offset + hist_size as usize + loops_size) as usize {  /* enough space for loop info? */
+        if start_offset >= ainf_size + 0x06
+            && start_offset - ainf_size - 0x06 >= hist_offset + hist_size as usize + loops_size {  /* enough space for loop info? */
             let loops_offset = base_size + hist_size as usize;
 
             /* 0x00 (2): initial loop padding (the encoder adds a few blank samples so loop start is block-aligned; max 31)
              *  ex. loop_start=12: enc_start=32, padding=20 (32-20=12); loop_start=35: enc_start=64, padding=29 (64-29=35)
              * 0x02 (2): loop flag? (always 1) */
-            loop_flag           = read_s32be(sf, loops_offset+0x04) != 0; /* loop count + loop type? (always 1) */
-            loop_start_sample   = read_s32be(sf, loops_offset+0x08);
-            loop_end_sample     = read_s32be(sf, loops_offset+0x10);
+            loop_flag           = read_s32be(sf, loops_offset+0x04)? != 0; /* loop count + loop type? (always 1) */
+            loop_start_sample   = read_s32be(sf, loops_offset+0x08)?;
+            loop_end_sample     = read_s32be(sf, loops_offset+0x10)?;
         }
 
         /* AINF header info (may be inserted by CRI's tools but is rarely used)
@@ -153,7 +173,7 @@ fn init_vgmstream_adx_subkey(sf: &mut Streamfile, subkey: u16) -> Option<VGMStre
         header_type = MetaType::meta_ADX_05;
     }
     else { /* not a known/supported version signature */
-        return None;
+        return Err(VgmstreamError::BadHeader { meta: "ADX", offset: 0x12, field: "version" });
     }
 
     vgmstream.sample_rate = sample_rate;
@@ -208,23 +228,278 @@ fn init_vgmstream_adx_subkey(sf: &mut Streamfile, subkey: u16) -> Option<VGMStre
 
     for i in 0..vgmstream.ch.len() {
         if hist_offset != 0 {
-            vgmstream.ch[i].adpcm_history1_32 = read_s16be(sf, hist_offset + i*4 + 0x00) as i32;
-            vgmstream.ch[i].adpcm_history2_32 = read_s16be(sf, hist_offset + i*4 + 0x02) as i32;
+            vgmstream.ch[i].adpcm_history1_32 = read_s16be(sf, hist_offset + i*4 + 0x00)? as i32;
+            vgmstream.ch[i].adpcm_history2_32 = read_s16be(sf, hist_offset + i*4 + 0x02)? as i32;
+        }
+
+        if coding_type == CodingType::coding_CRI_ADX_enc_8 || coding_type == CodingType::coding_CRI_ADX_enc_9 {
+            vgmstream.ch[i].adx_channels = channels as i32;
+            vgmstream.ch[i].adx_xor = xor_start;
+            vgmstream.ch[i].adx_mult = xor_mult;
+            vgmstream.ch[i].adx_add = xor_add;
+
+            /* frames are interleaved, so each channel's key starts one step after the previous */
+            for _ in 0..i {
+                adx_next_key(&mut vgmstream.ch[i]);
+            }
+        }
+    }
+
+    vgmstream.open_stream(sf, start_offset as isize)?;
+
+    return Ok(vgmstream);
+}
+
+/* max frames used to test keys, enough to reject wrong ones */
+const ADX_BRUTE_FRAMES: usize = 0x400;
+
+/* Recovers possible XOR keys (start/mult/add) of an encrypted ADX from its frame scales.
+ * Real scales never use the top bits, so those bits of encrypted scales are the key's.
+ * Silent frames (no nibbles) are usually encoded with scale 0, so their encrypted scale is
+ * the full XOR value, and 3 in a row are enough to solve mult and add. If the file has no
+ * silence, type 8 keys can still be found by testing all prime-table combinations.
+ * Returns all keys that match the first frames, best candidates first (may be empty). */
+pub fn find_adx_key_candidates(sf: &mut Streamfile) -> Result<Vec<(u16, u16, u16)>> {
+    let start_offset = read_u16be(sf, 0x02)? as usize + 0x04;
+    let channels = read_u8(sf, 0x07)? as usize;
+    let num_samples = read_u32be(sf, 0x0c)? as usize;
+    let (enc_type, keymask) = match read_u16be(sf, 0x12)? {
+        0x0408 => (8, 0x6000),
+        0x0409 => (9, 0x1000),
+        _ => return Err(VgmstreamError::BadHeader { meta: "ADX", offset: 0x12, field: "version (not encrypted)" }),
+    };
+
+    /* keys step once per frame in file order (channels are interleaved) */
+    let frame_count = std::cmp::min((num_samples + 31) / 32 * channels, ADX_BRUTE_FRAMES);
+    /* (checked like upstream's "prescales", as blank frames may have scale 0) */
+    let mut scales: Vec<u16> = Vec::with_capacity(frame_count);
+    let mut silent: Vec<bool> = Vec::with_capacity(frame_count);
+    for i in 0..frame_count {
+        let mut frame = [0u8; 0x12];
+        if read_streamfile(sf, start_offset + i * 0x12, &mut frame) != frame.len() {
+            break;
+        }
+        scales.push(get_u16be(&frame[0x00..]));
+        silent.push(frame[0x02..].iter().all(|&c| c == 0));
+    }
+
+    let is_valid_key = |start: u16, mult: u16, add: u16| -> bool {
+        if enc_type == 8 {
+            return KEY8_PRIMES.contains(&start) && KEY8_PRIMES.contains(&mult) && KEY8_PRIMES.contains(&add);
+        }
+        /* type 9 derivation always sets mult's low bits to 01 and add's low bit */
+        return mult & 0x03 == 0x01 && add & 0x01 == 0x01;
+    };
+
+    let mut candidates: Vec<(u16, u16, u16)> = Vec::new();
+
+    /* solve from silent frames: x2 - x1 = mult * (x1 - x0) and x1 = x0 * mult + add (mod 2^15) */
+    for p in 0..scales.len().saturating_sub(2) {
+        if !(silent[p] && silent[p + 1] && silent[p + 2]) {
+            continue;
+        }
+        let (x0, x1, x2) = (scales[p] as u32, scales[p + 1] as u32, scales[p + 2] as u32);
+        if x0 == 0 || x0 > 0x7fff || x1 > 0x7fff || x2 > 0x7fff {
+            continue; /* blank frames may be written unencrypted */
+        }
+
+        let d1 = x1.wrapping_sub(x0) & 0x7fff;
+        let d2 = x2.wrapping_sub(x1) & 0x7fff;
+        if d1 == 0 {
+            continue; /* can't tell mult */
+        }
+        let shift = d1.trailing_zeros();
+        if d2 & ((1 << shift) - 1) != 0 {
+            continue; /* not a real XOR sequence (non-zero scale in silence?) */
+        }
+
+        /* mult is only known in the lower (15 - shift) bits, try all upper bits */
+        let low_mask = (1u32 << (15 - shift)) - 1;
+        let mult_low = ((d2 >> shift) * adx_mod_inverse(d1 >> shift)) & low_mask;
+        for high in 0..(1u32 << shift) {
+            let mult = mult_low | (high << (15 - shift));
+            if mult & 1 == 0 {
+                continue;
+            }
+            let add = x1.wrapping_sub(x0 * mult) & 0x7fff;
+
+            /* step back to the first frame */
+            let mult_inv = adx_mod_inverse(mult);
+            let mut start = x0;
+            for _ in 0..p {
+                start = (start.wrapping_sub(add) & 0x7fff) * mult_inv & 0x7fff;
+            }
+
+            let key = (start as u16, mult as u16, add as u16);
+            if is_valid_key(key.0, key.1, key.2)
+                && adx_key_matches(key.0, key.1, key.2, &scales, &[], keymask)
+                && !candidates.contains(&key)
+            {
+                candidates.push(key);
+            }
+        }
+    }
+
+    /* no usable silence: type 8 values all come from the prime table, so test every combination
+     * (most fail at the first frames) */
+    if candidates.is_empty() && enc_type == 8 {
+        for &start in KEY8_PRIMES.iter() {
+            if let Some(&scale) = scales.first() {
+                if scale != 0 && (scale & keymask) != (start & keymask) {
+                    continue;
+                }
+            }
+            for &mult in KEY8_PRIMES.iter() {
+                let base = start.wrapping_mul(mult);
+                for &add in KEY8_PRIMES.iter() {
+                    /* quick reject with the second frame before the full test */
+                    if let Some(&scale) = scales.get(1) {
+                        if scale != 0 && (scale & keymask) != (base.wrapping_add(add) & keymask) {
+                            continue;
+                        }
+                    }
+                    if adx_key_matches(start, mult, add, &scales, &[], keymask) {
+                        candidates.push((start, mult, add));
+                    }
+                }
+            }
         }
+    }
 
-        // if coding_type == CodingType::coding_CRI_ADX_enc_8 || coding_type == CodingType::coding_CRI_ADX_enc_9 {
-        //     vgmstream.ch[i].adx_channels = channels as i32;
-        //     vgmstream.ch[i].adx_xor = xor_start;
-        //     vgmstream.ch[i].adx_mult = xor_mult;
-        //     vgmstream.ch[i].adx_add = xor_add;
+    return Ok(candidates);
+}
 
-        //     for j in 0..i {
-        //         adx_next_key(&vgmstream.ch[i]);
-        //     }
-        // }
+/* inverse of an odd number mod 2^15 (Newton's iteration, each step doubles the correct bits) */
+fn adx_mod_inverse(value: u32) -> u32 {
+    let mut inv = value;
+    for _ in 0..4 {
+        inv = inv.wrapping_mul(2u32.wrapping_sub(value.wrapping_mul(inv)));
     }
+    return inv & 0x7fff;
+}
 
-    vgmstream.open_stream(sf, start_offset as isize);
+/* Finds XOR key params for encrypted ADX: from an external key file, or by testing known keys
+ * against the scales of the first frames (encrypted scales must match the key's XOR in a few
+ * bits, as real scales never use them). Returns start/mult/add. */
+fn find_adx_key(sf: &mut Streamfile, enc_type: u8, subkey: u16) -> Result<Option<(u16, u16, u16)>> {
+    /* try to find key in external file first */
+    if let Some(keybuf) = read_key_file(sf, 0x40) {
+        /* keystrings should be ASCII, also needed to tell apart 0x06 strings from derived keys */
+        let is_ascii = enc_type == 8 && keybuf.iter().all(|&c| (0x20..=0x7f).contains(&c));
+
+        if keybuf.len() == 0x06 && !is_ascii {
+            /* derived key */
+            return Ok(Some((get_u16be(&keybuf[0x00..]), get_u16be(&keybuf[0x02..]), get_u16be(&keybuf[0x04..]))));
+        }
+        else if enc_type == 8 && is_ascii {
+            return Ok(Some(derive_adx_key8(&String::from_utf8_lossy(&keybuf))));
+        }
+        else if enc_type == 9 && keybuf.len() == 0x08 {
+            let keycode = adx_apply_subkey(get_u64be(&keybuf[0x00..]), subkey);
+            return Ok(Some(derive_adx_key9(keycode)));
+        }
+        else if enc_type == 9 && keybuf.len() == 0x08 + 0x02 {
+            /* keycode + subkey it applies to (for AWB with many subkeys) */
+            let file_key = get_u64be(&keybuf[0x00..]);
+            let file_sub = get_u16be(&keybuf[0x08..]);
+            if subkey == file_sub {
+                return Ok(Some(derive_adx_key9(file_key)));
+            }
+        }
+        /* no key set or unknown format, try guessing */
+    }
+
+    /* setup totals */
+    let start_offset = read_u16be(sf, 0x02)? as usize + 0x04;
+    let channels = read_u8(sf, 0x07)? as usize;
+    let num_samples = read_u32be(sf, 0x0c)? as usize;
+    let end_offset = (num_samples + 31) / 32 * 0x12 * channels + start_offset;
+    let frame_count = (end_offset - start_offset) / 0x12;
+
+    /* find longest run of nonzero frames (blank frames have no usable scale) */
+    let mut longest: Option<usize> = None;
+    let mut longest_length = 0;
+    let mut length = 0;
+    for i in 0..frame_count {
+        let mut frame = [0u8; 0x12];
+        read_streamfile(sf, start_offset + i * 0x12, &mut frame);
+
+        if frame.iter().any(|&c| c != 0) {
+            length += 1;
+        } else {
+            length = 0;
+        }
+
+        if length > longest_length {
+            longest_length = length;
+            longest = Some(i + 1 - length);
+            if longest_length >= 0x8000 {
+                break;
+            }
+        }
+    }
+    let Some(bruteframe) = longest else {
+        return Ok(None);
+    };
+    let bruteframe_count = longest_length;
+
+    /* scales before the run (blank frames) and within it */
+    let mut prescales: Vec<u16> = Vec::with_capacity(bruteframe);
+    for i in 0..bruteframe {
+        prescales.push(read_u16be(sf, start_offset + i * 0x12)?);
+    }
+    let mut scales: Vec<u16> = Vec::with_capacity(bruteframe_count);
+    for i in 0..bruteframe_count {
+        scales.push(read_u16be(sf, start_offset + (bruteframe + i) * 0x12)?);
+    }
+
+    /* try to guess key */
+    let (keys, keymask) = match enc_type {
+        8 => (ADXKEY8_LIST, 0x6000),
+        _ => (ADXKEY9_LIST, 0x1000),
+    };
+
+    /* try all keys until one decrypts correctly vs expected values */
+    for key in keys {
+        /* get pre-derived keys or derive by key */
+        let (key_xor, key_mul, key_add) = if key.start != 0 || key.mult != 0 || key.add != 0 {
+            (key.start, key.mult, key.add)
+        } else if let (8, Some(key8)) = (enc_type, key.key8) {
+            derive_adx_key8(key8)
+        } else if enc_type == 9 && key.key9 != 0 {
+            derive_adx_key9(adx_apply_subkey(key.key9, subkey))
+        } else {
+            continue;
+        };
+
+        if adx_key_matches(key_xor, key_mul, key_add, &prescales, &scales, keymask) {
+            return Ok(Some((key_xor, key_mul, key_add)));
+        }
+    }
+
+    return Ok(None);
+}
+
+/* checks that a key's XOR sequence matches frame scales in the masked bits */
+fn adx_key_matches(start: u16, mult: u16, add: u16, prescales: &[u16], scales: &[u16], keymask: u16) -> bool {
+    let mut xor = start;
+
+    /* test vs prescales while xor looks valid */
+    for &prescale in prescales {
+        if prescale != 0 && (prescale & keymask) != (xor & keymask) {
+            return false;
+        }
+        xor = xor.wrapping_mul(mult).wrapping_add(add);
+    }
+
+    /* test vs scales while xor looks valid */
+    for &scale in scales {
+        if (scale & keymask) != (xor & keymask) {
+            return false;
+        }
+        xor = xor.wrapping_mul(mult).wrapping_add(add);
+    }
 
-    return Some(vgmstream);
+    /* all scales are valid, key found */
+    return true;
 }
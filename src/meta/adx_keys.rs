@@ -0,0 +1,100 @@
+/* ADX encryption keys.
+ *
+ * Type 8 keys are derived from a keystring (an ASCII password passed to CRI's encoder), and
+ * type 9 keys from a 64-bit keycode (same as HCA's). Both end up as a start/mult/add triplet
+ * for the XOR generator that scrambles frame scales: xor = (xor * mult + add) & 0x7fff.
+ * Entries may store the triplet directly when the original keystring/keycode isn't known.
+ */
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct AdxKeyInfo {
+    pub start: u16,
+    pub mult: u16,
+    pub add: u16,
+    pub key8: Option<&'static str>,
+    pub key9: u64,
+}
+
+/* type 8 keys */
+pub const ADXKEY8_LIST: &[AdxKeyInfo] = &[
+    /* GOD HAND (PS2), Okami (PS2) [Clover Studio] */
+    AdxKeyInfo { start: 0x49e1, mult: 0x4a57, add: 0x553d, key8: Some("karaage"), key9: 0 },
+];
+
+/* type 9 keys */
+pub const ADXKEY9_LIST: &[AdxKeyInfo] = &[
+    /* keycodes are per game and usually shared with HCA, add them as found */
+];
+
+/* type 8 derivation uses the first 0x400 primes over 0x4000 */
+pub(crate) const KEY8_PRIMES: [u16; 0x400] = generate_key8_primes();
+
+const fn generate_key8_primes() -> [u16; 0x400] {
+    let mut primes = [0u16; 0x400];
+    let mut count = 0;
+    let mut value: u32 = 0x4000;
+
+    while count < 0x400 {
+        let mut is_prime = true;
+        let mut div = 2;
+        while div * div <= value {
+            if value % div == 0 {
+                is_prime = false;
+                break;
+            }
+            div += 1;
+        }
+        if is_prime {
+            primes[count] = value as u16;
+            count += 1;
+        }
+        value += 1;
+    }
+
+    return primes;
+}
+
+/* Returns start/mult/add from a type 8 keystring */
+pub fn derive_adx_key8(key8: &str) -> (u16, u16, u16) {
+    if key8.is_empty() {
+        return (0, 0, 0);
+    }
+
+    let mut start = KEY8_PRIMES[0x100] as u32;
+    let mut mult = KEY8_PRIMES[0x200] as u32;
+    let mut add = KEY8_PRIMES[0x300] as u32;
+
+    for c in key8.bytes() {
+        /* chars are signed in CRI's lib, matters for non-ASCII */
+        let prime = KEY8_PRIMES[(c as i8 as i32 + 0x80) as usize] as u32;
+        start = KEY8_PRIMES[(start * prime % 0x400) as usize] as u32;
+        mult = KEY8_PRIMES[(mult * prime % 0x400) as usize] as u32;
+        add = KEY8_PRIMES[(add * prime % 0x400) as usize] as u32;
+    }
+
+    return (start as u16, mult as u16, add as u16);
+}
+
+/* Returns start/mult/add from a type 9 keycode */
+pub fn derive_adx_key9(key9: u64) -> (u16, u16, u16) {
+    /* 0 is ignored by CRI's encoder, only from 1..18446744073709551615 */
+    if key9 == 0 {
+        return (0, 0, 0);
+    }
+
+    let key9 = key9 - 1;
+    let start = ((key9 >> 27) & 0x7fff) as u16;
+    let mult = (((key9 >> 12) & 0x7ffc) | 1) as u16;
+    let add = (((key9 << 1) & 0x7fff) | 1) as u16;
+
+    return (start, mult, add);
+}
+
+/* AWB/ACB may store a per-file subkey that modifies the main keycode (same as HCA) */
+pub fn adx_apply_subkey(keycode: u64, subkey: u16) -> u64 {
+    if subkey == 0 {
+        return keycode;
+    }
+    let modifier = ((subkey as u64) << 16) | ((!subkey) as u64 + 2);
+    return keycode.wrapping_mul(modifier);
+}
@@ -0,0 +1,98 @@
+use crate::vgm_logi;
+use crate::coding::coding::MpegCustomConfig;
+use crate::coding::mpeg_custom_utils_ahx::init_ffmpeg_ahx;
+use crate::error::{Result, VgmstreamError};
+use crate::meta::adx_keys::derive_adx_key8;
+use crate::streamfile::*;
+use crate::util::reader::get_u16be;
+use crate::vgmstream::*;
+
+/* AHX - CRI voice format */
+pub fn init_vgmstream_ahx(sf: &mut Streamfile) -> Result<VGMStream> {
+    let mut vgmstream = VGMStream::default();
+
+    /* checks */
+    if read_u16be(sf, 0x00)? != 0x8000 {
+        return Err(VgmstreamError::UnknownFormat { filename: sf.name.clone() });
+    }
+
+    /* .ahx: common
+     * .ahxi: Wanna Be The Strongest in the World (PS2) */
+    if !check_extensions(sf, vec!["ahx", "ahxi"]) {
+        return Err(VgmstreamError::UnknownFormat { filename: sf.name.clone() });
+    }
+
+    let start_offset = read_u16be(sf, 0x02)? as usize + 0x04;
+    if start_offset < 0x06 + 0x14 {
+        return Err(VgmstreamError::BadHeader { meta: "AHX", offset: 0x02, field: "header size" });
+    }
+    if read_u16be(sf, start_offset - 0x06)? != 0x2863 ||     /* "(c" */
+        read_u32be(sf, start_offset - 0x04)? != 0x29435249   /* ")CRI" */
+    {
+        return Err(VgmstreamError::UnknownFormat { filename: sf.name.clone() });
+    }
+
+    /* types: 0x10 = AHX for DC with bigger frames, 0x11 = AHX, 0x0N = ADX */
+    let cri_type = read_u8(sf, 0x04)?;
+    if cri_type != 0x10 && cri_type != 0x11 {
+        return Err(VgmstreamError::UnknownFormat { filename: sf.name.clone() });
+    }
+
+    /* frame size and bits per sample (0 for AHX) */
+    if read_u8(sf, 0x05)? != 0 || read_u8(sf, 0x06)? != 0 {
+        return Err(VgmstreamError::BadHeader { meta: "AHX", offset: 0x05, field: "frame size" });
+    }
+
+    /* only mono AHX can be created by the encoder */
+    let channels = read_u8(sf, 0x07)?;
+    if channels != 1 {
+        return Err(VgmstreamError::BadHeader { meta: "AHX", offset: 0x07, field: "channels" });
+    }
+
+    /* version signature */
+    if read_u8(sf, 0x12)? != 0x06 {
+        return Err(VgmstreamError::BadHeader { meta: "AHX", offset: 0x12, field: "version" });
+    }
+
+    let mut cfg: MpegCustomConfig = Default::default();
+    cfg.cri_type = cri_type;
+    cfg.encryption = read_u8(sf, 0x13)?; /* 0x08 = keyword encryption */
+
+    if cfg.encryption == 0x08 {
+        if let Some(keybuf) = read_key_file(sf, 0x10) {
+            /* keystrings should be ASCII, also needed to tell apart 0x06 strings from derived keys */
+            let is_ascii = keybuf.iter().all(|&c| (0x20..=0x7f).contains(&c));
+
+            if is_ascii {
+                (cfg.cri_key1, cfg.cri_key2, cfg.cri_key3) = derive_adx_key8(&String::from_utf8_lossy(&keybuf));
+            }
+            else if keybuf.len() == 0x06 {
+                cfg.cri_key1 = get_u16be(&keybuf[0x00..]);
+                cfg.cri_key2 = get_u16be(&keybuf[0x02..]);
+                cfg.cri_key3 = get_u16be(&keybuf[0x04..]);
+            }
+        }
+
+        /* encrypted bits can't be validated, so keys can't be guessed like ADX */
+        if cfg.cri_key1 == 0 && cfg.cri_key2 == 0 && cfg.cri_key3 == 0 {
+            vgm_logi!("AHX: decryption key not found (needs .ahxkey)");
+        }
+    }
+    else if cfg.encryption != 0x00 {
+        return Err(VgmstreamError::UnsupportedCodec { meta: "AHX", codec: format!("encryption 0x{:x}", cfg.encryption) });
+    }
+
+    vgmstream.channels = channels as i32;
+    vgmstream.ch = vec![VGMStreamChannel::default(); channels as usize];
+    vgmstream.sample_rate = read_s32be(sf, 0x08)?; /* real sample rate */
+    vgmstream.num_samples = read_s32be(sf, 0x0c)?; /* doesn't include encoder delay (skipped in the decoder) */
+    vgmstream.meta_type = MetaType::meta_AHX;
+
+    let ffmpeg_data = init_ffmpeg_ahx(sf, start_offset, &cfg)?;
+    vgmstream.codec_data = Some(VGMStreamCodecData::CustomFFmpeg(ffmpeg_data));
+    vgmstream.coding_type = CodingType::coding_FFmpeg;
+    vgmstream.layout_type = LayoutType::layout_none;
+
+    vgmstream.open_stream(sf, start_offset as isize)?;
+    return Ok(vgmstream);
+}
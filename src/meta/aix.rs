@@ -0,0 +1,150 @@
+use crate::error::{Result, VgmstreamError};
+use crate::layout::layered::*;
+use crate::layout::segmented::*;
+use crate::meta::adx::init_vgmstream_adx;
+use crate::streamfile::*;
+use crate::vgmstream::*;
+
+/* AIX - N segments with M layers (ADX) inside [SoulCalibur IV (PS3), Dragon Ball Z: Burst Limit (PS3)] */
+pub fn init_vgmstream_aix(sf: &mut Streamfile) -> Result<VGMStream> {
+    /* checks */
+    if !is_id32be(sf, 0x00, "AIXF") {
+        return Err(VgmstreamError::UnknownFormat { filename: sf.name.clone() });
+    }
+
+    if !check_extensions(sf, vec!["aix"]) {
+        return Err(VgmstreamError::UnknownFormat { filename: sf.name.clone() });
+    }
+
+    if read_u32be(sf, 0x08)? != 0x01000014 { /* version? */
+        return Err(VgmstreamError::BadHeader { meta: "AIX", offset: 0x08, field: "version" });
+    }
+    if read_u32be(sf, 0x0c)? != 0x00000800 { /* header size */
+        return Err(VgmstreamError::BadHeader { meta: "AIX", offset: 0x0c, field: "header size" });
+    }
+
+    /* AIX combines layers for multichannel and segments for looping, all very hacky.
+     * For some reason AIX with 1 layer and 1 segment exist (equivalent to a single ADX). */
+
+    /* base segment header */
+    let segment_list_offset = 0x20;
+    let segment_count = read_u16be(sf, 0x18)? as usize;
+    if segment_count < 1 {
+        return Err(VgmstreamError::BadHeader { meta: "AIX", offset: 0x18, field: "segment count" });
+    }
+
+    let mut segment_offsets = Vec::with_capacity(segment_count);
+    let mut segment_sizes = Vec::with_capacity(segment_count);
+    let mut segment_samples = Vec::with_capacity(segment_count);
+    let mut sample_rate = 0;
+    for i in 0..segment_count {
+        let offset = segment_list_offset + i * 0x10;
+        segment_offsets.push(read_u32be(sf, offset + 0x00)? as usize);
+        segment_sizes.push(read_u32be(sf, offset + 0x04)? as usize);
+        segment_samples.push(read_s32be(sf, offset + 0x08)?);
+        let mut segment_rate = read_s32be(sf, offset + 0x0c)?;
+
+        /* segments > 0 can have 0 sample rate, seems to indicate same as first
+         * [Ryu ga Gotoku: Kenzan! (PS3) tenkei_sng1.aix] */
+        if i > 0 && segment_rate == 0 {
+            segment_rate = sample_rate;
+        }
+
+        /* use first segment as reference */
+        if i == 0 {
+            sample_rate = segment_rate;
+        }
+
+        /* all segments must have equal sample rate */
+        if segment_rate != sample_rate {
+            return Err(VgmstreamError::BadHeader { meta: "AIX", offset: offset + 0x0c, field: "segment sample rate" });
+        }
+    }
+
+    if segment_offsets[0] != 0x800 {
+        return Err(VgmstreamError::BadHeader { meta: "AIX", offset: segment_list_offset, field: "segment offset" });
+    }
+
+    /* between the segment and layer table some kind of 0x10 pseudo-header seems to exist */
+
+    /* base layer header */
+    let layer_list_offset = segment_list_offset + segment_count * 0x10 + 0x10;
+    if layer_list_offset >= 0x800 { /* assumed max */
+        return Err(VgmstreamError::BadHeader { meta: "AIX", offset: layer_list_offset, field: "layer list" });
+    }
+
+    /* per layer after 0x08: sample rate, channels, etc (same as each ADX's header) */
+    let layer_count = read_u8(sf, layer_list_offset)? as i32;
+    if layer_count < 1 {
+        return Err(VgmstreamError::BadHeader { meta: "AIX", offset: layer_list_offset, field: "layer count" });
+    }
+
+    /* build combo layers + segments VGMStream */
+    let mut vgmstream = build_segmented_vgmstream(sf, &segment_offsets, &segment_sizes, &segment_samples, layer_count)?;
+    vgmstream.meta_type = MetaType::meta_AIX;
+
+    return Ok(vgmstream);
+}
+
+fn build_layered_vgmstream(sf: &mut Streamfile, segment_offset: usize, segment_size: usize, layer_count: i32) -> Result<VGMStream> {
+    /* build layers */
+    let mut data = init_layout_layered(layer_count);
+    for i in 0..layer_count {
+        let mut temp_sf = setup_aix_streamfile(sf, segment_offset, segment_size, i as u8, "adx")?;
+        data.layers.push(init_vgmstream_adx(&mut temp_sf)?);
+    }
+
+    setup_layout_layered(&mut data)?;
+
+    return allocate_layered_vgmstream(data);
+}
+
+fn build_segmented_vgmstream(sf: &mut Streamfile, segment_offsets: &[usize], segment_sizes: &[usize], segment_samples: &[i32], layer_count: i32) -> Result<VGMStream> {
+    let segment_count = segment_offsets.len();
+    let loop_flag = segment_count > 1; /* assumed: intro + loop segment (+ ending segment) */
+
+    /* build segments */
+    let mut data = init_layout_segmented(segment_count as i32);
+    for i in 0..segment_count {
+        let mut segment = build_layered_vgmstream(sf, segment_offsets[i], segment_sizes[i], layer_count)?;
+        segment.num_samples = segment_samples[i]; /* just in case */
+        data.segments.push(segment);
+    }
+
+    setup_layout_segmented(&mut data)?;
+
+    return allocate_segmented_vgmstream(data, loop_flag, 1, 1);
+}
+
+/* Deblocks one layer of a segment: data is divided in "AIXP" blocks (0x10 header + ADX data)
+ * for each layer, interleaved until an "AIXE" end block. */
+fn setup_aix_streamfile(sf: &mut Streamfile, segment_offset: usize, segment_size: usize, layer: u8, extension: &str) -> Result<Streamfile> {
+    let segment_end = segment_offset + segment_size;
+    let mut layer_data = Vec::new();
+    let mut offset = segment_offset;
+
+    while offset < segment_end {
+        let block_id = read_u32be(sf, offset + 0x00)?;
+        let block_size = read_u32be(sf, offset + 0x04)? as usize + 0x08;
+
+        if block_id == get_id32be("AIXE") {
+            break;
+        }
+        if block_id != get_id32be("AIXP") || block_size < 0x10 {
+            return Err(VgmstreamError::BadHeader { meta: "AIX", offset, field: "block id" });
+        }
+
+        /* 0x08: layer number, 0x09: layer count, 0x0a: header size?, 0x0c: unknown */
+        if read_u8(sf, offset + 0x08)? == layer {
+            layer_data.extend(read_exact_bytes(sf, offset + 0x10, block_size - 0x10)?);
+        }
+
+        offset += block_size;
+    }
+
+    if layer_data.is_empty() {
+        return Err(VgmstreamError::BadHeader { meta: "AIX", offset: segment_offset, field: "layer data" });
+    }
+
+    return setup_memory_streamfile(sf, layer_data, Some(extension));
+}
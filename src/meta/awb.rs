@@ -0,0 +1,193 @@
+use crate::vgm_log;
+use crate::error::{Result, VgmstreamError};
+use crate::meta::acb::load_acb_wave_name;
+use crate::meta::adx::init_vgmstream_adx_subkey;
+use crate::streamfile::*;
+use crate::vgmstream::*;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum AwbType {
+    ADX,
+    HCA,
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+struct AwbHeader {
+    offset_size: usize,
+    total_subsongs: i32,
+    alignment: usize,
+    subkey: u16,
+    file_size: usize,
+}
+
+/* one waveform in the bank */
+#[derive(Debug, Copy, Clone)]
+struct AwbEntry {
+    waveid: u16,
+    subfile_offset: usize,
+    subfile_size: usize,
+    awb_type: AwbType,
+}
+
+/* AFS2/AWB (Atom Wave Bank) - CRI container of streaming audio, often together with a .acb cue sheet.
+ * Only ADX waveforms can be played: HCA is detected but there is no HCA meta/decoder, so those
+ * subsongs fail with UnsupportedCodec (and are listed with that error). */
+pub fn init_vgmstream_awb(sf: &mut Streamfile) -> Result<VGMStream> {
+    let mut target_subsong = sf.stream_index;
+
+    let awb = parse_awb(sf)?;
+
+    if target_subsong == 0 {
+        target_subsong = 1;
+    }
+    if target_subsong < 0 || target_subsong > awb.total_subsongs {
+        return Err(VgmstreamError::InvalidSubsong { index: target_subsong, total: awb.total_subsongs });
+    }
+
+    let entry = read_awb_entry(sf, &awb, target_subsong)?;
+
+    let mut vgmstream = match entry.awb_type {
+        AwbType::ADX => {
+            let mut temp_sf = setup_subfile_streamfile(sf, entry.subfile_offset, entry.subfile_size, Some("adx"))?;
+            init_vgmstream_adx_subkey(&mut temp_sf, awb.subkey)?
+        }
+        AwbType::HCA => {
+            vgm_log!("AWB: HCA subsong {} at 0x{:x} not supported", target_subsong, entry.subfile_offset);
+            return Err(awb_hca_unsupported());
+        }
+    };
+
+    vgmstream.num_streams = awb.total_subsongs;
+    vgmstream.stream_index = target_subsong;
+    vgmstream.stream_size = entry.subfile_size as isize;
+
+    /* try to load cue names */
+    if let Some(name) = open_acb(sf).and_then(|mut sf_acb| load_acb_wave_name(&mut sf_acb, entry.waveid, false)) {
+        vgmstream.stream_name = name;
+    }
+
+    return Ok(vgmstream);
+}
+
+/* Lists AWB waveforms from the header (plus cue names), without opening each one */
+pub fn list_subsongs_awb(sf: &mut Streamfile) -> Result<Vec<SubsongInfo>> {
+    let awb = parse_awb(sf)?;
+    let mut sf_acb = open_acb(sf);
+
+    let mut subsongs = Vec::with_capacity(awb.total_subsongs as usize);
+    for index in 1..=awb.total_subsongs {
+        let entry = read_awb_entry(sf, &awb, index).and_then(|entry| match entry.awb_type {
+            AwbType::ADX => Ok(entry),
+            AwbType::HCA => Err(awb_hca_unsupported()),
+        });
+
+        subsongs.push(match entry {
+            Ok(entry) => {
+                let name = sf_acb.as_mut().and_then(|sf_acb| load_acb_wave_name(sf_acb, entry.waveid, false));
+                SubsongInfo { index, name: name.unwrap_or_default(), error: None }
+            }
+            Err(err) => SubsongInfo { index, name: String::new(), error: Some(err.to_string()) },
+        });
+    }
+
+    return Ok(subsongs);
+}
+
+fn parse_awb(sf: &mut Streamfile) -> Result<AwbHeader> {
+    let mut awb: AwbHeader = Default::default();
+
+    /* checks */
+    if !is_id32be(sf, 0x00, "AFS2") {
+        return Err(VgmstreamError::UnknownFormat { filename: sf.name.clone() });
+    }
+
+    /* .awb: standard
+     * .afs2: sometimes [Okami HD (PS4)] */
+    if !check_extensions(sf, vec!["awb", "afs2"]) {
+        return Err(VgmstreamError::UnknownFormat { filename: sf.name.clone() });
+    }
+
+    /* 0x04: version? 0x01=common, 0x02=2018+ (no apparent differences) */
+    awb.offset_size = read_u8(sf, 0x05)? as usize;
+    /* 0x06(2): always 0x0002? */
+    awb.total_subsongs = read_s32le(sf, 0x08)?;
+    awb.alignment = read_u16le(sf, 0x0c)? as usize;
+    awb.subkey = read_u16le(sf, 0x0e)?;
+    awb.file_size = sf.get_size(std::ptr::null_mut());
+
+    if awb.total_subsongs <= 0 {
+        return Err(VgmstreamError::InvalidSubsong { index: 1, total: awb.total_subsongs });
+    }
+    if awb.offset_size != 0x02 && awb.offset_size != 0x04 {
+        return Err(VgmstreamError::BadHeader { meta: "AWB", offset: 0x05, field: "offset size" });
+    }
+
+    return Ok(awb);
+}
+
+/* reads subsong N (1-based) from the id and offset tables */
+fn read_awb_entry(sf: &mut Streamfile, awb: &AwbHeader, subsong: i32) -> Result<AwbEntry> {
+    let mut offset = 0x10;
+
+    /* id table: read target (waveid in .acb's tables) */
+    let waveid = read_u16le(sf, offset + (subsong - 1) as usize * 0x02)?;
+    offset += awb.total_subsongs as usize * 0x02;
+
+    /* offset table: find target (last sub-offset is always file end, so table entries = total_subsongs+1) */
+    offset += (subsong - 1) as usize * awb.offset_size;
+    let (mut subfile_offset, mut subfile_next) = if awb.offset_size == 0x04 { /* common */
+        (read_u32le(sf, offset + 0x00)? as usize, read_u32le(sf, offset + 0x04)? as usize)
+    } else { /* mostly sfx in .acb */
+        (read_u16le(sf, offset + 0x00)? as usize, read_u16le(sf, offset + 0x02)? as usize)
+    };
+
+    /* offsets point to the padding before each subfile */
+    if awb.alignment > 0 {
+        if subfile_offset % awb.alignment != 0 {
+            subfile_offset += awb.alignment - (subfile_offset % awb.alignment);
+        }
+        if subfile_next % awb.alignment != 0 && subfile_next < awb.file_size {
+            subfile_next += awb.alignment - (subfile_next % awb.alignment);
+        }
+    }
+    if subfile_next <= subfile_offset {
+        return Err(VgmstreamError::BadHeader { meta: "AWB", offset, field: "subfile offset" });
+    }
+
+    /* autodetect as there isn't anything, plus can mix types
+     * (waveid<>codec info is usually in the companion .acb) */
+    let awb_type = if read_u16be(sf, subfile_offset)? == 0x8000 { /* ADX id (type 0) */
+        AwbType::ADX
+    }
+    else if (read_u32be(sf, subfile_offset)? & 0x7f7f7f7f) == 0x48434100 { /* "HCA\0" (masked for encrypted) */
+        AwbType::HCA
+    }
+    else {
+        vgm_log!("AWB: unknown codec at 0x{:x}", subfile_offset);
+        return Err(VgmstreamError::UnsupportedCodec { meta: "AWB", codec: format!("unknown subfile at 0x{:x}", subfile_offset) });
+    };
+
+    return Ok(AwbEntry {
+        waveid,
+        subfile_offset,
+        subfile_size: subfile_next - subfile_offset,
+        awb_type,
+    });
+}
+
+/* no HCA meta/decoder yet */
+fn awb_hca_unsupported() -> VgmstreamError {
+    return VgmstreamError::UnsupportedCodec { meta: "AWB", codec: "HCA (no decoder)".to_string() };
+}
+
+/* opens the companion .acb (with the same name, or "(name).acb" for "(name)_streamfiles.awb") to get cue names */
+fn open_acb(sf: &Streamfile) -> Option<Streamfile> {
+    return match open_streamfile_by_ext(sf, "acb") {
+        Ok(sf_acb) => Some(sf_acb),
+        Err(_) => {
+            let filename = sf.name.rsplit(|c| c == '/' || c == '\\').next()?;
+            let basename = filename.strip_suffix("_streamfiles.awb")?;
+            open_streamfile_by_filename(sf, &format!("{}.acb", basename)).ok()
+        }
+    };
+}
@@ -0,0 +1,141 @@
+use crate::error::{Result, VgmstreamError};
+use crate::meta::wwise::init_vgmstream_wwise_bnk;
+use crate::streamfile::*;
+use crate::util::util::find_chunk;
+use crate::vgmstream::*;
+
+/* DIDX index of memory .wem */
+#[derive(Debug, Copy, Clone, Default)]
+struct BkhdHeader {
+    big_endian: bool,
+    didx_offset: usize,
+    data_offset: usize,
+    total_subsongs: i32,
+}
+
+/* BKHD - Wwise soundbank container */
+pub fn init_vgmstream_bkhd(sf: &mut Streamfile) -> Result<VGMStream> {
+    let mut target_subsong = sf.stream_index;
+
+    let bkhd = parse_bkhd(sf)?;
+
+    if target_subsong == 0 {
+        target_subsong = 1;
+    }
+    if target_subsong < 0 || target_subsong > bkhd.total_subsongs {
+        return Err(VgmstreamError::InvalidSubsong { index: target_subsong, total: bkhd.total_subsongs });
+    }
+
+    let (subfile_id, mut temp_sf) = open_bkhd_subfile(sf, &bkhd, target_subsong)?;
+    let subfile_size = temp_sf.get_size(std::ptr::null_mut());
+
+    let mut vgmstream = init_vgmstream_wwise_bnk(&mut temp_sf, true)?;
+
+    vgmstream.num_streams = bkhd.total_subsongs;
+    vgmstream.stream_index = target_subsong;
+    vgmstream.stream_name = bkhd_subsong_name(subfile_id, subfile_size, vgmstream.stream_size);
+
+    return Ok(vgmstream);
+}
+
+/* Lists bank .wem by DIDX id, without opening each one */
+pub fn list_subsongs_bkhd(sf: &mut Streamfile) -> Result<Vec<SubsongInfo>> {
+    let bkhd = parse_bkhd(sf)?;
+
+    let mut subsongs = Vec::with_capacity(bkhd.total_subsongs as usize);
+    for index in 1..=bkhd.total_subsongs {
+        subsongs.push(match open_bkhd_subfile(sf, &bkhd, index) {
+            Ok((subfile_id, mut temp_sf)) => {
+                /* just the "data" chunk size to detect prefetch */
+                let big_endian = is_id32be(&mut temp_sf, 0x00, "RIFX");
+                let data_size = find_chunk(&mut temp_sf, get_id32be("data"), 0x0c, false, big_endian).map_or(0, |(_, size)| size);
+                let subfile_size = temp_sf.get_size(std::ptr::null_mut());
+                SubsongInfo { index, name: bkhd_subsong_name(subfile_id, subfile_size, data_size as isize), error: None }
+            }
+            Err(err) => SubsongInfo { index, name: String::new(), error: Some(err.to_string()) },
+        });
+    }
+
+    return Ok(subsongs);
+}
+
+fn parse_bkhd(sf: &mut Streamfile) -> Result<BkhdHeader> {
+    let mut bkhd: BkhdHeader = Default::default();
+    let mut base_offset = 0;
+
+    /* checks */
+    if is_id32be(sf, 0x00, "AKBK") { /* [Shadowrun (X360)] */
+        base_offset = 0x0c;
+    }
+    if !is_id32be(sf, base_offset + 0x00, "BKHD") {
+        return Err(VgmstreamError::UnknownFormat { filename: sf.name.clone() });
+    }
+
+    /* .bnk: Wwise
+     * .wem: used when (rarely) external */
+    if !check_extensions(sf, vec!["bnk", "wem"]) {
+        return Err(VgmstreamError::UnknownFormat { filename: sf.name.clone() });
+    }
+
+    bkhd.big_endian = guess_endian32(sf, base_offset + 0x04)?;
+    let read_u32 = if bkhd.big_endian { read_u32be } else { read_u32le };
+
+    /* Wwise banks have event/track/sequence/etc info in the HIRC chunk, as well as other chunks,
+     * and may have a DIDX index to memory .wem in DATA. We only expose the internal .wem here,
+     * as HIRC is complex and better handled externally (see https://github.com/bnnm/wwiser) */
+
+    /* unlike RIFF, first chunk follows chunk rules */
+    let mut version = read_u32(sf, base_offset + 0x08)?;
+    if version == 0 || version == 1 { /* early games */
+        version = read_u32(sf, base_offset + 0x10)?;
+    }
+
+    if version <= 26 {
+        /* older banks use a custom index inside DATA instead */
+        return Err(VgmstreamError::UnsupportedCodec { meta: "BKHD", codec: format!("bank version {}", version) });
+    }
+
+    let (didx_offset, didx_size) = match find_chunk(sf, get_id32be("DIDX"), base_offset, false, bkhd.big_endian) {
+        Some(chunk) => chunk,
+        None => return Err(VgmstreamError::BadHeader { meta: "BKHD", offset: base_offset, field: "DIDX chunk" }),
+    };
+    let (data_offset, _) = match find_chunk(sf, get_id32be("DATA"), base_offset, false, bkhd.big_endian) {
+        Some(chunk) => chunk,
+        None => return Err(VgmstreamError::BadHeader { meta: "BKHD", offset: base_offset, field: "DATA chunk" }),
+    };
+
+    /* DIDX entries: id + offset (within DATA) + size */
+    bkhd.didx_offset = didx_offset;
+    bkhd.data_offset = data_offset;
+    bkhd.total_subsongs = (didx_size / 0x0c) as i32;
+    if bkhd.total_subsongs < 1 {
+        return Err(VgmstreamError::InvalidSubsong { index: 1, total: bkhd.total_subsongs });
+    }
+
+    return Ok(bkhd);
+}
+
+/* opens DIDX entry N (1-based) as a .wem */
+fn open_bkhd_subfile(sf: &mut Streamfile, bkhd: &BkhdHeader, subsong: i32) -> Result<(u32, Streamfile)> {
+    let read_u32 = if bkhd.big_endian { read_u32be } else { read_u32le };
+
+    let offset = bkhd.didx_offset + (subsong - 1) as usize * 0x0c;
+    let subfile_id = read_u32(sf, offset + 0x00)?;
+    let subfile_offset = read_u32(sf, offset + 0x04)? as usize + bkhd.data_offset;
+    let subfile_size = read_u32(sf, offset + 0x08)? as usize;
+
+    let mut temp_sf = setup_subfile_streamfile(sf, subfile_offset, subfile_size, Some("wem"))?;
+
+    if !is_id32be(&mut temp_sf, 0x00, "RIFF") && !is_id32be(&mut temp_sf, 0x00, "RIFX") {
+        /* plugin/fx .wem (no audio data) */
+        return Err(VgmstreamError::UnsupportedCodec { meta: "BKHD", codec: format!("non-RIFF .wem {}", subfile_id) });
+    }
+
+    return Ok((subfile_id, temp_sf));
+}
+
+/* prefetch .wem are truncated copies of a streamed .wem with the same id */
+fn bkhd_subsong_name(subfile_id: u32, subfile_size: usize, data_size: isize) -> String {
+    let prefetch = (subfile_size as isize) < data_size;
+    return format!("{}{}", subfile_id, if prefetch { " [pre]" } else { "" });
+}
@@ -1,2 +1,8 @@
+pub mod acb;
 pub mod adx;
+pub mod adx_keys;
+pub mod ahx;
+pub mod aix;
+pub mod awb;
+pub mod bkhd;
 pub mod wwise;
\ No newline at end of file
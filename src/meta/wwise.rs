@@ -1,12 +1,19 @@
+use crate::{vgm_log, vgm_logi};
 use std::fmt::Display;
 
 use crate::coding::coding::*;
-use crate::coding::ffmpeg_opus::init_ffmpeg_wwise_opus;
+use crate::coding::ffmpeg_opus::{init_ffmpeg_switch_opus, init_ffmpeg_wwise_opus, switch_opus_get_encoder_delay};
+use crate::coding::coding_utils::{xma2_parse_fmt_chunk_extra, xma2_parse_xma2_chunk, xma_fix_raw_samples};
+use crate::coding::ffmpeg::{ffmpeg_get_samples, init_ffmpeg_atrac9, ffmpeg_make_riff_xma2_from_xma2_chunk, ffmpeg_make_riff_xma_from_fmt_chunk, ffmpeg_make_riff_xwma, init_ffmpeg_header_offset, init_ffmpeg_offset};
+use crate::coding::ima::xbox_ima_bytes_to_samples;
+use crate::coding::ngc_dsp::{dsp_bytes_to_samples, dsp_read_coefs, dsp_read_hist};
+use crate::coding::ptadpcm::ptadpcm_bytes_to_samples;
 use crate::coding::pcm_decoder::pcm_bytes_to_samples;
+use crate::error::{Result, VgmstreamError};
 use crate::streamfile::*;
 use crate::util::util::{next_chunk, ChunkType};
-use crate::vgmstream::{check_extensions, CodingType, LayoutType, Streamfile, VGMStream};
-use crate::vgmstream::{MetaType, VGMStreamCodecData};
+use crate::vgmstream::{check_extensions, CodingType, LayoutType, Streamfile, VGMStream, VGMStreamChannel, VGMStreamCodecData, VGMStreamMarker};
+use crate::vgmstream::MetaType;
 
 /* Wwise uses a custom RIFF/RIFX header, non-standard enough that it's parsed it here.
  * There is some repetition from other metas, but not enough to bother me.
@@ -81,6 +88,10 @@ pub struct WwiseHeader {
     pub seek_size: isize,
     pub meta_offset: usize,
     pub meta_size: isize,
+    pub cue_offset: usize,
+    pub cue_size: isize,
+    pub list_offset: usize,
+    pub list_size: isize,
 
     /* standard fmt stuff */
     pub codec: WwiseCodec,
@@ -91,7 +102,7 @@ pub struct WwiseHeader {
     pub avg_bitrate: i32,
     pub bits_per_sample: i32,
     pub channel_type: u8,
-    pub channel_layout: ChannelMapping,
+    pub channel_layout: u32,
     pub extra_size: isize,
 
     pub num_samples: i32,
@@ -100,11 +111,11 @@ pub struct WwiseHeader {
     pub loop_end_sample: i32,
 }
 
-pub fn init_vgmstream_wwise(sf: &mut Streamfile) -> Option<VGMStream> {
+pub fn init_vgmstream_wwise(sf: &mut Streamfile) -> Result<VGMStream> {
     return init_vgmstream_wwise_bnk(sf, false);
 }
 
-fn init_vgmstream_wwise_bnk(sf: &mut Streamfile, p_prefetch: bool) -> Option<VGMStream> {
+pub fn init_vgmstream_wwise_bnk(sf: &mut Streamfile, is_bnk: bool) -> Result<VGMStream> {
     let mut vgmstream: VGMStream = Default::default();
     let mut ww: WwiseHeader = Default::default();
     /* checks */
@@ -112,7 +123,7 @@ fn init_vgmstream_wwise_bnk(sf: &mut Streamfile, p_prefetch: bool) -> Option<VGM
     && !is_id32be(sf, 0x00, "RIFX")
     {
         /* BE */
-        return None;
+        return Err(VgmstreamError::UnknownFormat { filename: sf.name.clone() });
     }
 
     /* note that Wwise allows those extensions only, so custom engine exts shouldn't be added
@@ -122,13 +133,11 @@ fn init_vgmstream_wwise_bnk(sf: &mut Streamfile, p_prefetch: bool) -> Option<VGM
      * .ogg: older Vorbis files [The King of Fighters XII (X360)]
      * .bnk: Wwise banks for memory .wem detection (hack) */
     if !check_extensions(sf, vec!["wem", "wav", "lwav", "ogg", "logg", "xma", "bnk"]) {
-        return None;
+        return Err(VgmstreamError::UnknownFormat { filename: sf.name.clone() });
     }
 
-    ww.is_bnk = p_prefetch;
-    if !parse_wwise(sf, &mut ww) {
-        return None;
-    }
+    ww.is_bnk = is_bnk; /* prefetch is detected from the data size */
+    parse_wwise(sf, &mut ww)?;
 
     let read_u32 = if ww.big_endian {
         read_u32be
@@ -148,13 +157,21 @@ fn init_vgmstream_wwise_bnk(sf: &mut Streamfile, p_prefetch: bool) -> Option<VGM
 
     let mut start_offset = ww.data_offset;
 
+    /* build the VGMSTREAM */
+    vgmstream.channels = ww.channels;
+    vgmstream.ch = vec![VGMStreamChannel::default(); ww.channels as usize];
+    vgmstream.loop_flag = ww.loop_flag;
+
     vgmstream.meta_type = MetaType::meta_WWISE_RIFF;
     vgmstream.sample_rate = ww.sample_rate;
     vgmstream.loop_start_sample = ww.loop_start_sample;
     vgmstream.loop_end_sample = ww.loop_end_sample;
-    vgmstream.channel_layout = ww.channel_layout.try_into().unwrap();
+    vgmstream.channel_layout = ww.channel_layout;
     vgmstream.stream_size = ww.data_size;
 
+    /* samples the decoder discards at the start, while loops/cues count from the encoder's input */
+    let mut encoder_delay = 0;
+
     match ww.codec {
         WwiseCodec::PCM => {
             if ww.fmt_size != 0x10
@@ -162,23 +179,27 @@ fn init_vgmstream_wwise_bnk(sf: &mut Streamfile, p_prefetch: bool) -> Option<VGM
                 && ww.fmt_size != 0x18
                 && ww.fmt_size != 0x28
             {
-                return None;
-            }
-            if ww.bits_per_sample != 16 {
-                return None;
+                return Err(VgmstreamError::BadHeader { meta: "WWISE", offset: ww.fmt_offset, field: "fmt size" });
             }
 
-            vgmstream.coding_type = if ww.big_endian {
-                CodingType::coding_PCM16BE
-            } else {
-                CodingType::coding_PCM16LE
+            vgmstream.coding_type = match (ww.bits_per_sample, ww.big_endian) {
+                (8, _) => CodingType::coding_PCM8_U,
+                (16, false) => CodingType::coding_PCM16LE,
+                (16, true) => CodingType::coding_PCM16BE,
+                (24, false) => CodingType::coding_PCM24LE,
+                (24, true) => CodingType::coding_PCM24BE,
+                (32, _) => CodingType::coding_PCMFLOAT, /* authoring PCMEX, always float */
+                _ => {
+                    return Err(VgmstreamError::BadHeader { meta: "WWISE", offset: ww.fmt_offset + 0x0e, field: "bits per sample" });
+                }
             };
+            vgmstream.codec_endian = ww.big_endian;
             vgmstream.layout_type = if ww.channels > 1 {
                 LayoutType::layout_interleave
             } else {
                 LayoutType::layout_none
             };
-            vgmstream.interleave_block_size = 0x02;
+            vgmstream.interleave_block_size = (ww.bits_per_sample / 8) as isize;
 
             if ww.prefetch {
                 ww.data_size = ww.file_size - ww.data_offset as isize;
@@ -192,6 +213,217 @@ fn init_vgmstream_wwise_bnk(sf: &mut Streamfile, p_prefetch: bool) -> Option<VGM
                 vgmstream.num_samples = 1; /* force something to avoid broken subsongs */
             }
         }
+        WwiseCodec::IMA => {
+            /* slightly modified XBOX-IMA */
+            /* Wwise reuses common codec ids (ex. 0x0002 MSADPCM) for IMA so this parser should go AFTER riff.c avoid misdetection */
+            if ww.fmt_size != 0x14 && ww.fmt_size != 0x28 && ww.fmt_size != 0x18 {
+                /* oddities [Pikmin 3 (WiiU): 0x18 (size=0x28)] */
+                return Err(VgmstreamError::BadHeader { meta: "WWISE", offset: ww.fmt_offset, field: "fmt size" });
+            }
+            if ww.bits_per_sample != 4 {
+                return Err(VgmstreamError::BadHeader { meta: "WWISE", offset: ww.fmt_offset + 0x0e, field: "bits per sample" });
+            }
+            if ww.block_size != 0x24 * ww.channels {
+                return Err(VgmstreamError::BadHeader { meta: "WWISE", offset: ww.fmt_offset + 0x0c, field: "block size" });
+            }
+
+            vgmstream.coding_type = CodingType::coding_WWISE_IMA;
+            vgmstream.layout_type = LayoutType::layout_none;
+            vgmstream.interleave_block_size = ww.block_size as isize;
+            vgmstream.codec_endian = ww.big_endian;
+
+            if ww.prefetch {
+                ww.data_size = ww.file_size - ww.data_offset as isize;
+            }
+
+            vgmstream.num_samples = xbox_ima_bytes_to_samples(ww.data_size, ww.channels);
+        }
+        WwiseCodec::DSP => {
+            /* Wii/3DS/WiiU */
+            //if ww.fmt_size != 0x28 && ww.fmt_size != ? { goto fail; } /* old versions use 0x12 */
+            if ww.bits_per_sample != 4 {
+                return Err(VgmstreamError::BadHeader { meta: "WWISE", offset: ww.fmt_offset + 0x0e, field: "bits per sample" });
+            }
+
+            vgmstream.coding_type = CodingType::coding_NGC_DSP;
+            vgmstream.layout_type = LayoutType::layout_interleave;
+            vgmstream.interleave_block_size = 0x08; /* ww.block_size = 0x8 in older Wwise, samples per block in newer Wwise */
+
+            /* find coef position */
+            let wiih_offset;
+            if ww.wiih_offset != 0 {
+                /* older */
+                vgmstream.num_samples = dsp_bytes_to_samples(ww.data_size, ww.channels);
+                if ww.wiih_size != 0x2e * ww.channels as isize {
+                    return Err(VgmstreamError::BadHeader { meta: "WWISE", offset: ww.wiih_offset, field: "WiiH size" });
+                }
+                wiih_offset = ww.wiih_offset;
+            } else if ww.extra_size == 0x0c + ww.channels as isize * 0x2e {
+                /* newer */
+                vgmstream.num_samples = read_s32(sf, ww.fmt_offset + 0x18)?;
+                wiih_offset = ww.fmt_offset + 0x1c;
+            } else {
+                return Err(VgmstreamError::BadHeader { meta: "WWISE", offset: ww.fmt_offset + 0x10, field: "DSP extra size" });
+            }
+
+            if ww.prefetch {
+                ww.data_size = ww.file_size - ww.data_offset as isize;
+                vgmstream.num_samples = dsp_bytes_to_samples(ww.data_size, ww.channels);
+            }
+
+            /* per channel: coefs + gain + initial ps + initial hist + loop ps/hist */
+            dsp_read_coefs(&mut vgmstream, sf, wiih_offset + 0x00, 0x2e, ww.big_endian)?;
+            dsp_read_hist(&mut vgmstream, sf, wiih_offset + 0x24, 0x2e, ww.big_endian)?;
+        }
+        WwiseCodec::XMA2 => {
+            /* X360/XBone */
+            //if ww.fmt_size != ... { goto fail; } /* XMA1 0x20, XMA2old: 0x34, XMA2new: 0x40, XMA2 Guitar Hero Live/padded: 0x64, etc */
+
+            /* only Wwise XMA: X360=BE, or XBone=LE+wem (X360 uses "XMA2" in the header, old Wwise .xma uses RIFF but XBone uses .wem) */
+            if !ww.big_endian && !ww.is_wem {
+                return Err(VgmstreamError::UnknownFormat { filename: sf.name.clone() });
+            }
+
+            if ww.prefetch {
+                ww.data_size = ww.file_size - start_offset as isize;
+            }
+
+            let mut buf: Vec<u8> = Vec::new();
+            let bytes = if ww.xma2_offset != 0 {
+                /* older */
+                ffmpeg_make_riff_xma2_from_xma2_chunk(&mut buf, ww.xma2_offset, ww.xma2_size as usize, ww.data_size as usize, sf)?
+            } else {
+                /* newer */
+                ffmpeg_make_riff_xma_from_fmt_chunk(&mut buf, ww.fmt_offset, ww.fmt_size as usize, ww.data_size as usize, sf, ww.big_endian)?
+            };
+            buf.truncate(bytes);
+
+            let ffmpeg_data = init_ffmpeg_header_offset(sf, &buf, ww.data_offset as u64, ww.data_size as u64)?;
+            vgmstream.codec_data = Some(VGMStreamCodecData::CustomFFmpeg(ffmpeg_data));
+            vgmstream.coding_type = CodingType::coding_FFmpeg;
+            vgmstream.layout_type = LayoutType::layout_none;
+
+            vgmstream.num_samples = ww.num_samples; /* set while parsing XMAWAVEFORMATs */
+
+            /* "no seek table" XMA2: samples include the encoder delay, but "smpl" loops don't */
+            xma_fix_raw_samples(&mut vgmstream, true, false);
+        }
+        WwiseCodec::XWMA => {
+            /* X360 */
+            if ww.fmt_size != 0x18 {
+                return Err(VgmstreamError::BadHeader { meta: "WWISE", offset: ww.fmt_offset, field: "fmt size" });
+            }
+            if !ww.big_endian {
+                /* must be from Wwise X360 (PC LE XWMA is parsed elsewhere) */
+                return Err(VgmstreamError::UnknownFormat { filename: sf.name.clone() });
+            }
+
+            if ww.prefetch {
+                ww.data_size = ww.file_size - start_offset as isize;
+            }
+
+            /* "seek" is a BE xWMA "dpds" table: cumulative decoded bytes (16-bit PCM) per packet,
+             * which FFmpeg's demuxer uses for seeking if it matches the packet count */
+            let mut dpds: Vec<u32> = Vec::new();
+            if ww.seek_offset != 0 {
+                let entries = (ww.seek_size / 0x04) as usize;
+                for i in 0..entries {
+                    dpds.push(read_u32(sf, ww.seek_offset + i * 0x04)?);
+                }
+            }
+            let packets = if ww.block_size > 0 { (ww.data_size / ww.block_size as isize) as usize } else { 0 };
+            if ww.prefetch {
+                dpds.truncate(packets); /* only the first packets are present */
+            }
+            let riff_dpds: &[u32] = if dpds.len() == packets {
+                &dpds
+            } else {
+                vgm_log!("WWISE: XWMA seek entries {} don't match packets {}", dpds.len(), packets);
+                &[]
+            };
+
+            let mut buf: Vec<u8> = Vec::new();
+            ffmpeg_make_riff_xwma(&mut buf, ww.format as u16, ww.data_size as usize, ww.channels, ww.sample_rate, ww.avg_bitrate, ww.block_size, riff_dpds);
+
+            let ffmpeg_data = init_ffmpeg_header_offset(sf, &buf, ww.data_offset as u64, ww.data_size as u64)?;
+
+            /* last dpds entry has the total decoded bytes, otherwise FFmpeg estimates duration from the bitrate */
+            vgmstream.num_samples = match dpds.last() {
+                Some(total_bytes) if ww.channels > 0 => (*total_bytes / ww.channels as u32 / 2) as i32,
+                _ => 0,
+            };
+            if vgmstream.num_samples == 0 {
+                vgmstream.num_samples = ffmpeg_get_samples(&ffmpeg_data);
+            }
+
+            /* WMA's encoder delay is handled (and trimmed) by FFmpeg's decoder */
+            vgmstream.codec_data = Some(VGMStreamCodecData::CustomFFmpeg(ffmpeg_data));
+            vgmstream.coding_type = CodingType::coding_FFmpeg;
+            vgmstream.layout_type = LayoutType::layout_none;
+        }
+        WwiseCodec::AAC => {
+            /* iOS/Mac */
+            if ww.fmt_size != 0x24 {
+                return Err(VgmstreamError::BadHeader { meta: "WWISE", offset: ww.fmt_offset, field: "fmt size" });
+            }
+            if ww.block_size != 0 || ww.bits_per_sample != 0 {
+                return Err(VgmstreamError::BadHeader { meta: "WWISE", offset: ww.fmt_offset + 0x0c, field: "block size" });
+            }
+
+            /* extra: size 0x12, unknown values */
+
+            if ww.prefetch {
+                ww.data_size = ww.file_size - start_offset as isize;
+            }
+
+            /* data is a regular MP4 (or ADTS), FFmpeg applies the MP4's encoder delay (edit list)
+             * and reports duration without it */
+            let ffmpeg_data = init_ffmpeg_offset(sf, ww.data_offset as u64, ww.data_size as u64)?;
+            vgmstream.num_samples = ffmpeg_get_samples(&ffmpeg_data);
+
+            vgmstream.codec_data = Some(VGMStreamCodecData::CustomFFmpeg(ffmpeg_data));
+            vgmstream.coding_type = CodingType::coding_FFmpeg;
+            vgmstream.layout_type = LayoutType::layout_none;
+        }
+        WwiseCodec::ATRAC9 => {
+            /* PSV/PS4 */
+            if ww.fmt_size != 0x24 {
+                return Err(VgmstreamError::BadHeader { meta: "WWISE", offset: ww.fmt_offset, field: "fmt size" });
+            }
+            if ww.extra_size != 0x12 {
+                return Err(VgmstreamError::BadHeader { meta: "WWISE", offset: ww.fmt_offset + 0x10, field: "extra size" });
+            }
+
+            let mut cfg: Atrac9Config = Default::default();
+            cfg.channels = ww.channels;
+            cfg.config_data = read_u32be(sf, ww.fmt_offset + 0x18)?;
+            cfg.encoder_delay = read_u32(sf, ww.fmt_offset + 0x20)? as i32;
+
+            if ww.prefetch {
+                ww.data_size = ww.file_size - ww.data_offset as isize;
+            }
+
+            let ffmpeg_data = init_ffmpeg_atrac9(sf, ww.data_offset as u64, ww.data_size as u64, ww.sample_rate, &cfg)?;
+            vgmstream.codec_data = Some(VGMStreamCodecData::CustomFFmpeg(ffmpeg_data));
+            vgmstream.coding_type = CodingType::coding_FFmpeg;
+            vgmstream.layout_type = LayoutType::layout_none;
+
+            vgmstream.num_samples = read_s32(sf, ww.fmt_offset + 0x1c)?;
+        }
+        WwiseCodec::HEVAG => {
+            /* PSV */
+            /* changed values, another bizarre Wwise quirk */
+            //ww.block_size /* unknown (1ch=0x10, 2ch=0x10?, 4ch=0x10) */
+            //if (ww.fmt_size != 0x18) goto fail;
+            if ww.big_endian {
+                return Err(VgmstreamError::BadHeader { meta: "WWISE", offset: 0x00, field: "HEVAG endianness" });
+            }
+
+            /* HEVAG frames are VAG frames with a 7-bit coef index into a 128 x 4-tap table;
+             * that table isn't part of this tree yet, and decoding with only the VAG subset
+             * would silently corrupt most frames, so reject until it's added */
+            return Err(VgmstreamError::UnsupportedCodec { meta: "WWISE", codec: ww.codec.to_string() });
+        }
         WwiseCodec::VORBIS => {
             use crate::coding::vorbis::init_vorbis_custom;
             let mut data_offsets: usize = 0;
@@ -208,7 +440,7 @@ fn init_vgmstream_wwise_bnk(sf: &mut Streamfile, p_prefetch: bool) -> Option<VGM
             cfg.stream_end = (ww.data_offset + ww.data_size as usize) as u32;
 
             if ww.block_size != 0 || ww.bits_per_sample != 0 {
-                return None;
+                return Err(VgmstreamError::BadHeader { meta: "WWISE", offset: ww.fmt_offset + 0x0c, field: "block size" });
             }
 
             /* autodetect format (fields are mostly common, see the end of the file) */
@@ -239,17 +471,16 @@ fn init_vgmstream_wwise_bnk(sf: &mut Streamfile, p_prefetch: bool) -> Option<VGM
                         cfg.setup_type = WwiseSetupType::WWV_EXTERNAL_CODEBOOKS;
                     },
                     _ => {
-                        println!("WWISE: unknown vorb size 0x{:x}", ww.vorb_size);
-                        return None;
+                        return Err(VgmstreamError::UnsupportedCodec { meta: "WWISE", codec: format!("Vorbis with vorb size 0x{:x}", ww.vorb_size) });
                     }
                 }
 
-                vgmstream.num_samples = read_s32(sf, ww.vorb_offset as usize + 0x00);
-                setup_offset = read_u32(sf, ww.vorb_offset + data_offsets + 0x00) as usize; /* within data (0 = no seek table) */
-                audio_offset = read_u32(sf, ww.vorb_offset + data_offsets + 0x04) as usize; /* within data */
+                vgmstream.num_samples = read_s32(sf, ww.vorb_offset as usize + 0x00)?;
+                setup_offset = read_u32(sf, ww.vorb_offset + data_offsets + 0x00)? as usize; /* within data (0 = no seek table) */
+                audio_offset = read_u32(sf, ww.vorb_offset + data_offsets + 0x04)? as usize; /* within data */
                 if block_offsets != 0 {
-                    cfg.blocksize_1_exp = read_u8(sf, ww.vorb_offset + block_offsets + 0x00) as i32; /* small */
-                    cfg.blocksize_0_exp = read_u8(sf, ww.vorb_offset + block_offsets + 0x01) as i32;
+                    cfg.blocksize_1_exp = read_u8(sf, ww.vorb_offset + block_offsets + 0x00)? as i32; /* small */
+                    cfg.blocksize_0_exp = read_u8(sf, ww.vorb_offset + block_offsets + 0x01)? as i32;
                     /* big */
                 }
                 ww.data_size -= audio_offset as isize;
@@ -267,8 +498,8 @@ fn init_vgmstream_wwise_bnk(sf: &mut Streamfile, p_prefetch: bool) -> Option<VGM
                  * - trimmed inline: ~2010, ex. Army of Two: 40 days (X360) some multiplayer files
                  * - external: ~2010, ex. Assassin's Creed Brotherhood (X360), Dead Nation (X360) */
                 if ww.vorb_size == 0x34 {
-                    let setup_size = read_u16(sf, start_offset + setup_offset + 0x00);
-                    let setup_id = read_u32be(sf, start_offset + setup_offset + 0x06);
+                    let setup_size = read_u16(sf, start_offset + setup_offset + 0x00)?;
+                    let setup_id = read_u32be(sf, start_offset + setup_offset + 0x06)?;
 
                     /* if the setup after header starts with "(data)BCV" it's an inline codebook) */
                     if (setup_id & 0x00FFFFFF) == get_id32be("\0BCV") {
@@ -282,21 +513,12 @@ fn init_vgmstream_wwise_bnk(sf: &mut Streamfile, p_prefetch: bool) -> Option<VGM
                 }
                 // println!("WWISE: vorbis initialization is stubbed.");
 
-                vgmstream.codec_data = init_vorbis_custom(
+                vgmstream.codec_data = Some(init_vorbis_custom(
                     sf,
                     start_offset + setup_offset,
                     VorbisCustomType::VORBIS_WWISE,
                     &mut cfg,
-                );
-
-                if vgmstream.codec_data.is_none()
-                    || !matches!(
-                        vgmstream.codec_data.as_ref().unwrap(),
-                        VGMStreamCodecData::CustomVorbis(_)
-                    )
-                {
-                    return None;
-                }
+                )?);
             } else {
                 /* newer Wwise (>2012) */
                 let extra_offset = ww.fmt_offset + 0x18; /* after flag + channels */
@@ -318,22 +540,21 @@ fn init_vgmstream_wwise_bnk(sf: &mut Streamfile, p_prefetch: bool) -> Option<VGM
                         }; /* aoTuV came along .wem */
                     }
                     _ => {
-                        println!("WWISE: unknown extra size 0x{:x}", ww.vorb_size);
-                        return None;
+                        return Err(VgmstreamError::UnsupportedCodec { meta: "WWISE", codec: format!("Vorbis with extra size 0x{:x}", ww.extra_size) });
                     }
                 }
 
-                vgmstream.num_samples = read_s32(sf, extra_offset + 0x00);
-                setup_offset = read_u32(sf, extra_offset + data_offsets + 0x00) as usize; /* within data */
-                audio_offset = read_u32(sf, extra_offset + data_offsets + 0x04) as usize; /* within data */
-                cfg.blocksize_1_exp = read_u8(sf, extra_offset + block_offsets + 0x00) as i32; /* small */
-                cfg.blocksize_0_exp = read_u8(sf, extra_offset + block_offsets + 0x01) as i32; /* big */
+                vgmstream.num_samples = read_s32(sf, extra_offset + 0x00)?;
+                setup_offset = read_u32(sf, extra_offset + data_offsets + 0x00)? as usize; /* within data */
+                audio_offset = read_u32(sf, extra_offset + data_offsets + 0x04)? as usize; /* within data */
+                cfg.blocksize_1_exp = read_u8(sf, extra_offset + block_offsets + 0x00)? as i32; /* small */
+                cfg.blocksize_0_exp = read_u8(sf, extra_offset + block_offsets + 0x01)? as i32; /* big */
                 ww.data_size -= audio_offset as isize;
 
                 /* mutant .wem with metadata (voice strings/etc) between seek table and vorbis setup [Gears of War 4 (PC)] */
                 if ww.meta_offset != 0 {
                     /* 0x00: original setup_offset */
-                    setup_offset += read_u32(sf, ww.meta_offset + 0x04) as usize;
+                    setup_offset += read_u32(sf, ww.meta_offset + 0x04)? as usize;
                     /* metadata size */
                 }
 
@@ -346,42 +567,29 @@ fn init_vgmstream_wwise_bnk(sf: &mut Streamfile, p_prefetch: bool) -> Option<VGM
                 }
 
                 /* try with the selected codebooks */
-                vgmstream.codec_data = init_vorbis_custom(
+                let codec_data = match init_vorbis_custom(
                     sf,
                     start_offset + setup_offset,
                     VorbisCustomType::VORBIS_WWISE,
                     &mut cfg,
-                );
-
-                if vgmstream.codec_data.is_none() {
-                    return None;
-                }
-                if !matches!(
-                    vgmstream.codec_data.as_ref().unwrap(),
-                    VGMStreamCodecData::CustomVorbis(_)
                 ) {
-                    /* codebooks failed: try again with the other type */
-                    cfg.setup_type = if ww.is_wem {
-                        WwiseSetupType::WWV_EXTERNAL_CODEBOOKS
-                    } else {
-                        WwiseSetupType::WWV_AOTUV603_CODEBOOKS
-                    };
-                    vgmstream.codec_data = init_vorbis_custom(
-                        sf,
-                        start_offset + setup_offset,
-                        VorbisCustomType::VORBIS_WWISE,
-                        &mut cfg,
-                    );
-
-                    if vgmstream.codec_data.is_none()
-                        || !matches!(
-                            vgmstream.codec_data.as_ref().unwrap(),
-                            VGMStreamCodecData::CustomVorbis(_)
-                        )
-                    {
-                        return None;
+                    Ok(codec_data) => codec_data,
+                    Err(_) => {
+                        /* codebooks failed: try again with the other type */
+                        cfg.setup_type = if ww.is_wem {
+                            WwiseSetupType::WWV_EXTERNAL_CODEBOOKS
+                        } else {
+                            WwiseSetupType::WWV_AOTUV603_CODEBOOKS
+                        };
+                        init_vorbis_custom(
+                            sf,
+                            start_offset + setup_offset,
+                            VorbisCustomType::VORBIS_WWISE,
+                            &mut cfg,
+                        )?
                     }
-                }
+                };
+                vgmstream.codec_data = Some(codec_data);
             }
             vgmstream.layout_type = LayoutType::layout_none;
             vgmstream.coding_type = CodingType::coding_VORBIS_custom;
@@ -396,17 +604,104 @@ fn init_vgmstream_wwise_bnk(sf: &mut Streamfile, p_prefetch: bool) -> Option<VGM
                     / ww.data_size as f64) as i32;
             }
         }
+        WwiseCodec::OPUSNX => {
+            /* Switch */
+            if ww.fmt_size != 0x28 {
+                return Err(VgmstreamError::BadHeader { meta: "WWISE", offset: ww.fmt_offset, field: "fmt size" });
+            }
+            /* values up to 0x14 seem fixed and similar to HEVAG's (block_align 0x02/04, bits_per_sample 0x10) */
+
+            vgmstream.num_samples = read_s32(sf, ww.fmt_offset + 0x18)?;
+            /* 0x1c: null?
+             * 0x20: data_size without seek_size */
+            let seek_size = read_u32(sf, ww.fmt_offset + 0x24)? as usize;
+
+            start_offset += seek_size;
+            ww.data_size -= seek_size as isize;
+
+            /* OPUS is VBR so this is very approximate percent, meh */
+            if ww.prefetch {
+                vgmstream.num_samples = (vgmstream.num_samples as f64
+                    * (ww.file_size - start_offset as isize) as f64
+                    / ww.data_size as f64) as i32;
+                ww.data_size = ww.file_size - start_offset as isize;
+            }
+
+            /* frames have a per-packet header (size + state), no Ogg pages */
+            let skip = switch_opus_get_encoder_delay(start_offset, sf); /* should be 120 */
+            encoder_delay = skip;
+
+            vgmstream.codec_data = Some(init_ffmpeg_switch_opus(
+                sf,
+                start_offset,
+                ww.data_size as usize,
+                vgmstream.channels,
+                skip,
+                vgmstream.sample_rate,
+            )?);
+            vgmstream.coding_type = CodingType::coding_FFmpeg;
+            vgmstream.layout_type = LayoutType::layout_none;
+        }
+        WwiseCodec::OPUS => {
+            /* PC/mobile/etc, rare (most games use Vorbis) */
+            if ww.block_size != 0 || ww.bits_per_sample != 0 {
+                return Err(VgmstreamError::BadHeader { meta: "WWISE", offset: ww.fmt_offset + 0x0c, field: "block size" });
+            }
+
+            /* extra: size 0x12 */
+            vgmstream.num_samples = read_s32(sf, ww.fmt_offset + 0x18)?;
+            /* 0x1c: null?
+             * 0x20: data_size without seek_size */
+            let seek_size = read_u32(sf, ww.fmt_offset + 0x24)? as usize;
+
+            start_offset += seek_size;
+            ww.data_size -= seek_size as isize;
+
+            /* OPUS is VBR so this is very approximate percent, meh */
+            if ww.prefetch {
+                vgmstream.num_samples = (vgmstream.num_samples as f64
+                    * (ww.file_size - start_offset as isize) as f64
+                    / ww.data_size as f64) as i32;
+                ww.data_size = ww.file_size - start_offset as isize;
+            }
+
+            /* data is a standard Ogg Opus stream, FFmpeg applies the pre-skip from its OpusHead */
+            if is_id32be(sf, start_offset, "OggS") {
+                let head_offset = start_offset + 0x1b + read_u8(sf, start_offset + 0x1a)? as usize;
+                if is_id32be(sf, head_offset, "Opus") {
+                    encoder_delay = read_u16le(sf, head_offset + 0x0a)? as i32;
+                }
+            }
+            let ffmpeg_data = init_ffmpeg_offset(sf, start_offset as u64, ww.data_size as u64)?;
+
+            vgmstream.codec_data = Some(VGMStreamCodecData::CustomFFmpeg(ffmpeg_data));
+            vgmstream.coding_type = CodingType::coding_FFmpeg;
+            vgmstream.layout_type = LayoutType::layout_none;
+        }
+        WwiseCodec::OPUSCPR => {
+            /* CD Projekt RED's Ogg Opus [Cyberpunk 2077 (PC)] */
+
+            /* data/riff sizes are wrong (see parse_wwise), so use the whole file;
+             * no samples in the header either */
+            let data_size = ww.file_size - ww.data_offset as isize;
+            let ffmpeg_data = init_ffmpeg_offset(sf, ww.data_offset as u64, data_size as u64)?;
+            vgmstream.num_samples = ffmpeg_get_samples(&ffmpeg_data);
+
+            vgmstream.codec_data = Some(VGMStreamCodecData::CustomFFmpeg(ffmpeg_data));
+            vgmstream.coding_type = CodingType::coding_FFmpeg;
+            vgmstream.layout_type = LayoutType::layout_none;
+        }
         WwiseCodec::OPUSWW => {
             let mut cfg: OpusConfig = Default::default();
 
             if ww.block_size != 0 || ww.bits_per_sample != 0 {
-                return None;
+                return Err(VgmstreamError::BadHeader { meta: "WWISE", offset: ww.fmt_offset + 0x0c, field: "block size" });
             }
             if ww.seek_offset == 0 {
-                return None;
+                return Err(VgmstreamError::BadHeader { meta: "WWISE", offset: 0, field: "seek table" });
             }
             if ww.channels > 255 {
-                return None;
+                return Err(VgmstreamError::BadHeader { meta: "WWISE", offset: ww.fmt_offset + 0x02, field: "channels" });
             } /* opus limit */
 
             cfg.channels = ww.channels as u8;
@@ -416,17 +711,17 @@ fn init_vgmstream_wwise_bnk(sf: &mut Streamfile, p_prefetch: bool) -> Option<VGM
 
             /* extra: size 0x10 (though last 2 fields are beyond, AK plz) */
             /* 0x12: samples per frame */
-            vgmstream.num_samples = read_s32(sf, ww.fmt_offset + 0x18);
-            cfg.table_count = read_u32(sf, ww.fmt_offset + 0x1c) as i32; /* same as seek size / 2 */
-            cfg.skip = read_u16(sf, ww.fmt_offset + 0x20) as i32;
+            vgmstream.num_samples = read_s32(sf, ww.fmt_offset + 0x18)?;
+            cfg.table_count = read_u32(sf, ww.fmt_offset + 0x1c)? as i32; /* same as seek size / 2 */
+            cfg.skip = read_u16(sf, ww.fmt_offset + 0x20)? as i32;
             /* 0x22: codec version */
-            let mut mapping = read_u8(sf, ww.fmt_offset + 0x23);
+            let mut mapping = read_u8(sf, ww.fmt_offset + 0x23)?;
             if mapping == 1 && ww.channels > 8 {
-                return None;
+                return Err(VgmstreamError::BadHeader { meta: "WWISE", offset: ww.fmt_offset + 0x23, field: "channel mapping" });
             } /* mapping not defined */
 
-            if read_u8(sf, ww.fmt_offset + 0x22) != 1 {
-                return None;
+            if read_u8(sf, ww.fmt_offset + 0x22)? != 1 {
+                return Err(VgmstreamError::UnsupportedCodec { meta: "WWISE", codec: format!("Opus version {}", read_u8(sf, ww.fmt_offset + 0x22)?) });
             }
 
             /* OPUS is VBR so this is very approximate percent, meh */
@@ -452,19 +747,23 @@ fn init_vgmstream_wwise_bnk(sf: &mut Streamfile, p_prefetch: bool) -> Option<VGM
                 ];
 
                 if ww.channels > 8 {
-                    return None;
+                    return Err(VgmstreamError::BadHeader { meta: "WWISE", offset: ww.fmt_offset + 0x02, field: "channels" });
                 } /* matrix limit */
 
                 /* find coupled (stereo) OPUS streams (simplification of ChannelConfigToMapping) */
-                match ww.channel_layout {
-                    ChannelMapping::mapping_7POINT1_surround => {  cfg.coupled_count = 3; }   /* 2ch+2ch+2ch+1ch+1ch, 5 streams */
-                    ChannelMapping::mapping_5POINT1_surround |                                  /* 2ch+2ch+1ch+1ch, 4 streams */
-                    ChannelMapping::mapping_QUAD_side => {         cfg.coupled_count = 2; }   /* 2ch+2ch, 2 streams */
-                    ChannelMapping::mapping_2POINT1_xiph |                                      /* 2ch+1ch, 2 streams */
-                    ChannelMapping::mapping_STEREO => {            cfg.coupled_count = 1; }   /* 2ch, 1 stream */
-                    _ => {                        cfg.coupled_count = 0; }   /* 1ch, 1 stream */
+                let is_layout = |mapping: ChannelMapping| ww.channel_layout == mapping as u32;
+                cfg.coupled_count = if is_layout(ChannelMapping::mapping_7POINT1_surround) {
+                    3 /* 2ch+2ch+2ch+1ch+1ch, 5 streams */
+                } else if is_layout(ChannelMapping::mapping_5POINT1_surround)   /* 2ch+2ch+1ch+1ch, 4 streams */
+                    || is_layout(ChannelMapping::mapping_QUAD_side) {           /* 2ch+2ch, 2 streams */
+                    2
+                } else if is_layout(ChannelMapping::mapping_2POINT1_xiph)       /* 2ch+1ch, 2 streams */
+                    || is_layout(ChannelMapping::mapping_STEREO) {              /* 2ch, 1 stream */
+                    1
+                } else {
+                    0 /* 1ch, 1 stream */
                     //TODO: AK OPUS doesn't seem to handle others mappings, though AK's .h imply they exist (uses 0 coupleds?)
-                }
+                };
 
                 /* total number internal OPUS streams (should be >0) */
                 cfg.stream_count = ww.channels - cfg.coupled_count;
@@ -490,31 +789,120 @@ fn init_vgmstream_wwise_bnk(sf: &mut Streamfile, p_prefetch: bool) -> Option<VGM
             } else {
                 /* mapping 0: standard opus (implicit mono/stereo)  */
                 if ww.channels > 2 {
-                    return None;
+                    return Err(VgmstreamError::BadHeader { meta: "WWISE", offset: ww.fmt_offset + 0x02, field: "channels" });
                 }
             }
 
             /* Wwise Opus saves all frame sizes in the seek table */
+            encoder_delay = cfg.skip;
             vgmstream.codec_data =
-                init_ffmpeg_wwise_opus(sf, ww.data_offset, ww.data_size as usize, &mut cfg);
-            // vgmstream.codec_data = codec;
-            if vgmstream.codec_data.is_none() {
-                return None;
-            }
+                Some(init_ffmpeg_wwise_opus(sf, ww.data_offset, ww.data_size as usize, &mut cfg)?);
             vgmstream.coding_type = CodingType::coding_FFmpeg;
             vgmstream.layout_type = LayoutType::layout_none;
         }
+        WwiseCodec::PTADPCM => {
+            /* substitutes IMA as default ADPCM codec */
+            if ww.bits_per_sample != 4 {
+                return Err(VgmstreamError::BadHeader { meta: "WWISE", offset: ww.fmt_offset + 0x0e, field: "bits per sample" });
+            }
+            if ww.block_size != 0x24 * ww.channels && ww.block_size != 0x104 * ww.channels {
+                return Err(VgmstreamError::BadHeader { meta: "WWISE", offset: ww.fmt_offset + 0x0c, field: "block size" });
+            }
+
+            vgmstream.coding_type = CodingType::coding_PTADPCM;
+            vgmstream.layout_type = LayoutType::layout_interleave;
+            vgmstream.interleave_block_size = (ww.block_size / ww.channels) as isize;
+
+            if ww.prefetch {
+                ww.data_size = ww.file_size - ww.data_offset as isize;
+            }
+
+            vgmstream.num_samples = ptadpcm_bytes_to_samples(ww.data_size, ww.channels, vgmstream.interleave_block_size);
+        }
         _ => {
-            println!("{} not implemented!", ww.codec);
-            return None;
+            return Err(VgmstreamError::UnsupportedCodec { meta: "WWISE", codec: ww.codec.to_string() });
         }
     }
 
-    vgmstream.open_stream(sf, start_offset as isize);
-    return Some(vgmstream);
+    vgmstream.markers = parse_wwise_markers(sf, &ww)?;
+    wwise_apply_encoder_delay(&mut vgmstream, encoder_delay);
+
+    vgmstream.open_stream(sf, start_offset as isize)?;
+    return Ok(vgmstream);
 }
 
-fn parse_wwise(sf: &mut Streamfile, ww: &mut WwiseHeader) -> bool {
+/* smpl/cue positions come from the original .wav, so they only move when the decoder drops an
+ * encoder delay that the stream's timeline counts:
+ * - Opus: pre-skip is removed by the decoder but smpl/cue positions include it
+ * - Vorbis: libvorbis only drops the first packet's half window, which the encoder doesn't count
+ * - XMA: the start skip is dropped from num_samples only, smpl/cue are already without it
+ * - AAC/XWMA: FFmpeg trims the container's delay, so output lines up with the .wav */
+pub(crate) fn wwise_apply_encoder_delay(vgmstream: &mut VGMStream, encoder_delay: i32) {
+    if encoder_delay <= 0 {
+        return;
+    }
+
+    if vgmstream.loop_flag {
+        vgmstream.loop_start_sample = (vgmstream.loop_start_sample - encoder_delay).max(0);
+        vgmstream.loop_end_sample = (vgmstream.loop_end_sample - encoder_delay).max(0);
+    }
+    for marker in vgmstream.markers.iter_mut() {
+        marker.position = (marker.position - encoder_delay).max(0);
+    }
+}
+
+/* Reads "cue " points plus their "LIST/adtl/labl" names, left by the authoring tool from the
+ * original .wav (used by games for music sync) */
+fn parse_wwise_markers(sf: &mut Streamfile, ww: &WwiseHeader) -> Result<Vec<VGMStreamMarker>> {
+    let read_u32 = if ww.big_endian { read_u32be } else { read_u32le };
+    let mut markers: Vec<VGMStreamMarker> = Vec::new();
+
+    if ww.cue_offset == 0 || ww.cue_size < 0x04 {
+        return Ok(markers);
+    }
+
+    /* cue entries: id, position (play order), fourcc, chunk start, block start, sample offset */
+    let cue_count = read_u32(sf, ww.cue_offset)? as usize;
+    if cue_count > (ww.cue_size as usize - 0x04) / 0x18 {
+        return Err(VgmstreamError::BadHeader { meta: "WWISE", offset: ww.cue_offset, field: "cue count" });
+    }
+
+    let mut cue_ids: Vec<u32> = Vec::with_capacity(cue_count);
+    for i in 0..cue_count {
+        let entry_offset = ww.cue_offset + 0x04 + i * 0x18;
+        cue_ids.push(read_u32(sf, entry_offset + 0x00)?);
+        markers.push(VGMStreamMarker {
+            position: read_u32(sf, entry_offset + 0x14)? as i32,
+            name: String::new(),
+        });
+    }
+
+    /* "adtl" subchunks, padded to even sizes like regular RIFF */
+    if ww.list_offset != 0 {
+        let mut offset = ww.list_offset + 0x04;
+        let list_end = std::cmp::min(ww.list_offset + ww.list_size as usize, ww.file_size as usize);
+        while offset + 0x08 <= list_end {
+            let chunk_size = read_u32(sf, offset + 0x04)? as usize;
+            if chunk_size > list_end - offset - 0x08 {
+                vgm_log!("WWISE: wrong adtl chunk size at {:x}", offset);
+                break;
+            }
+            /* "note" and "ltxt" also exist but aren't used */
+            if is_id32be(sf, offset, "labl") && chunk_size > 0x04 {
+                let cue_id = read_u32(sf, offset + 0x08)?;
+                if let Some(index) = cue_ids.iter().position(|&id| id == cue_id) {
+                    markers[index].name = read_string(sf, offset + 0x0c, chunk_size - 0x04);
+                }
+            }
+            offset += 0x08 + chunk_size + (chunk_size & 1);
+        }
+    }
+
+    markers.sort_by_key(|marker| marker.position);
+    return Ok(markers);
+}
+
+fn parse_wwise(sf: &mut Streamfile, ww: &mut WwiseHeader) -> Result<()> {
     ww.big_endian = is_id32be(sf, 0x00, "RIFX"); /* RIFF size not useful to detect, see below */
 
     let read_u32 = if ww.big_endian {
@@ -531,7 +919,7 @@ fn parse_wwise(sf: &mut Streamfile, ww: &mut WwiseHeader) -> bool {
     ww.file_size = sf.get_size(std::ptr::null_mut()) as isize;
 
     if !is_id32be(sf, 0x08, "WAVE") && !is_id32be(sf, 0x08, "XWMA") {
-        return false;
+        return Err(VgmstreamError::UnknownFormat { filename: sf.name.clone() });
     }
 
     let mut rc: ChunkType = Default::default();
@@ -582,25 +970,35 @@ fn parse_wwise(sf: &mut Streamfile, ww: &mut WwiseHeader) -> bool {
                 ww.meta_offset = rc.offset as usize;
                 ww.meta_size = rc.size as isize;
             }
+            0x63756520 => {
+                /* "cue " */
+                ww.cue_offset = rc.offset as usize;
+                ww.cue_size = rc.size as isize;
+            }
+            0x4C495354 => {
+                /* "LIST" */
+                if is_id32be(sf, rc.offset as usize, "adtl") {
+                    ww.list_offset = rc.offset as usize;
+                    ww.list_size = rc.size as isize;
+                }
+            }
             0x66616374 => {
                 /* "fact" */
                 /* Wwise never uses fact, but if somehow some file does uncomment the following: */
-                //if (size == 0x10 && read_u32be(offset + 0x04, sf) == 0x4C794E20) /* "LyN " */
+                //if (size == 0x10 && read_u32be(offset + 0x04, sf)? == 0x4C794E20) /* "LyN " */
                 //    goto fail; /* ignore LyN RIFF */
-                return false;
+                return Err(VgmstreamError::UnknownFormat { filename: sf.name.clone() });
             }
 
             /* "XMAc": rare XMA2 physical loop regions (loop_start_b, loop_end_b, loop_subframe_data)
              *         Can appear even in the file doesn't loop, maybe it's meant to be the playable physical region */
-            /* "LIST": leftover 'cue' info from OG .wavs (ex. loop starts in Platinum Games) */
             /* "JUNK": optional padding for aligment (0-size JUNK exists too) */
             /* "akd ": extra info for Wwise? (wave peaks/loudness/HDR envelope?) */
             _ => {
                 /* mainly for incorrectly ripped wems, but should allow truncated wems
                  * (could also check that fourcc is ASCII)  */
                 if rc.offset + rc.size > file_size {
-                    println!("WWISE: broken .wem (bad extract?)");
-                    return false;
+                    return Err(VgmstreamError::Truncated { meta: "WWISE", offset: rc.offset as usize, size: rc.size as usize });
                 }
             }
         }
@@ -613,57 +1011,67 @@ fn parse_wwise(sf: &mut Streamfile, ww: &mut WwiseHeader) -> bool {
     if ww.xma2_offset != 0 {
         /* pseudo-XMA2WAVEFORMAT, "fmt"+"XMA2" (common) or only "XMA2" [Too Human (X360)] */
         ww.format = 0x0165; /* signal for below */
-    // xma2_parse_xma2_chunk(sf, ww.xma2_offset, &ww.channels, &ww.sample_rate, &ww.loop_flag, &ww.num_samples, &ww.loop_start_sample, &ww.loop_end_sample);
+        let info = xma2_parse_xma2_chunk(sf, ww.xma2_offset)?;
+        ww.channels = info.channels;
+        ww.sample_rate = info.sample_rate;
+        ww.loop_flag = info.loop_flag;
+        ww.num_samples = info.num_samples;
+        ww.loop_start_sample = info.loop_start_sample;
+        ww.loop_end_sample = info.loop_end_sample;
     } else {
         /* pseudo-WAVEFORMATEX */
         if ww.fmt_size < 0x10 {
-            return false;
-        }
-        ww.format = read_u16(sf, ww.fmt_offset + 0x00) as i32;
-        ww.channels = read_u16(sf, ww.fmt_offset + 0x02) as i32;
-        ww.sample_rate = read_u32(sf, ww.fmt_offset + 0x04) as i32;
-        ww.avg_bitrate = read_u32(sf, ww.fmt_offset + 0x08) as i32;
-        ww.block_size = read_u16(sf, ww.fmt_offset + 0x0c) as i32;
-        ww.bits_per_sample = read_u16(sf, ww.fmt_offset + 0x0e) as i32;
+            return Err(VgmstreamError::BadHeader { meta: "WWISE", offset: ww.fmt_offset, field: "fmt size" });
+        }
+        ww.format = read_u16(sf, ww.fmt_offset + 0x00)? as i32;
+        ww.channels = read_u16(sf, ww.fmt_offset + 0x02)? as i32;
+        ww.sample_rate = read_u32(sf, ww.fmt_offset + 0x04)? as i32;
+        ww.avg_bitrate = read_u32(sf, ww.fmt_offset + 0x08)? as i32;
+        ww.block_size = read_u16(sf, ww.fmt_offset + 0x0c)? as i32;
+        ww.bits_per_sample = read_u16(sf, ww.fmt_offset + 0x0e)? as i32;
         if ww.fmt_size > 0x10 && ww.format != 0x0165 && ww.format != 0x0166 {
             /* ignore XMAWAVEFORMAT */
-            ww.extra_size = read_u16(sf, ww.fmt_offset + 0x10) as isize;
+            ww.extra_size = read_u16(sf, ww.fmt_offset + 0x10)? as isize;
         }
         if ww.extra_size >= 0x06 {
             /* always present (actual RIFFs only have it in WAVEFORMATEXTENSIBLE) */
             /* mostly WAVEFORMATEXTENSIBLE's bitmask (see AkSpeakerConfig.h) */
-            let mapping32 = read_u32(sf, ww.fmt_offset + 0x14);
-            // ww.channel_layout = mapping32.try_into().unwrap();
+            ww.channel_layout = read_u32(sf, ww.fmt_offset + 0x14)?;
             /* later games (+2018?) have a pseudo-format instead to handle more cases:
              * - 8b: uNumChannels
              * - 4b: eConfigType  (0=none, 1=standard, 2=ambisonic)
              * - 19b: uChannelMask */
-            if (ww.channel_layout as u32 & 0xFF) == ww.channels as u32 {
-                ww.channel_type = ((mapping32 >> 8) & 0x0F) as u8;
-                ww.channel_layout = (mapping32 >> 12).try_into().unwrap();
+            if (ww.channel_layout & 0xFF) == ww.channels as u32 {
+                ww.channel_type = ((ww.channel_layout >> 8) & 0x0F) as u8;
+                ww.channel_layout >>= 12;
             }
         }
 
         if ww.format == 0x0166 { /* XMA2WAVEFORMATEX in fmt */
-            // xma2_parse_fmt_chunk_extra(sf, ww.fmt_offset, &ww.loop_flag, &ww.num_samples, &ww.loop_start_sample, &ww.loop_end_sample, ww.big_endian);
+            if let Some(info) = xma2_parse_fmt_chunk_extra(sf, ww.fmt_offset, ww.big_endian)? {
+                ww.loop_flag = info.loop_flag;
+                ww.num_samples = info.num_samples;
+                ww.loop_start_sample = info.loop_start_sample;
+                ww.loop_end_sample = info.loop_end_sample;
+            }
         }
     }
     /* common loops ("XMA2" chunks already read them) */
     if ww.smpl_offset != 0 {
         if ww.smpl_size >= 0x34
-                && read_u32(sf, ww.smpl_offset + 0x1c) == 1           /* loop count */
-                && read_u32(sf, ww.smpl_offset + 0x24 + 0x04) == 0
+                && read_u32(sf, ww.smpl_offset + 0x1c)? == 1           /* loop count */
+                && read_u32(sf, ww.smpl_offset + 0x24 + 0x04)? == 0
         {
             /* loop type */
             ww.loop_flag = true;
-            ww.loop_start_sample = read_u32(sf, ww.smpl_offset + 0x24 + 0x8) as i32;
-            ww.loop_end_sample = read_u32(sf, ww.smpl_offset + 0x24 + 0xc) as i32 + 1;
+            ww.loop_start_sample = read_u32(sf, ww.smpl_offset + 0x24 + 0x8)? as i32;
+            ww.loop_end_sample = read_u32(sf, ww.smpl_offset + 0x24 + 0xc)? as i32 + 1;
             /* +1 like standard RIFF */
         }
     }
 
     if ww.data_offset == 0 {
-        return false;
+        return Err(VgmstreamError::BadHeader { meta: "WWISE", offset: 0, field: "data chunk" });
     }
 
     /* format to codec */
@@ -722,9 +1130,9 @@ fn parse_wwise(sf: &mut Streamfile, ww: &mut WwiseHeader) -> bool {
         _ => {
             /* some .wav may end up here, only report in .wem cases (newer codecs) */
             if ww.is_wem {
-                println!("WWISE: unknown codec 0x{:x} (report)", ww.format)
+                return Err(VgmstreamError::UnsupportedCodec { meta: "WWISE", codec: format!("format 0x{:x}", ww.format) });
             }
-            return false;
+            return Err(VgmstreamError::UnknownFormat { filename: sf.name.clone() });
         }
     }
 
@@ -751,8 +1159,8 @@ fn parse_wwise(sf: &mut Streamfile, ww: &mut WwiseHeader) -> bool {
          * but it's possible to pre-fetch small files too [Punch Out!! (Wii)] */
         if ww.data_offset as isize + ww.data_size - ww.file_size < 0x5000 && ww.file_size > 0x10000
         {
-            println!("WWISE: wrong expected size (re-rip?)");
-            return false;
+            vgm_logi!("WWISE: wrong expected size (re-rip?)");
+            return Err(VgmstreamError::Truncated { meta: "WWISE", offset: ww.data_offset, size: ww.data_size as usize });
         }
 
         if ww.codec == WwiseCodec::PCM
@@ -769,8 +1177,8 @@ fn parse_wwise(sf: &mut Streamfile, ww: &mut WwiseHeader) -> bool {
         {
             ww.prefetch = true; /* only seen those, probably all exist (missing XWMA, AAC, HEVAG) */
         } else {
-            println!("WWISE: wrong expected size, maybe prefetch (report)");
-            return false;
+            vgm_logi!("WWISE: wrong expected size, maybe prefetch (report)");
+            return Err(VgmstreamError::Truncated { meta: "WWISE", offset: ww.data_offset, size: ww.data_size as usize });
         }
     }
 
@@ -783,5 +1191,5 @@ fn parse_wwise(sf: &mut Streamfile, ww: &mut WwiseHeader) -> bool {
         }
     }
 
-    return true;
+    return Ok(());
 }
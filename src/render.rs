@@ -1,20 +1,389 @@
-use crate::vgmstream::{VGMStream, LayoutType};
+use crate::vgmstream::{VGMStream, CodingType, LayoutType, VGMStreamCodecData};
+use crate::decode::decode_do_loop;
+use crate::coding::ffmpeg::seek_ffmpeg;
+use crate::coding::vorbis::seek_vorbis_custom;
 use crate::layout::blocked::render_vgmstream_blocked;
 use crate::layout::interleave::render_vgmstream_interleave;
 use crate::layout::flat::render_vgmstream_flat;
 use crate::layout::segmented::render_vgmstream_segmented;
+use crate::layout::layered::render_vgmstream_layered;
 
+/* Decodes samples into the buffer, applying config (pads, trims, loops, fades) if enabled.
+ * Returns samples done, which may be less than requested once the play duration is reached. */
 pub fn render_vgmstream(buffer: &mut Vec<i16>, sample_count: i32, vgmstream: &mut VGMStream) -> i32 {
-    let samples_to_do = sample_count;
-    // let mut buf: Vec<i16> = vec![0; samples_to_do as usize * vgmstream.channels as usize * 2];
+    let mut samples_to_do = sample_count;
+    let mut samples_done = 0;
+    let mut outpos = 0; /* samples written in the buffer, "as if mixed" */
+    let output_channels;
+
+    /* simple mode with no settings (just skip everything below) */
     if !vgmstream.config_enabled {
         render_layout(buffer, samples_to_do, vgmstream);
-        // buf = mix_vgmstream(samples_to_do, vgmstream);
+        // mix_vgmstream(buffer, samples_to_do, vgmstream);
+        return samples_to_do;
+    }
+
+    output_channels = vgmstream.pstate.output_channels;
+
+    /* trim may go first since it doesn't need output nor changes totals */
+    if vgmstream.pstate.trim_begin_left != 0 {
+        render_trim(vgmstream);
+    }
+
+    /* adds empty samples to buf */
+    if vgmstream.pstate.pad_begin_left != 0 {
+        let done = render_pad_begin(vgmstream, buffer, samples_to_do);
+        samples_done += done;
+        samples_to_do -= done;
+        outpos += done;
+    }
+
+    /* end padding (before to avoid decoding if possible, but must be inside pad region) */
+    if vgmstream.config.play_forever == 0
+        && vgmstream.pstate.play_position >= vgmstream.pstate.pad_end_start
+        && samples_to_do != 0
+    {
+        let start = (outpos * output_channels) as usize;
+        let done = render_pad_end(vgmstream, &mut buffer[start..], samples_to_do);
+        samples_done += done;
+        samples_to_do -= done;
+        outpos += done;
+    }
+
+    /* main decode */
+    if samples_to_do > 0 {
+        let start = (outpos * output_channels) as usize;
+        let done = if start == 0 {
+            render_layout(buffer, samples_to_do, vgmstream);
+            samples_to_do
+        } else {
+            /* layouts decode from the buffer start, so use the (reusable) temp buffer for the rest */
+            let samples = (samples_to_do * output_channels) as usize;
+            let mut tmpbuf = std::mem::take(&mut vgmstream.tmpbuf);
+            if tmpbuf.len() < samples {
+                tmpbuf.resize(samples, 0);
+                vgmstream.tmpbuf_size = samples as isize;
+            }
+            render_layout(&mut tmpbuf, samples_to_do, vgmstream);
+            buffer[start..start + samples].copy_from_slice(&tmpbuf[..samples]);
+            vgmstream.tmpbuf = tmpbuf;
+            samples_to_do
+        };
+
+        // mix_vgmstream(&mut buffer[start..], done, vgmstream);
+
+        samples_done += done;
+
+        if vgmstream.config.play_forever == 0 {
+            /* simple fadeout */
+            if vgmstream.pstate.fade_left != 0
+                && vgmstream.pstate.play_position + done >= vgmstream.pstate.fade_start
+            {
+                render_fade(vgmstream, &mut buffer[start..], done);
+            }
+
+            /* silence leftover buf samples (rarely used when no fade is set) */
+            if vgmstream.pstate.play_position + done >= vgmstream.pstate.pad_end_start {
+                render_pad_end(vgmstream, &mut buffer[start..], done);
+            }
+        }
+    }
+
+    vgmstream.pstate.play_position += samples_done;
+
+    /* signal end */
+    if vgmstream.config.play_forever == 0
+        && vgmstream.pstate.play_position > vgmstream.pstate.play_duration
+    {
+        let mut excess = vgmstream.pstate.play_position - vgmstream.pstate.play_duration;
+        if excess > sample_count {
+            excess = sample_count;
+        }
+
+        samples_done = sample_count - excess;
+
+        vgmstream.pstate.play_position = vgmstream.pstate.play_duration;
+    }
+
+    return samples_done;
+}
+
+/* adds silence before the decoded samples */
+fn render_pad_begin(vgmstream: &mut VGMStream, buffer: &mut [i16], samples_to_do: i32) -> i32 {
+    let channels = vgmstream.pstate.output_channels;
+    let mut to_do = vgmstream.pstate.pad_begin_left;
+    if to_do > samples_to_do {
+        to_do = samples_to_do;
+    }
+
+    buffer[..(to_do * channels) as usize].fill(0);
+    vgmstream.pstate.pad_begin_left -= to_do;
+
+    return to_do;
+}
+
+/* fades out the decoded samples with a linear curve, silencing what comes after the fade */
+fn render_fade(vgmstream: &mut VGMStream, buffer: &mut [i16], samples_left: i32) -> i32 {
+    let ps = &mut vgmstream.pstate;
+    let channels = ps.output_channels as usize;
+    let mut to_do = ps.fade_left;
+    let start;
+    let mut fade_pos;
+
+    if ps.play_position < ps.fade_start {
+        start = samples_left - (ps.play_position + samples_left - ps.fade_start);
+        fade_pos = 0;
+    } else {
+        start = 0;
+        fade_pos = ps.play_position - ps.fade_start;
+    }
+
+    if to_do > samples_left - start {
+        to_do = samples_left - start;
+    }
+
+    for s in start..start + to_do {
+        let fadedness = (ps.fade_duration - fade_pos) as f64 / ps.fade_duration as f64;
+        for ch in 0..channels {
+            let pos = s as usize * channels + ch;
+            buffer[pos] = (buffer[pos] as f64 * fadedness) as i16;
+        }
+        fade_pos += 1;
+    }
+
+    ps.fade_left -= to_do;
+
+    /* next samples after fade end would be pad end/silence, so we can just memset */
+    buffer[(start + to_do) as usize * channels..samples_left as usize * channels].fill(0);
+    return samples_left;
+}
+
+/* adds silence after the decoded samples (also used to silence decoded samples past the pad end) */
+fn render_pad_end(vgmstream: &mut VGMStream, buffer: &mut [i16], samples_to_do: i32) -> i32 {
+    let ps = &vgmstream.pstate;
+    let channels = ps.output_channels;
+    let skip;
+    let mut to_do;
+
+    /* pad end works like fades, where part of buf samples and part padding (silent),
+     * calc exact totals (beyond pad end normally is silence, except with segmented layout) */
+    if ps.play_position < ps.pad_end_start {
+        skip = ps.pad_end_start - ps.play_position;
+        to_do = ps.pad_end_duration;
+    } else {
+        skip = 0;
+        to_do = (ps.pad_end_start + ps.pad_end_duration) - ps.play_position;
+    }
+
+    if to_do > samples_to_do - skip {
+        to_do = samples_to_do - skip;
+    }
+    if to_do < 0 {
+        /* when play_position is past pad end, ex. last frame */
+        to_do = 0;
+    }
+
+    buffer[(skip * channels) as usize..((skip + to_do) * channels) as usize].fill(0);
+    return skip + to_do;
+}
+
+/* garbage buffer for trims and seeks, allocated on first use */
+fn setup_tmpbuf(vgmstream: &mut VGMStream) {
+    if vgmstream.tmpbuf.is_empty() {
+        vgmstream.tmpbuf_size = 0x10000; /* for all channels */
+        vgmstream.tmpbuf = vec![0; vgmstream.tmpbuf_size as usize];
+    }
+}
+
+/* decodes and discards samples from the beginning */
+fn render_trim(vgmstream: &mut VGMStream) {
+    let samples = vgmstream.pstate.trim_begin_left;
+
+    seek_force_decode(vgmstream, samples);
+    vgmstream.pstate.trim_begin_left = 0;
+}
+
+/* decodes and discards samples from the current position */
+fn seek_force_decode(vgmstream: &mut VGMStream, samples: i32) {
+    setup_tmpbuf(vgmstream);
+
+    /* base channels, no need to apply mixing */
+    let buf_samples = vgmstream.tmpbuf_size as i32 / vgmstream.channels;
+    let mut tmpbuf = std::mem::take(&mut vgmstream.tmpbuf);
+    let mut samples = samples;
+
+    while samples > 0 {
+        let mut to_do = samples;
+        if to_do > buf_samples {
+            to_do = buf_samples;
+        }
+
+        render_layout(&mut tmpbuf, to_do, vgmstream);
+        /* no mixing */
+        samples -= to_do;
+    }
+
+    vgmstream.tmpbuf = tmpbuf;
+}
+
+/* pretend decoder reached loop end so state is set to loop start (only called after hit loop) */
+fn seek_force_loop(vgmstream: &mut VGMStream, loop_count: i32) {
+    if vgmstream.hit_loop == 0 {
+        return;
     }
-    
-    // TODO: config
-    
-    return samples_to_do;
+
+    vgmstream.loop_count = loop_count - 1; /* seeking to first loop must become ++ > 0 */
+    vgmstream.current_sample = vgmstream.loop_end_sample as isize;
+    decode_do_loop(vgmstream);
+}
+
+/* position in the decoder's timeline, with loops unrolled */
+fn get_decode_position(vgmstream: &VGMStream) -> i32 {
+    let loop_body = vgmstream.loop_end_sample - vgmstream.loop_start_sample;
+    let position = vgmstream.current_sample as i32;
+
+    if !vgmstream.loop_flag && vgmstream.loop_target == 0 {
+        return position;
+    }
+
+    /* loop_count reaches loop_target once the last loop ends (and playback continues to the stream end) */
+    let mut loops = vgmstream.loop_count;
+    if vgmstream.loop_target > 0 && loops >= vgmstream.loop_target {
+        loops = vgmstream.loop_target - 1;
+    }
+    return position + loops * loop_body;
+}
+
+/* Seeks with the codec's own means (for codecs that don't depend on channel offsets).
+ * Returns false if the codec can't, so samples must be decoded and discarded instead. */
+fn seek_codec(vgmstream: &mut VGMStream, num_sample: i32) -> bool {
+    if vgmstream.layout_type != LayoutType::layout_none {
+        return false;
+    }
+
+    match (vgmstream.coding_type, vgmstream.codec_data.as_mut()) {
+        (CodingType::coding_FFmpeg, Some(VGMStreamCodecData::CustomFFmpeg(data))) => {
+            seek_ffmpeg(data, num_sample);
+        }
+        (CodingType::coding_VORBIS_custom, Some(VGMStreamCodecData::CustomVorbis(data))) => {
+            seek_vorbis_custom(data, num_sample);
+            vgmstream.ch[0].offset = vgmstream.ch[0].channel_start_offset;
+        }
+        _ => return false,
+    }
+
+    vgmstream.current_sample = num_sample as isize;
+    vgmstream.samples_into_block = num_sample as isize;
+    return true;
+}
+
+/* Moves the decoder to a position in its timeline (loops unrolled, before applying pads/trims/fades) */
+fn seek_decode(vgmstream: &mut VGMStream, decode_sample: i32) {
+    let loop_body = vgmstream.loop_end_sample - vgmstream.loop_start_sample;
+    let is_looped = (vgmstream.loop_flag || vgmstream.loop_target > 0) && loop_body > 0;
+
+    /* find which loop the target falls into and the position within that pass */
+    let mut target_loop = 0;
+    if is_looped && decode_sample >= vgmstream.loop_end_sample {
+        target_loop = (decode_sample - vgmstream.loop_start_sample) / loop_body;
+        if vgmstream.loop_target > 0 && target_loop >= vgmstream.loop_target {
+            target_loop = vgmstream.loop_target - 1; /* last pass continues to the stream end */
+        }
+    }
+    let target_sample = decode_sample - target_loop * loop_body;
+
+    /* codecs with their own seeking can jump directly */
+    if vgmstream.coding_type == CodingType::coding_FFmpeg || vgmstream.coding_type == CodingType::coding_VORBIS_custom {
+        vgmstream.reset();
+
+        /* save the real loop start state first, as if the decoder had passed it */
+        if is_looped && target_sample >= vgmstream.loop_start_sample && seek_codec(vgmstream, vgmstream.loop_start_sample) {
+            decode_do_loop(vgmstream);
+        }
+
+        if seek_codec(vgmstream, target_sample) {
+            vgmstream.loop_count = target_loop;
+            if is_looped && vgmstream.loop_target > 0 && target_sample > vgmstream.loop_end_sample {
+                /* past the last loop end */
+                vgmstream.loop_count = vgmstream.loop_target;
+                vgmstream.loop_flag = false;
+            }
+            return;
+        }
+    }
+
+    /* can only decode forward, restart if needed */
+    if decode_sample < get_decode_position(vgmstream) {
+        vgmstream.reset();
+    }
+
+    /* skip whole loops by jumping to the target loop's start */
+    if target_loop > 0 && target_loop > vgmstream.loop_count {
+        if vgmstream.hit_loop == 0 {
+            let samples = vgmstream.loop_start_sample - vgmstream.current_sample as i32;
+            seek_force_decode(vgmstream, samples);
+            decode_do_loop(vgmstream); /* save loop start state */
+        }
+        seek_force_loop(vgmstream, target_loop);
+    }
+
+    let samples = decode_sample - get_decode_position(vgmstream);
+    seek_force_decode(vgmstream, samples);
+}
+
+/* Seeks to a sample in the final output (after applying config, if enabled).
+ * Decodes and discards samples when the codec can't seek by itself, so may be slow. */
+pub fn seek_vgmstream(vgmstream: &mut VGMStream, seek_sample: i32) {
+    let mut seek_sample = seek_sample;
+    let play_forever = vgmstream.config.play_forever != 0;
+
+    /* cleanup */
+    if seek_sample < 0 {
+        seek_sample = 0;
+    }
+
+    if !vgmstream.config_enabled {
+        /* will decode and loop until seek sample, but slower */
+        seek_decode(vgmstream, seek_sample);
+        return;
+    }
+
+    /* play forever can seek past max */
+    if seek_sample > vgmstream.pstate.play_duration && !play_forever {
+        seek_sample = vgmstream.pstate.play_duration;
+    }
+
+    let ps = vgmstream.pstate;
+    let decode_sample;
+    let pad_begin_left;
+    let trim_begin_left;
+
+    if seek_sample < ps.pad_begin_duration {
+        /* inside pad begin: decoder starts from the beginning (trim is applied later) */
+        decode_sample = 0;
+        pad_begin_left = ps.pad_begin_duration - seek_sample;
+        trim_begin_left = ps.trim_begin_duration;
+    } else {
+        decode_sample = seek_sample - ps.pad_begin_duration + ps.trim_begin_duration;
+        pad_begin_left = 0;
+        trim_begin_left = 0;
+    }
+
+    /* beyond the play duration nothing is decoded (except when playing forever) */
+    if play_forever || seek_sample < ps.pad_end_start {
+        seek_decode(vgmstream, decode_sample);
+    }
+
+    /* set state after seeking, as decoder resets restore it */
+    let ps = &mut vgmstream.pstate;
+    ps.pad_begin_left = pad_begin_left;
+    ps.trim_begin_left = trim_begin_left;
+    if seek_sample <= ps.fade_start {
+        ps.fade_left = ps.fade_duration;
+    } else {
+        ps.fade_left = (ps.fade_duration - (seek_sample - ps.fade_start)).max(0);
+    }
+    ps.play_position = seek_sample;
 }
 
 pub fn render_layout(buffer: &mut Vec<i16>, sample_count: i32, vgmstream: &mut VGMStream) {
@@ -75,14 +444,14 @@ pub fn render_layout(buffer: &mut Vec<i16>, sample_count: i32, vgmstream: &mut V
         LayoutType::layout_blocked_vid1 |
         LayoutType::layout_blocked_ubi_sce |
         LayoutType::layout_blocked_tt_ad => {
-            // render_vgmstream_blocked(buffer, sample_count, vgmstream);
+            render_vgmstream_blocked(buffer, sample_count, vgmstream);
         }
         LayoutType::layout_segmented => {
-            // render_vgmstream_segmented(buffer, sample_count,vgmstream);
+            render_vgmstream_segmented(buffer, sample_count, vgmstream);
+        }
+        LayoutType::layout_layered => {
+            render_vgmstream_layered(buffer, sample_count, vgmstream);
         }
-        // LayoutType::layout_layered => {
-        //     buf = render_vgmstream_layered(sample_count, vgmstream);
-        // }
         _ => {}
     }
 }
\ No newline at end of file
@@ -1,47 +1,62 @@
-use std::io::{BufReader, Cursor, Read, Seek};
-#[derive(Clone, PartialEq, Eq)]
+use std::cell::RefCell;
+use std::io::{Read, Seek, SeekFrom};
+use std::rc::Rc;
+
+use crate::constants::STREAMFILE_DEFAULT_BUFFER_SIZE;
+use crate::error::{Result, VgmstreamError};
+
+/* anything that can back a Streamfile (files, memory, archives, network...) */
+pub trait StreamfileReader: Read + Seek {}
+impl<T: Read + Seek> StreamfileReader for T {}
+
+/* Opens another file by full name, used to reach companion files. Custom openers can be set
+ * to read from a virtual filesystem (archives, memory, etc) instead of disk. */
+pub type StreamfileOpener = Rc<dyn Fn(&str) -> Result<Streamfile>>;
+
+/* underlying data, shared between clones of the same Streamfile */
+struct StreamfileSource {
+    reader: Box<dyn StreamfileReader>,
+    size: usize,
+}
+
+/* Reads are done on demand through a buffered window, so only the parts actually used are loaded.
+ * Clones share the source but keep their own window (like upstream reopening the file per channel),
+ * so channels reading far apart don't keep trashing each other's buffer. */
+#[derive(Clone)]
 pub struct Streamfile {
     pub stream_index: i32,
     pub name: String,
-    pub reader: Cursor<Vec<u8>>,
+
+    source: Rc<RefCell<StreamfileSource>>,
+    buf: Vec<u8>,                           /* window data (allocated on first read) */
+    buf_offset: usize,                      /* window start in the source */
+    buf_valid: usize,                       /* bytes loaded in the window */
+    pub buf_size: usize,                    /* max window size */
 
     pub read: Option<fn(&mut Streamfile, usize, usize, *mut std::ffi::c_void) -> Vec<u8>>,
     pub get_size: Option<fn(&mut Streamfile, *mut std::ffi::c_void) -> usize>,
     pub get_name: Option<fn(&mut Streamfile, *mut std::ffi::c_void) -> String>,
-    pub open: Option<fn(String) -> Option<Streamfile>>,
+    pub open: Option<StreamfileOpener>,
     pub close: Option<fn(&mut Streamfile, *mut std::ffi::c_void)>,
 
     pub data: *mut std::ffi::c_void,
 }
 
 impl Streamfile {
-    pub fn new(stream_index: i32, name: String, reader: Cursor<Vec<u8>>) -> Self {
-        Self {
-            stream_index,
-            name,
-            reader,
-            open: Some(Self::open_stdio),
-            read: Some(Self::read),
-            get_size: Some(Self::get_size),
-            get_name: Some(Self::get_name),
-            close: Some(Self::close),
-            data: std::ptr::null_mut(),
-        }
-    }
+    /* Opens a Streamfile over any Read + Seek source (name is used for extension checks) */
+    pub fn from_reader<R: Read + Seek + 'static>(name: String, reader: R) -> Result<Self> {
+        let mut reader = reader;
+        let size = reader.seek(SeekFrom::End(0))? as usize;
 
-    pub fn open_stdio(filename: String) -> Option<Self> {
-        let reader = std::fs::File::open(filename.clone()).ok()?;
-        let bytes = BufReader::new(reader).bytes();
-        let mut vec = Vec::new();
-        for byte in bytes {
-            vec.push(byte.unwrap());
-        }
-        // println!("{:?}", vec);
-        Some(Self {
+        Ok(Self {
             stream_index: 0,
-            name: filename.clone(),
-            reader: Cursor::new(vec),
-            open: Some(Self::open_stdio),
+            name,
+            source: Rc::new(RefCell::new(StreamfileSource { reader: Box::new(reader), size })),
+            buf: Vec::new(),
+            buf_offset: 0,
+            buf_valid: 0,
+            buf_size: STREAMFILE_DEFAULT_BUFFER_SIZE,
+            open: Some(Rc::new(|filename: &str| Self::open_stdio(filename.to_string()))),
             read: Some(Self::read),
             get_size: Some(Self::get_size),
             get_name: Some(Self::get_name),
@@ -50,25 +65,37 @@ impl Streamfile {
         })
     }
 
+    pub fn open_stdio(filename: String) -> Result<Self> {
+        let file = std::fs::File::open(&filename)?;
+        return Self::from_reader(filename, file);
+    }
+
+    /* Sets how companion files are opened (see open_streamfile) */
+    pub fn set_opener(&mut self, opener: StreamfileOpener) {
+        self.open = Some(opener);
+    }
+
     pub fn get_name(&mut self, _data: *mut std::ffi::c_void) -> String {
         self.name.clone()
     }
 
+    /* frees this clone's buffer (source is closed once all clones are dropped) */
     pub fn close(&mut self, _data: *mut std::ffi::c_void) {
-        self.reader = Cursor::new(Vec::new());
+        self.buf = Vec::new();
+        self.buf_offset = 0;
+        self.buf_valid = 0;
     }
 
+    /* returns the bytes actually read (less than length near EOF) */
     pub fn read(&mut self, offset: usize, length: usize, _data: *mut std::ffi::c_void) -> Vec<u8> {
         let mut frame = vec![0; length];
-        self.reader
-            .seek(std::io::SeekFrom::Start(offset as u64))
-            .unwrap();
-        self.reader.read_exact(&mut frame).unwrap();
+        let bytes = read_streamfile(self, offset, &mut frame);
+        frame.truncate(bytes);
         frame
     }
 
     pub fn get_size(&mut self, _data: *mut std::ffi::c_void) -> usize {
-        self.reader.get_ref().len()
+        self.source.borrow().size
     }
 }
 
@@ -77,8 +104,12 @@ impl Default for Streamfile {
         Self {
             stream_index: 0,
             name: String::new(),
-            reader: Cursor::new(Vec::new()),
-            open: Some(Self::open_stdio),
+            source: Rc::new(RefCell::new(StreamfileSource { reader: Box::new(std::io::Cursor::new(Vec::new())), size: 0 })),
+            buf: Vec::new(),
+            buf_offset: 0,
+            buf_valid: 0,
+            buf_size: STREAMFILE_DEFAULT_BUFFER_SIZE,
+            open: Some(Rc::new(|filename: &str| Self::open_stdio(filename.to_string()))),
             read: Some(Self::read),
             get_size: Some(Self::get_size),
             get_name: Some(Self::get_name),
@@ -88,14 +119,158 @@ impl Default for Streamfile {
     }
 }
 
+/* same file if they share the source */
+impl PartialEq for Streamfile {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.source, &other.source) && self.stream_index == other.stream_index && self.name == other.name
+    }
+}
+
+impl Eq for Streamfile {}
+
 impl std::fmt::Debug for Streamfile {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Streamfile")
             .field("stream_index", &self.stream_index)
+            .field("name", &self.name)
             .finish()
     }
 }
 
+/* Opens a file through the same opener as sf (so virtual filesystems keep working) */
+pub fn open_streamfile(sf: &Streamfile, pathname: &str) -> Result<Streamfile> {
+    let opener = match &sf.open {
+        Some(opener) => opener.clone(),
+        None => return Err(VgmstreamError::UnknownFormat { filename: pathname.to_string() }),
+    };
+
+    /* keep the opener so files opened from the new one come from the same place */
+    let mut new_sf = opener(pathname)?;
+    new_sf.open = Some(opener);
+    return Ok(new_sf);
+}
+
+/* Opens a file with the same name as sf but a different extension (ex. "bgm.acb" > "bgm.awb") */
+pub fn open_streamfile_by_ext(sf: &Streamfile, ext: &str) -> Result<Streamfile> {
+    return open_streamfile(sf, &replace_extension(&sf.name, ext));
+}
+
+/* Opens a file in the same dir as sf (filename may include subdirs) */
+pub fn open_streamfile_by_filename(sf: &Streamfile, filename: &str) -> Result<Streamfile> {
+    let path = &sf.name[..get_path_end(&sf.name)];
+    return open_streamfile(sf, &format!("{}{}", path, filename));
+}
+
+/* Reads a decryption key from "(name).(ext)key" or a generic ".(ext)key" (ex. "bgm.adxkey", ".adxkey") */
+pub fn read_key_file(sf: &Streamfile, max_size: usize) -> Option<Vec<u8>> {
+    let basename = &sf.name[get_path_end(&sf.name)..];
+    let ext = match basename.rfind('.') {
+        Some(pos) => basename[pos + 1..].to_ascii_lowercase(),
+        None => return None,
+    };
+
+    let key_ext = format!("{}key", ext);
+    let sf_key = open_streamfile_by_ext(sf, &key_ext).or_else(|_| open_streamfile_by_filename(sf, &format!(".{}", key_ext)));
+    let Ok(mut sf_key) = sf_key else {
+        return None;
+    };
+
+    let key_size = sf_key.get_size(std::ptr::null_mut());
+    if key_size == 0 || key_size > max_size {
+        return None;
+    }
+
+    let mut keybuf = vec![0; key_size];
+    if read_streamfile(&mut sf_key, 0x00, &mut keybuf) != key_size {
+        return None;
+    }
+    return Some(keybuf);
+}
+
+/* Reads part of another Streamfile as if it was a whole file */
+struct SubfileReader {
+    sf: Streamfile,
+    start: usize,
+    size: usize,
+    pos: u64,
+}
+
+impl Read for SubfileReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pos >= self.size as u64 {
+            return Ok(0);
+        }
+        let to_read = std::cmp::min(buf.len() as u64, self.size as u64 - self.pos) as usize;
+        let bytes = read_streamfile(&mut self.sf, self.start + self.pos as usize, &mut buf[..to_read]);
+        self.pos += bytes as u64;
+        Ok(bytes)
+    }
+}
+
+impl Seek for SubfileReader {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::End(offset) => self.size as i64 + offset,
+            SeekFrom::Current(offset) => self.pos as i64 + offset,
+        };
+        if new_pos < 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "seek before start"));
+        }
+        self.pos = new_pos as u64;
+        Ok(self.pos)
+    }
+}
+
+/* Opens part of sf as a new Streamfile (for files inside containers), optionally
+ * with a fake extension so metas that check it accept the subfile. */
+pub fn setup_subfile_streamfile(sf: &Streamfile, subfile_offset: usize, subfile_size: usize, extension: Option<&str>) -> Result<Streamfile> {
+    let file_size = sf.source.borrow().size;
+    if subfile_offset > file_size || subfile_size > file_size - subfile_offset {
+        return Err(VgmstreamError::Truncated { meta: "STREAMFILE", offset: subfile_offset, size: subfile_size });
+    }
+
+    let name = match extension {
+        Some(ext) => replace_extension(&sf.name, ext),
+        None => sf.name.clone(),
+    };
+
+    let reader = SubfileReader { sf: sf.clone(), start: subfile_offset, size: subfile_size, pos: 0 };
+    let mut new_sf = Streamfile::from_reader(name, reader)?;
+    new_sf.open = sf.open.clone();
+    return Ok(new_sf);
+}
+
+/* Makes a Streamfile from data in memory (for data rebuilt or deblocked by metas), named
+ * like sf with an optional fake extension so metas that check it accept the data. */
+pub fn setup_memory_streamfile(sf: &Streamfile, data: Vec<u8>, extension: Option<&str>) -> Result<Streamfile> {
+    let name = match extension {
+        Some(ext) => replace_extension(&sf.name, ext),
+        None => sf.name.clone(),
+    };
+
+    let mut new_sf = Streamfile::from_reader(name, std::io::Cursor::new(data))?;
+    new_sf.open = sf.open.clone();
+    return Ok(new_sf);
+}
+
+/* "path/name.ext" > "path/name.new_ext" (extension is added if missing) */
+fn replace_extension(name: &str, ext: &str) -> String {
+    let basename_start = get_path_end(name);
+    match name[basename_start..].rfind('.') {
+        Some(pos) => format!("{}.{}", &name[..basename_start + pos], ext),
+        None => format!("{}.{}", name, ext),
+    }
+}
+
+/* position after the last path separator (0 if none), both kinds as names may come from anywhere */
+fn get_path_end(name: &str) -> usize {
+    match name.rfind(|c| c == '/' || c == '\\') {
+        Some(pos) => pos + 1,
+        None => 0,
+    }
+}
+
 pub fn check_extensions(sf: &mut Streamfile, extensions: Vec<&str>) -> bool {
     let name = sf.name.clone();
     let mut ext = name.split('.').last().unwrap().to_string();
@@ -110,135 +285,169 @@ pub fn check_extensions(sf: &mut Streamfile, extensions: Vec<&str>) -> bool {
     false
 }
 
-pub fn read_u8(sf: &mut Streamfile, offset: usize) -> u8 {
-    let mut buf = [0; 1];
-    sf.reader
-        .seek(std::io::SeekFrom::Start(offset as u64))
-        .unwrap();
-    sf.reader.read_exact(&mut buf).unwrap();
-    buf[0]
+/* Reads from the source (unbuffered), returning bytes read until EOF or error */
+fn read_source(source: &RefCell<StreamfileSource>, offset: usize, buf: &mut [u8]) -> (usize, Option<std::io::Error>) {
+    let mut source = source.borrow_mut();
+    if offset >= source.size {
+        return (0, None);
+    }
+    if let Err(err) = source.reader.seek(SeekFrom::Start(offset as u64)) {
+        return (0, Some(err));
+    }
+
+    let mut done = 0;
+    while done < buf.len() {
+        match source.reader.read(&mut buf[done..]) {
+            Ok(0) => break,
+            Ok(bytes) => done += bytes,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => return (done, Some(err)),
+        }
+    }
+    (done, None)
+}
+
+/* Reads through the buffered window, refilling it as needed */
+fn read_buffered(sf: &mut Streamfile, offset: usize, buf: &mut [u8]) -> (usize, Option<std::io::Error>) {
+    let length = buf.len();
+
+    /* big reads would only trash the buffer */
+    if length >= sf.buf_size {
+        return read_source(&sf.source, offset, buf);
+    }
+
+    let mut done = 0;
+    while done < length {
+        let pos = offset + done;
+
+        /* inside the window: copy what we can */
+        if pos >= sf.buf_offset && pos < sf.buf_offset + sf.buf_valid {
+            let start = pos - sf.buf_offset;
+            let to_copy = std::cmp::min(sf.buf_valid - start, length - done);
+            buf[done..done + to_copy].copy_from_slice(&sf.buf[start..start + to_copy]);
+            done += to_copy;
+            continue;
+        }
+
+        /* outside: move the window to the current position */
+        if sf.buf.len() != sf.buf_size {
+            sf.buf = vec![0; sf.buf_size];
+        }
+        let (bytes, err) = read_source(&sf.source, pos, &mut sf.buf);
+        sf.buf_offset = pos;
+        sf.buf_valid = bytes;
+        if bytes == 0 {
+            return (done, err); /* EOF or error */
+        }
+    }
+
+    (done, None)
+}
+
+/* Reads into buf, returning the bytes read (rest is zeroed on short reads, like decoders expect) */
+pub fn read_streamfile(sf: &mut Streamfile, offset: usize, buf: &mut [u8]) -> usize {
+    let (done, _) = read_buffered(sf, offset, buf);
+    buf[done..].fill(0);
+    done
 }
 
-pub fn read_s8(sf: &mut Streamfile, offset: usize) -> i8 {
-    read_u8(sf, offset) as i8
+fn read_exact_at(sf: &mut Streamfile, offset: usize, buf: &mut [u8]) -> Result<()> {
+    let (done, err) = read_buffered(sf, offset, buf);
+    if let Some(err) = err {
+        return Err(VgmstreamError::Io(err));
+    }
+    if done < buf.len() {
+        return Err(VgmstreamError::Truncated { meta: "STREAMFILE", offset, size: buf.len() });
+    }
+    Ok(())
 }
 
-pub fn read_u16le(sf: &mut Streamfile, offset: usize) -> u16 {
-    let mut buf = [0; 2];
-    sf.reader
-        .seek(std::io::SeekFrom::Start(offset as u64))
-        .unwrap();
-    sf.reader.read_exact(&mut buf).unwrap();
-    u16::from_le_bytes(buf)
+fn read_array<const N: usize>(sf: &mut Streamfile, offset: usize) -> Result<[u8; N]> {
+    let mut buf = [0; N];
+    read_exact_at(sf, offset, &mut buf)?;
+    Ok(buf)
 }
 
-pub fn read_s16le(sf: &mut Streamfile, offset: usize) -> i16 {
-    read_u16le(sf, offset) as i16
+pub fn read_u8(sf: &mut Streamfile, offset: usize) -> Result<u8> {
+    Ok(read_array::<1>(sf, offset)?[0])
 }
 
-pub fn read_u32le(sf: &mut Streamfile, offset: usize) -> u32 {
-    let mut buf = [0; 4];
-    sf.reader
-        .seek(std::io::SeekFrom::Start(offset as u64))
-        .unwrap();
-    sf.reader.read_exact(&mut buf).unwrap();
-    u32::from_le_bytes(buf)
+pub fn read_s8(sf: &mut Streamfile, offset: usize) -> Result<i8> {
+    Ok(read_u8(sf, offset)? as i8)
 }
 
-pub fn read_s32le(sf: &mut Streamfile, offset: usize) -> i32 {
-    read_u32le(sf, offset) as i32
+pub fn read_u16le(sf: &mut Streamfile, offset: usize) -> Result<u16> {
+    Ok(u16::from_le_bytes(read_array(sf, offset)?))
 }
 
-pub fn read_u64le(sf: &mut Streamfile, offset: usize) -> u64 {
-    let mut buf = [0; 8];
-    sf.reader
-        .seek(std::io::SeekFrom::Start(offset as u64))
-        .unwrap();
-    sf.reader.read_exact(&mut buf).unwrap();
-    u64::from_le_bytes(buf)
+pub fn read_s16le(sf: &mut Streamfile, offset: usize) -> Result<i16> {
+    Ok(read_u16le(sf, offset)? as i16)
 }
 
-pub fn read_s64le(sf: &mut Streamfile, offset: usize) -> i64 {
-    read_u64le(sf, offset) as i64
+pub fn read_u32le(sf: &mut Streamfile, offset: usize) -> Result<u32> {
+    Ok(u32::from_le_bytes(read_array(sf, offset)?))
 }
 
-pub fn read_u16be(sf: &mut Streamfile, offset: usize) -> u16 {
-    let mut buf = [0; 2];
-    sf.reader
-        .seek(std::io::SeekFrom::Start(offset as u64))
-        .unwrap();
-    sf.reader.read_exact(&mut buf).unwrap();
-    u16::from_be_bytes(buf)
+pub fn read_s32le(sf: &mut Streamfile, offset: usize) -> Result<i32> {
+    Ok(read_u32le(sf, offset)? as i32)
 }
 
-pub fn read_s16be(sf: &mut Streamfile, offset: usize) -> i16 {
-    read_u16be(sf, offset) as i16
+pub fn read_u64le(sf: &mut Streamfile, offset: usize) -> Result<u64> {
+    Ok(u64::from_le_bytes(read_array(sf, offset)?))
 }
 
-pub fn read_u32be(sf: &mut Streamfile, offset: usize) -> u32 {
-    let mut buf = [0; 4];
-    sf.reader
-        .seek(std::io::SeekFrom::Start(offset as u64))
-        .unwrap();
-    sf.reader.read_exact(&mut buf).unwrap();
-    u32::from_be_bytes(buf)
+pub fn read_s64le(sf: &mut Streamfile, offset: usize) -> Result<i64> {
+    Ok(read_u64le(sf, offset)? as i64)
 }
 
-pub fn read_s32be(sf: &mut Streamfile, offset: usize) -> i32 {
-    read_u32be(sf, offset) as i32
+pub fn read_u16be(sf: &mut Streamfile, offset: usize) -> Result<u16> {
+    Ok(u16::from_be_bytes(read_array(sf, offset)?))
 }
 
-pub fn read_u64be(sf: &mut Streamfile, offset: usize) -> u64 {
-    let mut buf = [0; 8];
-    sf.reader
-        .seek(std::io::SeekFrom::Start(offset as u64))
-        .unwrap();
-    sf.reader.read_exact(&mut buf).unwrap();
-    u64::from_be_bytes(buf)
+pub fn read_s16be(sf: &mut Streamfile, offset: usize) -> Result<i16> {
+    Ok(read_u16be(sf, offset)? as i16)
 }
 
-pub fn read_s64be(sf: &mut Streamfile, offset: usize) -> i64 {
-    read_u64be(sf, offset) as i64
+pub fn read_u32be(sf: &mut Streamfile, offset: usize) -> Result<u32> {
+    Ok(u32::from_be_bytes(read_array(sf, offset)?))
 }
 
-pub fn read_f32le(sf: &mut Streamfile, offset: usize) -> f32 {
-    let mut buf = [0; 4];
-    sf.reader
-        .seek(std::io::SeekFrom::Start(offset as u64))
-        .unwrap();
-    sf.reader.read_exact(&mut buf).unwrap();
-    f32::from_le_bytes(buf)
+pub fn read_s32be(sf: &mut Streamfile, offset: usize) -> Result<i32> {
+    Ok(read_u32be(sf, offset)? as i32)
 }
 
-pub fn read_f32be(sf: &mut Streamfile, offset: usize) -> f32 {
-    let mut buf = [0; 4];
-    sf.reader
-        .seek(std::io::SeekFrom::Start(offset as u64))
-        .unwrap();
-    sf.reader.read_exact(&mut buf).unwrap();
-    f32::from_be_bytes(buf)
+pub fn read_u64be(sf: &mut Streamfile, offset: usize) -> Result<u64> {
+    Ok(u64::from_be_bytes(read_array(sf, offset)?))
 }
 
-pub fn read_f64le(sf: &mut Streamfile, offset: usize) -> f64 {
-    let mut buf = [0; 8];
-    sf.reader
-        .seek(std::io::SeekFrom::Start(offset as u64))
-        .unwrap();
-    sf.reader.read_exact(&mut buf).unwrap();
-    f64::from_le_bytes(buf)
+pub fn read_s64be(sf: &mut Streamfile, offset: usize) -> Result<i64> {
+    Ok(read_u64be(sf, offset)? as i64)
 }
 
-pub fn read_f64be(sf: &mut Streamfile, offset: usize) -> f64 {
-    let mut buf = [0; 8];
-    sf.reader
-        .seek(std::io::SeekFrom::Start(offset as u64))
-        .unwrap();
-    sf.reader.read_exact(&mut buf).unwrap();
-    f64::from_be_bytes(buf)
+pub fn read_f32le(sf: &mut Streamfile, offset: usize) -> Result<f32> {
+    Ok(f32::from_le_bytes(read_array(sf, offset)?))
 }
 
+pub fn read_f32be(sf: &mut Streamfile, offset: usize) -> Result<f32> {
+    Ok(f32::from_be_bytes(read_array(sf, offset)?))
+}
+
+pub fn read_f64le(sf: &mut Streamfile, offset: usize) -> Result<f64> {
+    Ok(f64::from_le_bytes(read_array(sf, offset)?))
+}
+
+pub fn read_f64be(sf: &mut Streamfile, offset: usize) -> Result<f64> {
+    Ok(f64::from_be_bytes(read_array(sf, offset)?))
+}
+
+/* unreadable ids (past EOF) never match */
 pub fn is_id32be(sf: &mut Streamfile, offset: usize, id: &str) -> bool {
-    return read_u32be(sf, offset) == u32::from_be_bytes(id.as_bytes().try_into().unwrap());
+    return matches!(read_u32be(sf, offset), Ok(value) if value == get_id32be(id));
+}
+
+/* big endian if the LE value is bigger (for fields known to be small) */
+pub fn guess_endian32(sf: &mut Streamfile, offset: usize) -> Result<bool> {
+    return Ok(read_u32le(sf, offset)? > read_u32be(sf, offset)?);
 }
 
 // #[allow(arithmetic_overflow)]
@@ -247,11 +456,19 @@ pub fn get_id32be(s: &str) -> u32 {
     ((s[0] as u32) << 24) | ((s[1] as u32) << 16) | ((s[2] as u32) << 8) | (s[3] as u32)
 }
 
-pub fn read_exact_bytes(sf: &mut Streamfile, offset: usize, size: usize) -> Vec<u8> {
+/* Reads a string up to max_size bytes or the first null, whichever comes first */
+pub fn read_string(sf: &mut Streamfile, offset: usize, max_size: usize) -> String {
+    let mut buf = vec![0; max_size];
+    let bytes = read_streamfile(sf, offset, &mut buf);
+    buf.truncate(bytes);
+    if let Some(end) = buf.iter().position(|&c| c == 0) {
+        buf.truncate(end);
+    }
+    return String::from_utf8_lossy(&buf).into_owned();
+}
+
+pub fn read_exact_bytes(sf: &mut Streamfile, offset: usize, size: usize) -> Result<Vec<u8>> {
     let mut buf = vec![0; size];
-    sf.reader
-        .seek(std::io::SeekFrom::Start(offset as u64))
-        .unwrap();
-    sf.reader.read_exact(&mut buf).unwrap();
-    buf
-}
\ No newline at end of file
+    read_exact_at(sf, offset, &mut buf)?;
+    Ok(buf)
+}
@@ -0,0 +1,265 @@
+use crate::error::{Result, VgmstreamError};
+use crate::streamfile::*;
+use crate::util::reader::*;
+
+/* CRI @UTF (tables) reader. Used in many CRI formats (ACB, CPK, AWB info, etc), a bit like
+ * a SQL table with a schema (name/type per column) and N rows of values. Values may be per-row,
+ * or constant ("default") for all rows and stored in the schema. Data columns may contain
+ * nested @UTF tables. All values are BE. */
+
+const COLUMN_FLAG_NAME: u8 = 0x10;
+const COLUMN_FLAG_DEFAULT: u8 = 0x20;
+const COLUMN_FLAG_ROW: u8 = 0x40;
+const COLUMN_FLAG_UNDEFINED: u8 = 0x80;
+
+const COLUMN_TYPE_UINT8: u8 = 0x00;
+const COLUMN_TYPE_SINT8: u8 = 0x01;
+const COLUMN_TYPE_UINT16: u8 = 0x02;
+const COLUMN_TYPE_SINT16: u8 = 0x03;
+const COLUMN_TYPE_UINT32: u8 = 0x04;
+const COLUMN_TYPE_SINT32: u8 = 0x05;
+const COLUMN_TYPE_UINT64: u8 = 0x06;
+const COLUMN_TYPE_SINT64: u8 = 0x07;
+const COLUMN_TYPE_FLOAT: u8 = 0x08;
+const COLUMN_TYPE_DOUBLE: u8 = 0x09;
+const COLUMN_TYPE_STRING: u8 = 0x0a;
+const COLUMN_TYPE_VLDATA: u8 = 0x0b;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UtfValue {
+    Int(i64),           /* any integer column */
+    Float(f64),         /* float or double column */
+    String(String),
+    Data(usize, usize), /* absolute offset + size in the streamfile */
+}
+
+#[derive(Debug, Clone)]
+struct UtfColumn {
+    flag: u8,
+    column_type: u8,
+    name: String,
+    offset: usize, /* within row (per-row values) or the schema (default values) */
+}
+
+#[derive(Debug, Clone)]
+pub struct UtfTable {
+    pub table_offset: usize,
+    pub name: String,
+    pub rows: usize,
+    buf: Vec<u8>,           /* whole table */
+    columns: Vec<UtfColumn>,
+    rows_offset: usize,
+    row_width: usize,
+    strings_offset: usize,
+    data_offset: usize,
+}
+
+fn get_column_size(column_type: u8) -> Option<usize> {
+    return match column_type {
+        COLUMN_TYPE_UINT8 | COLUMN_TYPE_SINT8 => Some(0x01),
+        COLUMN_TYPE_UINT16 | COLUMN_TYPE_SINT16 => Some(0x02),
+        COLUMN_TYPE_UINT32 | COLUMN_TYPE_SINT32 | COLUMN_TYPE_FLOAT | COLUMN_TYPE_STRING => Some(0x04),
+        COLUMN_TYPE_UINT64 | COLUMN_TYPE_SINT64 | COLUMN_TYPE_DOUBLE | COLUMN_TYPE_VLDATA => Some(0x08),
+        _ => None,
+    };
+}
+
+impl UtfTable {
+    /* Loads the table at offset, checking its header and schema */
+    pub fn open(sf: &mut Streamfile, table_offset: usize) -> Result<UtfTable> {
+        let bad_header = |field| VgmstreamError::BadHeader { meta: "@UTF", offset: table_offset, field };
+
+        if !is_id32be(sf, table_offset + 0x00, "@UTF") {
+            return Err(bad_header("id"));
+        }
+
+        /* load table header (offsets are relative to 0x08) */
+        let table_size = read_u32be(sf, table_offset + 0x04)? as usize + 0x08;
+        if table_size < 0x20 {
+            return Err(bad_header("table size"));
+        }
+        let buf = read_exact_bytes(sf, table_offset, table_size)?;
+
+        let version = get_u16be(&buf[0x08..]);
+        let rows_offset = get_u16be(&buf[0x0a..]) as usize + 0x08;
+        let strings_offset = get_u32be(&buf[0x0c..]) as usize + 0x08;
+        let data_offset = get_u32be(&buf[0x10..]) as usize + 0x08;
+        let name_offset = get_u32be(&buf[0x14..]) as usize;
+        let column_count = get_u16be(&buf[0x18..]) as usize;
+        let row_width = get_u16be(&buf[0x1a..]) as usize;
+        let rows = get_u32be(&buf[0x1c..]) as usize;
+        let schema_offset = 0x20;
+
+        /* 0: CPK/older ACB, 1: newer ACB */
+        if version != 0x00 && version != 0x01 {
+            return Err(bad_header("version"));
+        }
+        if rows_offset > table_size || strings_offset > table_size || data_offset > table_size
+            || strings_offset > data_offset || rows_offset + rows * row_width > strings_offset
+        {
+            return Err(bad_header("table offsets"));
+        }
+
+        let mut table = UtfTable {
+            table_offset,
+            name: String::new(),
+            rows,
+            buf,
+            columns: Vec::with_capacity(column_count),
+            rows_offset,
+            row_width,
+            strings_offset,
+            data_offset,
+        };
+        table.name = table.get_string(name_offset).ok_or(bad_header("table name"))?;
+
+        /* load schema */
+        let mut offset = schema_offset;
+        let mut column_offset = 0;
+        for _ in 0..column_count {
+            if offset + 0x01 > rows_offset {
+                return Err(bad_header("schema"));
+            }
+            let info = table.buf[offset];
+            let flag = info & 0xf0;
+            let column_type = info & 0x0f;
+            offset += 0x01;
+
+            let Some(value_size) = get_column_size(column_type) else {
+                return Err(bad_header("column type"));
+            };
+            /* default and row values are exclusive (undefined flag seems unused) */
+            if flag & COLUMN_FLAG_UNDEFINED != 0 || (flag & COLUMN_FLAG_DEFAULT != 0 && flag & COLUMN_FLAG_ROW != 0) {
+                return Err(bad_header("column flag"));
+            }
+
+            let mut name = String::new();
+            if flag & COLUMN_FLAG_NAME != 0 {
+                if offset + 0x04 > rows_offset {
+                    return Err(bad_header("schema"));
+                }
+                name = table.get_string(get_u32be(&table.buf[offset..]) as usize).ok_or(bad_header("column name"))?;
+                offset += 0x04;
+            }
+
+            let value_offset;
+            if flag & COLUMN_FLAG_DEFAULT != 0 {
+                value_offset = offset;
+                offset += value_size;
+            }
+            else if flag & COLUMN_FLAG_ROW != 0 {
+                value_offset = column_offset;
+                column_offset += value_size;
+            }
+            else {
+                value_offset = 0; /* no value (zero) */
+            }
+
+            table.columns.push(UtfColumn { flag, column_type, name, offset: value_offset });
+        }
+
+        if column_offset > row_width || offset > rows_offset {
+            return Err(bad_header("schema"));
+        }
+
+        return Ok(table);
+    }
+
+    fn get_string(&self, offset: usize) -> Option<String> {
+        let start = self.strings_offset + offset;
+        if start >= self.data_offset {
+            return None;
+        }
+        let strings = &self.buf[start..self.data_offset];
+        let end = strings.iter().position(|&c| c == 0)?;
+        return Some(String::from_utf8_lossy(&strings[..end]).into_owned());
+    }
+
+    pub fn has_column(&self, column: &str) -> bool {
+        return self.columns.iter().any(|c| c.name == column);
+    }
+
+    /* Returns the value of a column in a row, or None if the column doesn't exist */
+    pub fn query(&self, row: usize, column: &str) -> Option<UtfValue> {
+        if row >= self.rows {
+            return None;
+        }
+        let column = self.columns.iter().find(|c| c.name == column)?;
+
+        let offset = if column.flag & COLUMN_FLAG_DEFAULT != 0 {
+            column.offset
+        }
+        else if column.flag & COLUMN_FLAG_ROW != 0 {
+            self.rows_offset + row * self.row_width + column.offset
+        }
+        else {
+            return Some(UtfValue::Int(0));
+        };
+
+        let data = &self.buf[offset..];
+        let value = match column.column_type {
+            COLUMN_TYPE_UINT8 => UtfValue::Int(get_u8(data) as i64),
+            COLUMN_TYPE_SINT8 => UtfValue::Int(data[0] as i8 as i64),
+            COLUMN_TYPE_UINT16 => UtfValue::Int(get_u16be(data) as i64),
+            COLUMN_TYPE_SINT16 => UtfValue::Int(get_s16be(data) as i64),
+            COLUMN_TYPE_UINT32 => UtfValue::Int(get_u32be(data) as i64),
+            COLUMN_TYPE_SINT32 => UtfValue::Int(get_s32be(data) as i64),
+            COLUMN_TYPE_UINT64 => UtfValue::Int(get_u64be(data) as i64),
+            COLUMN_TYPE_SINT64 => UtfValue::Int(get_s64be(data)),
+            COLUMN_TYPE_FLOAT => UtfValue::Float(get_f32be(data) as f64),
+            COLUMN_TYPE_DOUBLE => UtfValue::Float(f64::from_bits(get_u64be(data))),
+            COLUMN_TYPE_STRING => UtfValue::String(self.get_string(get_u32be(data) as usize)?),
+            COLUMN_TYPE_VLDATA => {
+                let data_offset = get_u32be(&data[0x00..]) as usize;
+                let data_size = get_u32be(&data[0x04..]) as usize;
+                if self.data_offset + data_offset + data_size > self.buf.len() {
+                    return None;
+                }
+                UtfValue::Data(self.table_offset + self.data_offset + data_offset, data_size)
+            }
+            _ => return None,
+        };
+
+        return Some(value);
+    }
+
+    pub fn query_int(&self, row: usize, column: &str) -> Option<i64> {
+        match self.query(row, column)? {
+            UtfValue::Int(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn query_u8(&self, row: usize, column: &str) -> Option<u8> {
+        return self.query_int(row, column).map(|value| value as u8);
+    }
+
+    pub fn query_u16(&self, row: usize, column: &str) -> Option<u16> {
+        return self.query_int(row, column).map(|value| value as u16);
+    }
+
+    pub fn query_u32(&self, row: usize, column: &str) -> Option<u32> {
+        return self.query_int(row, column).map(|value| value as u32);
+    }
+
+    pub fn query_string(&self, row: usize, column: &str) -> Option<String> {
+        match self.query(row, column)? {
+            UtfValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /* Returns absolute offset and size of a data column (empty data is considered not set) */
+    pub fn query_data(&self, row: usize, column: &str) -> Option<(usize, usize)> {
+        match self.query(row, column)? {
+            UtfValue::Data(offset, size) if size > 0 => Some((offset, size)),
+            _ => None,
+        }
+    }
+
+    /* Opens a table stored in a data column */
+    pub fn open_subtable(&self, sf: &mut Streamfile, row: usize, column: &str) -> Option<UtfTable> {
+        let (offset, _) = self.query_data(row, column)?;
+        return UtfTable::open(sf, offset).ok();
+    }
+}
@@ -0,0 +1,127 @@
+use std::fmt;
+use std::io::Write;
+use std::sync::{Arc, RwLock};
+
+/* Log levels, similar to upstream's:
+ * - Info: user-visible messages (vgm_logi), things like "file may be a bad rip" or missing keys
+ * - Debug: developer messages (vgm_log), usually about wrong values found while parsing/decoding
+ * Messages are shown if their level is <= the logger's level. */
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum LogLevel {
+    #[default]
+    None = 0,
+    Info = 1,
+    Debug = 2,
+    All = 100,
+}
+
+/* shared so it can be called without holding the logger's lock (callbacks may log or change the logger) */
+pub type LogCallback = Arc<dyn Fn(LogLevel, &str) + Send + Sync>;
+
+/* where messages go */
+pub enum LogOutput {
+    Callback(LogCallback),
+    Stdout,
+    Stderr,
+}
+
+struct Logger {
+    level: LogLevel,
+    output: Option<LogOutput>,
+}
+
+/* disabled by default (like upstream) so library users don't get unexpected output */
+static LOGGER: RwLock<Logger> = RwLock::new(Logger { level: LogLevel::None, output: None });
+
+/* Sets the max level and output of log messages (LogLevel::None to disable logging).
+ * A callback can be used to capture messages or pass them to some other logger. */
+pub fn vgm_log_set_callback(level: LogLevel, output: LogOutput) {
+    let mut logger = match LOGGER.write() {
+        Ok(logger) => logger,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    logger.level = level;
+    logger.output = Some(output);
+}
+
+/* Disables all messages */
+pub fn vgm_log_disable() {
+    let mut logger = match LOGGER.write() {
+        Ok(logger) => logger,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    logger.level = LogLevel::None;
+    logger.output = None;
+}
+
+pub fn vgm_log_enabled(level: LogLevel) -> bool {
+    let logger = match LOGGER.read() {
+        Ok(logger) => logger,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    return level != LogLevel::None && level <= logger.level && logger.output.is_some();
+}
+
+/* Called by the log macros, use those instead */
+pub fn vgm_log_write(level: LogLevel, args: fmt::Arguments) {
+    let callback = {
+        let logger = match LOGGER.read() {
+            Ok(logger) => logger,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if level == LogLevel::None || level > logger.level {
+            return;
+        }
+
+        match &logger.output {
+            Some(LogOutput::Callback(callback)) => callback.clone(),
+            Some(LogOutput::Stdout) => {
+                let _ = writeln!(std::io::stdout(), "{}", args);
+                return;
+            }
+            Some(LogOutput::Stderr) => {
+                let _ = writeln!(std::io::stderr(), "{}", args);
+                return;
+            }
+            None => return,
+        }
+    };
+
+    callback(level, &args.to_string());
+}
+
+/* user-visible info message (upstream's vgm_logi) */
+#[macro_export]
+macro_rules! vgm_logi {
+    ($($arg:tt)*) => {
+        $crate::util::log::vgm_log_write($crate::util::log::LogLevel::Info, format_args!($($arg)*))
+    };
+}
+
+/* info message if condition is true (upstream's vgm_asserti) */
+#[macro_export]
+macro_rules! vgm_asserti {
+    ($cond:expr, $($arg:tt)*) => {
+        if $cond {
+            $crate::vgm_logi!($($arg)*);
+        }
+    };
+}
+
+/* debug message (upstream's VGM_LOG) */
+#[macro_export]
+macro_rules! vgm_log {
+    ($($arg:tt)*) => {
+        $crate::util::log::vgm_log_write($crate::util::log::LogLevel::Debug, format_args!($($arg)*))
+    };
+}
+
+/* debug message if condition is true (upstream's VGM_ASSERT) */
+#[macro_export]
+macro_rules! vgm_assert {
+    ($cond:expr, $($arg:tt)*) => {
+        if $cond {
+            $crate::vgm_log!($($arg)*);
+        }
+    };
+}
@@ -1,2 +1,4 @@
+pub mod log;
 pub mod reader;
-pub mod util;
\ No newline at end of file
+pub mod util;
+pub mod cri_utf;
\ No newline at end of file
@@ -62,8 +62,9 @@ pub fn next_chunk(chunk: &mut ChunkType, sf: &mut Streamfile) -> bool {
         return false;
     }
 
-    chunk.ctype = read_u32type(sf, chunk.current as usize + 0x00);
-    chunk.size = read_u32size(sf, chunk.current as usize + 0x04);
+    /* reads past EOF return -1 like C, handled below */
+    chunk.ctype = read_u32type(sf, chunk.current as usize + 0x00).unwrap_or(0xFFFFFFFF);
+    chunk.size = read_u32size(sf, chunk.current as usize + 0x04).unwrap_or(0xFFFFFFFF);
 
     chunk.offset = chunk.current as u32 + 0x04 + 0x04;
     chunk.current += if chunk.full_size { chunk.size as i32 } else { 0x08 + chunk.size as i32 };
@@ -83,6 +84,22 @@ pub fn next_chunk(chunk: &mut ChunkType, sf: &mut Streamfile) -> bool {
     return true;
 }
 
+/* Finds a chunk by id starting from some offset, returning its data offset and size */
+pub fn find_chunk(sf: &mut Streamfile, chunk_id: u32, start_offset: usize, full_chunk_size: bool, big_endian_size: bool) -> Option<(usize, usize)> {
+    let mut rc: ChunkType = Default::default();
+    rc.current = start_offset as i32;
+    rc.full_size = full_chunk_size;
+    rc.be_size = big_endian_size;
+
+    while next_chunk(&mut rc, sf) {
+        if rc.ctype == chunk_id {
+            return Some((rc.offset as usize, rc.size as usize));
+        }
+    }
+
+    return None;
+}
+
 pub mod SpeakerT {
     pub const speaker_FL: u32  = 1 << 0;     /* front left */
     pub const speaker_FR: u32  = 1 << 1;     /* front right */
@@ -1,65 +1,148 @@
 pub use crate::constants::*;
+use crate::vgm_log;
+use crate::error::{Result, VgmstreamError};
 use crate::meta::adx;
+use crate::meta::ahx;
+use crate::meta::aix;
+use crate::meta::awb;
+use crate::meta::bkhd;
 use crate::meta::wwise;
 pub use crate::streamfile::*;
 pub use crate::vgmstream_types::*;
 
-type InitVGMStream = fn(streamfile: &mut Streamfile) -> Option<VGMStream>;
+type InitVGMStream = fn(streamfile: &mut Streamfile) -> Result<VGMStream>;
+type ListSubsongs = fn(streamfile: &mut Streamfile) -> Result<Vec<SubsongInfo>>;
 
-pub const INIT_VGMSTREAM_FUNCTIONS: [InitVGMStream; 2] = [
+/* basic info of a subsong, see VGMStream::list_subsongs */
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SubsongInfo {
+    pub index: i32,                         /* 1-based, as passed to open_subsong */
+    pub name: String,                       /* stream_name (may be empty if the format has no names) */
+    pub error: Option<String>,              /* why the subsong can't be opened (unsupported codec, bad header, etc) */
+}
+
+pub const INIT_VGMSTREAM_FUNCTIONS: [InitVGMStream; 6] = [
     adx::init_vgmstream_adx,
+    ahx::init_vgmstream_ahx,
+    aix::init_vgmstream_aix,
+    awb::init_vgmstream_awb,
     wwise::init_vgmstream_wwise,
+    bkhd::init_vgmstream_bkhd,
+];
+
+/* metas with subsongs, which list them from their index without opening each one */
+pub const LIST_SUBSONGS_FUNCTIONS: [ListSubsongs; 2] = [
+    awb::list_subsongs_awb,
+    bkhd::list_subsongs_bkhd,
 ];
 
 impl VGMStream {
-    pub fn init(filename: String) -> Self {
-        println!("init({})", filename);
-        let mut stream: VGMStream = VGMStream::default();
-        // println!("{:?}", stream);
-        let sf = Streamfile::open_stdio(filename);
-        // TODO: check if streamfile is valid (options, duh)
-        if sf.is_some() {
-            println!("{:?}", sf);
-            stream = Self::init_from_streamfile(&mut sf.unwrap()).unwrap();
-            // sf.close();
-        }
-        return stream;
+    pub fn init(filename: String) -> Result<Self> {
+        let mut sf = Streamfile::open_stdio(filename)?;
+        return Self::init_from_streamfile(&mut sf);
     }
 
-    pub fn init_from_streamfile(sf: &mut Streamfile) -> Option<Self> {
+    pub fn init_from_streamfile(sf: &mut Streamfile) -> Result<Self> {
         return Self::init_internal(sf);
     }
 
-    fn init_internal(sf: &mut Streamfile) -> Option<Self> {
-        for func in INIT_VGMSTREAM_FUNCTIONS {
-            // let init_function = INIT_VGMSTREAM_FUNCTIONS[i];
-            let stream = func(sf);
-            if stream.is_none() {
-                continue;
+    /* Opens subsong N of a file (1-based; 0 opens the default/first one) */
+    pub fn open_subsong(filename: String, index: i32) -> Result<Self> {
+        let mut sf = Streamfile::open_stdio(filename)?;
+        return Self::init_subsong_from_streamfile(&mut sf, index);
+    }
+
+    pub fn init_subsong_from_streamfile(sf: &mut Streamfile, index: i32) -> Result<Self> {
+        if index < 0 {
+            return Err(VgmstreamError::InvalidSubsong { index, total: 0 });
+        }
+        sf.stream_index = index;
+        return Self::init_internal(sf);
+    }
+
+    /* Lists all subsongs in a file (files without subsongs have one).
+     * Containers list their entries from the index without opening codecs, so errors are those
+     * found in the index (unsupported codec, bad offsets), and other files are opened once. */
+    pub fn list_subsongs(filename: String) -> Result<Vec<SubsongInfo>> {
+        let mut sf = Streamfile::open_stdio(filename)?;
+        return Self::list_subsongs_from_streamfile(&mut sf);
+    }
+
+    pub fn list_subsongs_from_streamfile(sf: &mut Streamfile) -> Result<Vec<SubsongInfo>> {
+        for func in LIST_SUBSONGS_FUNCTIONS {
+            match func(sf) {
+                Err(VgmstreamError::UnknownFormat { .. }) => continue,
+                result => return result,
             }
+        }
+
+        let vgmstream = Self::init_subsong_from_streamfile(sf, 0)?;
+        return Ok(vec![SubsongInfo { index: 1, name: vgmstream.stream_name.clone(), error: None }]);
+    }
+
+    fn init_internal(sf: &mut Streamfile) -> Result<Self> {
+        /* first meaningful error is reported if no meta accepts the file
+         * (UnknownFormat just means "not mine" so the next meta is tried) */
+        let mut first_error: Option<VgmstreamError> = None;
+
+        for func in INIT_VGMSTREAM_FUNCTIONS {
+            let mut stream = match func(sf) {
+                Ok(stream) => stream,
+                Err(VgmstreamError::UnknownFormat { .. }) => continue,
+                Err(err) => {
+                    if first_error.is_none() {
+                        first_error = Some(err);
+                    }
+                    continue;
+                }
+            };
 
-            let mut stream = stream.unwrap();
             if stream.num_samples <= 0 || stream.num_samples >= VGMSTREAM_MAX_NUM_SAMPLES {
-                println!("VGMSTREAM: wrong num samples {}", stream.num_samples);
+                vgm_log!("VGMSTREAM: wrong num samples {}", stream.num_samples);
                 stream.close();
+                if first_error.is_none() {
+                    first_error = Some(VgmstreamError::BadHeader { meta: "VGMSTREAM", offset: 0, field: "num samples" });
+                }
                 continue;
             }
 
             if stream.sample_rate < VGMSTREAM_MIN_SAMPLE_RATE
                 || stream.sample_rate > VGMSTREAM_MAX_SAMPLE_RATE
             {
-                println!("VGMSTREAM: wrong sample rate {}", stream.sample_rate);
+                vgm_log!("VGMSTREAM: wrong sample rate {}", stream.sample_rate);
                 stream.close();
+                if first_error.is_none() {
+                    first_error = Some(VgmstreamError::BadHeader { meta: "VGMSTREAM", offset: 0, field: "sample rate" });
+                }
                 continue;
             }
 
-            // TODO: loops
+            /* Sanify loops and remove bad metadata */
+            if stream.loop_flag {
+                if stream.loop_end_sample <= stream.loop_start_sample
+                    || stream.loop_end_sample > stream.num_samples
+                    || stream.loop_start_sample < 0
+                {
+                    vgm_log!(
+                        "VGMSTREAM: wrong loops ignored (lss={}, lse={}, ns={})",
+                        stream.loop_start_sample, stream.loop_end_sample, stream.num_samples
+                    );
+                    stream.loop_flag = false;
+                    stream.loop_start_sample = 0;
+                    stream.loop_end_sample = 0;
+                }
+            }
 
             if stream.channels == 1 && stream.allow_dual_stereo {
                 // TODO: dual stereo
             }
 
-            // TODO: loops (set to 0)
+            /* clean as loops are readable metadata but loop fields may contain garbage
+             * (done *after* dual stereo as it needs loop fields to match) */
+            if !stream.loop_flag {
+                stream.loop_start_sample = 0;
+                stream.loop_end_sample = 0;
+            }
 
             // TODO: ffmpeg?
 
@@ -68,7 +151,7 @@ impl VGMStream {
                 for ch in 0..32 {
                     let bit = (stream.channel_layout >> ch) & 1;
                     if ch > 17 && bit == 1 {
-                        println!(
+                        vgm_log!(
                             "VGMSTREAM: wrong bit {} in channel_layout {:x}",
                             ch, stream.channel_layout
                         );
@@ -78,8 +161,8 @@ impl VGMStream {
                     count += bit;
                 }
 
-                if count > stream.channels.try_into().unwrap() {
-                    println!(
+                if count > stream.channels as u32 {
+                    vgm_log!(
                         "VGMSTREAM: wrong totals {} in channel_layout {:x}",
                         count, stream.channel_layout
                     );
@@ -88,8 +171,11 @@ impl VGMStream {
             }
 
             if stream.num_streams < 0 || stream.num_streams > VGMSTREAM_MAX_SUBSONGS {
-                println!("VGMSTREAM: wrong num_streams (ns={})\n", stream.num_streams);
+                vgm_log!("VGMSTREAM: wrong num_streams (ns={})", stream.num_streams);
                 stream.close();
+                if first_error.is_none() {
+                    first_error = Some(VgmstreamError::BadHeader { meta: "VGMSTREAM", offset: 0, field: "num streams" });
+                }
                 continue;
             }
 
@@ -97,24 +183,357 @@ impl VGMStream {
                 stream.stream_index = sf.stream_index;
             }
 
-            // stream.setup();
+            /* metas without subsongs ignore the index, so catch it here */
+            let total_subsongs = if stream.num_streams > 0 { stream.num_streams } else { 1 };
+            if sf.stream_index > total_subsongs {
+                stream.close();
+                return Err(VgmstreamError::InvalidSubsong { index: sf.stream_index, total: total_subsongs });
+            }
 
-            return Some(stream);
+            stream.setup();
+
+            return Ok(stream);
         }
 
-        return None;
+        return Err(first_error.unwrap_or(VgmstreamError::UnknownFormat { filename: sf.name.clone() }));
     }
 
-    // fn setup(&mut self) {
-    //     self.start_ch = self.ch.clone();
-    //     self.start_vgmstream = Some(Box::new(self.clone()));
-    // }
+    /* save initial state, to restart the stream on resets/seeks (after init or config changes) */
+    pub(crate) fn setup(&mut self) {
+        self.start_ch = self.ch.clone();
+        self.start_vgmstream = Some(Box::new(self.copy_state()));
+    }
+
+    /* Copies the stream's state, without codec/layout data (those can't be copied and
+     * are reset separately). Works like the shallow copy of the C struct. */
+    fn copy_state(&self) -> VGMStream {
+        return VGMStream {
+            num_samples: self.num_samples,
+            sample_rate: self.sample_rate,
+            channels: self.channels,
+            coding_type: self.coding_type,
+            layout_type: self.layout_type,
+            meta_type: self.meta_type,
+
+            loop_flag: self.loop_flag,
+            loop_start_sample: self.loop_start_sample,
+            loop_end_sample: self.loop_end_sample,
+
+            interleave_block_size: self.interleave_block_size,
+            interleave_first_block_size: self.interleave_first_block_size,
+            interleave_first_skip: self.interleave_first_skip,
+            interleave_last_block_size: self.interleave_last_block_size,
+            frame_size: self.frame_size,
+
+            num_streams: self.num_streams,
+            stream_index: self.stream_index,
+            stream_size: self.stream_size,
+            stream_name: self.stream_name.clone(),
+            markers: self.markers.clone(),
+
+            channel_layout: self.channel_layout,
+
+            allow_dual_stereo: self.allow_dual_stereo,
+
+            full_block_size: self.full_block_size,
+            current_sample: self.current_sample,
+            samples_into_block: self.samples_into_block,
+            current_block_offset: self.current_block_offset,
+            current_block_size: self.current_block_size,
+            current_block_samples: self.current_block_samples,
+            next_block_offset: self.next_block_offset,
+
+            loop_current_sample: self.loop_current_sample,
+            loop_samples_into_block: self.loop_samples_into_block,
+            loop_block_offset: self.loop_block_offset,
+            loop_block_size: self.loop_block_size,
+            loop_block_samples: self.loop_block_samples,
+            loop_next_block_offset: self.loop_next_block_offset,
+            hit_loop: self.hit_loop,
+
+            codec_endian: self.codec_endian,
+            codec_config: self.codec_config,
+            ws_output_size: self.ws_output_size,
+
+            ch: self.ch.clone(),
+            start_ch: self.start_ch.clone(),
+            loop_ch: self.loop_ch.clone(),
+
+            start_vgmstream: None,
+            init_vgmstream: None,
+
+            codec_data: None,
+            layered_layout_data: None,
+            segmented_layout_data: None,
+
+            config_enabled: self.config_enabled,
+            config: self.config,
+            pstate: self.pstate,
+            loop_count: self.loop_count,
+            loop_target: self.loop_target,
+            tmpbuf: Vec::new(),
+            tmpbuf_size: 0,
+        };
+    }
+
+    /* Restarts the stream to its initial state (as it was after init/setup), so it can be played again */
+    pub fn reset(&mut self) {
+        /* restore the whole state, keeping data that isn't part of the snapshot */
+        if let Some(start) = self.start_vgmstream.take() {
+            let codec_data = self.codec_data.take();
+            let layered_layout_data = self.layered_layout_data.take();
+            let segmented_layout_data = self.segmented_layout_data.take();
+            let init_vgmstream = self.init_vgmstream.take();
+            let tmpbuf = std::mem::take(&mut self.tmpbuf);
+            let tmpbuf_size = self.tmpbuf_size;
+
+            *self = start.copy_state();
+
+            self.start_vgmstream = Some(start);
+            self.init_vgmstream = init_vgmstream;
+            self.codec_data = codec_data;
+            self.layered_layout_data = layered_layout_data;
+            self.segmented_layout_data = segmented_layout_data;
+            self.tmpbuf = tmpbuf;
+            self.tmpbuf_size = tmpbuf_size;
+        }
+
+        /* channels (offsets, ADPCM history, ADX keys, etc) */
+        if !self.start_ch.is_empty() {
+            self.ch = self.start_ch.clone();
+        }
+
+        self.current_sample = 0;
+        self.samples_into_block = 0;
+        self.loop_count = 0;
+        self.hit_loop = 0;
+
+        /* codecs with internal state */
+        match self.codec_data.as_mut() {
+            Some(VGMStreamCodecData::CustomFFmpeg(data)) => {
+                crate::coding::ffmpeg::reset_ffmpeg(data);
+            }
+            Some(VGMStreamCodecData::CustomVorbis(data)) => {
+                crate::coding::vorbis::reset_vorbis_custom(data);
+            }
+            None => {}
+        }
+
+        /* layouts with sub-streams */
+        if let Some(data) = self.segmented_layout_data.as_mut() {
+            data.current_segment = 0;
+            for segment in data.segments.iter_mut() {
+                segment.reset();
+            }
+        }
+        if let Some(data) = self.layered_layout_data.as_mut() {
+            for layer in data.layers.iter_mut() {
+                layer.reset();
+            }
+        }
+    }
+
+    /* Seeks to a sample in the output timeline (with loops and config applied) */
+    pub fn seek(&mut self, sample: i32) {
+        crate::render::seek_vgmstream(self, sample);
+    }
+
+    /* Apply config like forced loops, fades, pads and trims, and enables it for rendering.
+     * Restarts the stream, so it can be called again to replay it with a different config. */
+    pub fn apply_config(&mut self, config: &PlayConfig) {
+        /* config changes (like forced loops) are saved in a new snapshot, so keep the
+         * state from before any config and go back to it, or configs would stack */
+        if self.start_vgmstream.is_none() {
+            self.setup();
+        }
+        match self.init_vgmstream.as_ref() {
+            Some(init) => self.start_vgmstream = Some(Box::new(init.copy_state())),
+            None => self.init_vgmstream = self.start_vgmstream.as_ref().map(|start| Box::new(start.copy_state())),
+        }
+        self.reset();
+
+        self.config = *config;
+        self.config.config_set = true;
+        self.config_enabled = true;
+
+        self.setup_state();
+    }
+
+    pub fn force_loop(&mut self, loop_flag: bool, loop_start_sample: i32, loop_end_sample: i32) {
+        /* ignore bad values */
+        if loop_flag
+            && (loop_start_sample < 0
+                || loop_start_sample > loop_end_sample
+                || loop_end_sample > self.num_samples)
+        {
+            return;
+        }
+
+        self.loop_flag = loop_flag;
+
+        /* keep metadata as it's may be shown (with 'loop disabled' info) */
+        if loop_flag {
+            self.loop_start_sample = loop_start_sample;
+            self.loop_end_sample = loop_end_sample;
+        }
+
+        /* segmented layout loops with standard loop start/end values and works ok */
+
+        /* notify of new initial state */
+        self.setup();
+    }
+
+    pub fn set_loop_target(&mut self, loop_target: i32) {
+        if !self.loop_flag {
+            return;
+        }
+
+        self.loop_target = loop_target; /* loop count must be rounded (int) as otherwise target is meaningless */
+    }
+
+    /* apply modifiers like forced loops, which may change the config */
+    fn setup_state_modifiers(&mut self) {
+        if self.config.really_force_loop != 0 {
+            self.force_loop(true, 0, self.num_samples);
+        }
+        if self.config.force_loop != 0 && !self.loop_flag {
+            self.force_loop(true, 0, self.num_samples);
+        }
+        if self.config.ignore_loop != 0 {
+            self.force_loop(false, 0, 0);
+        }
+
+        if !self.loop_flag {
+            self.config.play_forever = 0;
+        }
+        if self.config.play_forever != 0 {
+            self.config.ignore_fade = 0;
+        }
+
+        /* loop N times, but also play stream end instead of fading out */
+        if self.config.ignore_fade != 0 {
+            self.set_loop_target(self.config.loop_count as i32);
+            self.config.fade_time = 0.0;
+            self.config.fade_delay = 0.0;
+        }
+    }
+
+    /* turn the config into a timeline of pad begin > trim > body > fade > pad end */
+    fn setup_state_processing(&mut self) {
+        let pc = &mut self.config;
+        let ps = &mut self.pstate;
+        let sample_rate = self.sample_rate as f64;
+
+        /* time to samples */
+        if pc.pad_begin_s != 0.0 {
+            pc.pad_begin = (pc.pad_begin_s * sample_rate) as i32;
+        }
+        if pc.pad_end_s != 0.0 {
+            pc.pad_end = (pc.pad_end_s * sample_rate) as i32;
+        }
+        if pc.trim_begin_s != 0.0 {
+            pc.trim_begin = (pc.trim_begin_s * sample_rate) as i32;
+        }
+        if pc.trim_end_s != 0.0 {
+            pc.trim_end = (pc.trim_end_s * sample_rate) as i32;
+        }
+        if pc.body_time_s != 0.0 {
+            pc.body_time = (pc.body_time_s * sample_rate) as i32;
+        }
+        /* fade time/delay are always in seconds */
+
+        /* samples before all decode */
+        ps.pad_begin_duration = pc.pad_begin;
+
+        /* removed samples from first decode */
+        ps.trim_begin_duration = pc.trim_begin;
+
+        /* main samples part */
+        ps.body_duration = 0;
+        if pc.body_time != 0 {
+            ps.body_duration += pc.body_time; /* whether it loops or not */
+        } else if self.loop_flag {
+            let mut loop_count = 1.0;
+            if pc.loop_count_set != 0 {
+                /* may set 0.0 on purpose I guess */
+                loop_count = pc.loop_count;
+            }
+
+            ps.body_duration += self.loop_start_sample;
+            if pc.ignore_fade != 0 {
+                ps.body_duration += (self.loop_end_sample - self.loop_start_sample) * loop_count as i32;
+                ps.body_duration += self.num_samples - self.loop_end_sample;
+            } else {
+                ps.body_duration += ((self.loop_end_sample - self.loop_start_sample) as f64 * loop_count) as i32;
+            }
+        } else {
+            ps.body_duration += self.num_samples;
+        }
+
+        /* samples from some modify body */
+        if pc.trim_begin != 0 {
+            ps.body_duration -= pc.trim_begin;
+        }
+        if pc.trim_end != 0 {
+            ps.body_duration -= pc.trim_end;
+        }
+        if pc.fade_delay != 0.0 && self.loop_flag {
+            ps.body_duration += (pc.fade_delay * sample_rate) as i32;
+        }
+
+        /* samples from fade part */
+        ps.fade_duration = 0;
+        if pc.fade_time != 0.0 && self.loop_flag {
+            ps.fade_duration = (pc.fade_time * sample_rate) as i32;
+        }
+
+        /* samples from last part (anything beyond this is empty, unless play forever is set) */
+        ps.pad_end_duration = pc.pad_end;
+
+        /* values too big can overflow, just ignore */
+        if ps.pad_begin_duration < 0 {
+            ps.pad_begin_duration = 0;
+        }
+        if ps.body_duration < 0 {
+            ps.body_duration = 0;
+        }
+        if ps.fade_duration < 0 {
+            ps.fade_duration = 0;
+        }
+        if ps.pad_end_duration < 0 {
+            ps.pad_end_duration = 0;
+        }
+
+        /* final count */
+        ps.play_duration = ps.pad_begin_duration
+            .saturating_add(ps.body_duration)
+            .saturating_add(ps.fade_duration)
+            .saturating_add(ps.pad_end_duration);
+        ps.play_position = 0;
+
+        ps.pad_begin_left = ps.pad_begin_duration;
+        ps.trim_begin_left = ps.trim_begin_duration;
+        ps.fade_left = ps.fade_duration;
+        ps.fade_start = ps.pad_begin_duration + ps.body_duration;
+        ps.pad_end_start = ps.fade_start + ps.fade_duration;
+
+        /* other info (updated once mixing is enabled) */
+        ps.input_channels = self.channels;
+        ps.output_channels = self.channels;
+    }
+
+    /* apply config like forced loops */
+    fn setup_state(&mut self) {
+        self.setup_state_modifiers();
+        self.setup_state_processing();
+        self.setup(); /* save current config for reset */
+    }
 
     pub fn close(&mut self) {
         // TODO: close everything
     }
 
-    pub fn open_stream(&mut self, sf: &mut Streamfile, start_offset: isize) -> bool {
+    pub fn open_stream(&mut self, sf: &mut Streamfile, start_offset: isize) -> Result<()> {
         return self.open_stream_bf(sf, start_offset, false);
     }
 
@@ -123,30 +542,30 @@ impl VGMStream {
         sf: &mut Streamfile,
         start_offset: isize,
         force_multibuffer: bool,
-    ) -> bool {
+    ) -> Result<()> {
         if self.coding_type == CodingType::coding_SILENCE {
-            return true;
+            return Ok(());
         }
 
         if self.layout_type == LayoutType::layout_segmented
             || self.layout_type == LayoutType::layout_layered
         {
-            return true;
+            return Ok(());
         }
 
         if self.coding_type == CodingType::coding_NWA
             || self.coding_type == CodingType::coding_ACM
             || self.coding_type == CodingType::coding_CRI_HCA
         {
-            return true;
+            return Ok(());
         }
 
         if self.coding_type == CodingType::coding_OGG_VORBIS {
-            return true;
+            return Ok(());
         }
 
         if self.coding_type == CodingType::coding_FFmpeg {
-            return true;
+            return Ok(());
         }
 
         if (self.coding_type == CodingType::coding_CRI_ADX
@@ -156,11 +575,11 @@ impl VGMStream {
             || self.coding_type == CodingType::coding_CRI_ADX_fixed)
             && (self.interleave_block_size == 0 || self.interleave_block_size > 0x12)
         {
-            println!(
+            vgm_log!(
                 "VGMSTREAM: ADX decoder with wrong frame size {:x}",
                 self.interleave_block_size
             );
-            return false;
+            return Err(VgmstreamError::BadHeader { meta: "VGMSTREAM", offset: 0, field: "ADX frame size" });
         }
 
         if (self.coding_type == CodingType::coding_MSADPCM
@@ -179,11 +598,11 @@ impl VGMStream {
             || self.coding_type == CodingType::coding_PSX_pivotal)
             && (self.frame_size == 0 || self.frame_size > 0x50)
         {
-            println!(
+            vgm_log!(
                 "VGMSTREAM: PSX-cfg decoder with wrong frame size {:x}",
                 self.frame_size
             );
-            return true;
+            return Ok(());
         }
 
         if (self.coding_type == CodingType::coding_MSADPCM
@@ -191,11 +610,11 @@ impl VGMStream {
             || self.coding_type == CodingType::coding_MSADPCM_int)
             && (self.frame_size == 0 || self.frame_size > MSADPCM_MAX_BLOCK_SIZE)
         {
-            println!(
+            vgm_log!(
                 "VGMSTREAM: MSADPCM decoder with wrong frame size {:x}",
                 self.frame_size
             );
-            return false;
+            return Err(VgmstreamError::BadHeader { meta: "VGMSTREAM", offset: 0, field: "MSADPCM frame size" });
         }
 
         /* big interleaved values for non-interleaved data may result in incorrect behavior,
@@ -254,8 +673,8 @@ impl VGMStream {
         }
 
         if start_offset < 0 {
-            println!("VGMSTREAM: buggy code (wrong start_offset)");
-            return false;
+            vgm_log!("VGMSTREAM: buggy code (wrong start_offset)");
+            return Err(VgmstreamError::BadHeader { meta: "VGMSTREAM", offset: 0, field: "start offset" });
         }
 
         if !use_streamfile_per_channel {
@@ -292,7 +711,7 @@ impl VGMStream {
             // flush_ea_mt(vgmstream);
         }
 
-        return true;
+        return Ok(());
     }
 
     // fn block_update(&mut self, block_offset: isize) {
@@ -415,6 +834,28 @@ impl VGMStream {
     //     }
     // }
 
+    /* Samples that a player should render for the given loops and fade, or the stream end after
+     * looping N times if fade_seconds is negative (set loop_target to the same count then). */
+    pub fn get_play_samples(&self, loop_times: f64, fade_seconds: f64, fade_delay_seconds: f64) -> i32 {
+        if !self.loop_flag {
+            return self.num_samples;
+        }
+
+        if fade_seconds < 0.0 {
+            /* Continue playing the file normally after looping, instead of fading.
+             * Most files cut abruply after the loop, but some do have proper endings.
+             * With loop_times = 1 this option should give the same output vs loop disabled */
+            let loop_count = loop_times as i32; /* no half loops allowed */
+            return self.loop_start_sample
+                + (self.loop_end_sample - self.loop_start_sample) * loop_count
+                + (self.num_samples - self.loop_end_sample);
+        }
+
+        return self.loop_start_sample
+            + ((self.loop_end_sample - self.loop_start_sample) as f64 * loop_times) as i32
+            + ((fade_delay_seconds + fade_seconds) * self.sample_rate as f64) as i32;
+    }
+
     pub fn get_samples(&self) -> i32 {
         if !self.config_enabled || !self.config.config_set {
             return self.num_samples;
@@ -427,24 +868,21 @@ impl VGMStream {
         let mut samples_to_do = samples_left_this_block; /* by default decodes all samples left */
 
         /* fun loopy crap, why did I think this would be any simpler? */
+        if self.loop_flag {
+            let samples_after_decode = self.current_sample as i32 + samples_left_this_block;
 
-        // TODO: handle looping
-
-        // if vgmstream.loop_flag {
-        //     int samples_after_decode = vgmstream->current_sample + samples_left_this_block;
-
-        //     /* are we going to hit the loop end during this block? */
-        //     if (samples_after_decode > vgmstream->loop_end_sample) {
-        //         /* only do samples up to loop end */
-        //         samples_to_do = vgmstream->loop_end_sample - vgmstream->current_sample;
-        //     }
+            /* are we going to hit the loop end during this block? */
+            if samples_after_decode > self.loop_end_sample {
+                /* only do samples up to loop end */
+                samples_to_do = self.loop_end_sample - self.current_sample as i32;
+            }
 
-        //     /* are we going to hit the loop start during this block? (first time only) */
-        //     if (samples_after_decode > vgmstream->loop_start_sample && !vgmstream->hit_loop) {
-        //         /* only do samples up to loop start */
-        //         samples_to_do = vgmstream->loop_start_sample - vgmstream->current_sample;
-        //     }
-        // }
+            /* are we going to hit the loop start during this block? (first time only) */
+            if samples_after_decode > self.loop_start_sample && self.hit_loop == 0 {
+                /* only do samples up to loop start */
+                samples_to_do = self.loop_start_sample - self.current_sample as i32;
+            }
+        }
 
         /* if it's a framed encoding don't do more than one frame */
         if samples_per_frame > 1 && (self.samples_into_block as i32 % samples_per_frame) + samples_to_do > samples_per_frame {
@@ -141,6 +141,13 @@ impl Default for VGMStreamChannel {
     }
 }
 
+/* named position in the stream (ex. RIFF cue points), for external tools */
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct VGMStreamMarker {
+    pub position: i32,                  /* in samples */
+    pub name: String,                   /* label, may be empty */
+}
+
 #[derive(Default)]
 pub struct VGMStream {
     /* basic config */
@@ -168,6 +175,7 @@ pub struct VGMStream {
     pub stream_index: i32,                  /* selected subsong (also 1-based) */
     pub stream_size: isize,                 /* info to properly calculate bitrate in case of subsongs */
     pub stream_name: String,          /* name of the current stream (info), if the file stores it and it's filled */
+    pub markers: Vec<VGMStreamMarker>,      /* named positions (info), sorted by position */
 
     /* mapping config (info for plugins) see channel_mappings.h */
     pub channel_layout: u32,                /* order: FL FR FC LFE BL BR FLC FRC BC SL SR etc (WAVEFORMATEX flags where FL=lowest bit set) */
@@ -220,6 +228,7 @@ pub struct VGMStream {
     pub loop_ch: Vec<VGMStreamChannel>,
 
     pub start_vgmstream: Option<Box<VGMStream>>,
+    pub init_vgmstream: Option<Box<VGMStream>>,  /* initial state before any config, so configs can be re-applied */
 
     // todo: uh
     // pub mixing_data: *c_void,
@@ -233,8 +242,7 @@ pub struct VGMStream {
     pub pstate: PlayState,              /* player state (applied over decoding) */
     pub loop_count: i32,                /* counter of complete loops (1=looped once) */
     pub loop_target: i32,               /* max loops before continuing with the stream end (loops forever if not set) */
-    // todo: is this right?
-    pub tmpbuf: Vec<u8>,                /* garbage buffer used for seeking/trimming */
+    pub tmpbuf: Vec<i16>,               /* garbage buffer used for seeking/trimming */
     pub tmpbuf_size: isize,             /* for all channels (samples = tmpbuf_size / channels) */
 }
 
@@ -248,8 +256,8 @@ use crate::coding::ffmpeg_opus::FFmpegCodecData;
 // }
 
 pub enum VGMStreamCodecData {
-    CustomVorbis(VorbisCustomCodecData),
-    CustomFFmpeg(FFmpegCodecData),
+    CustomVorbis(Box<VorbisCustomCodecData>),
+    CustomFFmpeg(Box<FFmpegCodecData>),
 }
 
 /* for files made of "continuous" segments, one per section of a song (using a complete sub-VGMSTREAM) */
@@ -269,7 +277,7 @@ pub struct SegmentedLayoutData {
 pub struct LayeredLayoutData {
     pub layer_count: i32,
     pub layers: Vec<VGMStream>,
-    pub buffer: Vec<u8>,
+    pub buffer: Vec<i16>,
     pub input_channels: i32,     /* internal buffer channels */
     pub output_channels: i32,    /* resulting channels (after mixing, if applied) */
     pub external_looping: i32,   /* don't loop using per-layer loops, but layout's own looping */